@@ -47,6 +47,8 @@ create table arbitrage.arb_diff_rate_info
     to_price      decimal(20, 4)                not null comment 'To价格',
     diff_price    decimal(20, 4) default 0.0000 not null comment '差价',
     diff_rate     decimal(20, 4) default 0.0000 not null comment '差价比率',
+    open_diff_rate  decimal(20, 4) default 0.0000 not null comment '可成交开仓比率 正向 (to买一 - from卖一) / from卖一, 反向 (from卖一 - to买一) / to买一',
+    close_diff_rate decimal(20, 4) default 0.0000 not null comment '可成交平仓比率 正向 (to卖一 - from买一) / from买一, 反向 (from买一 - to卖一) / to卖一',
     created       int            default 0      null comment '创建时间',
     updated       int            default 0      null comment '更新时间',
     bak           varchar(255)                  null comment '备注'
@@ -60,28 +62,30 @@ create table arbitrage.arb_strategy
     diff_rate_id        bigint                 not null comment 'arb_diff_rate 表ID',
     user_id             bigint                 not null comment '用户ID',
     platform            varchar(64) default '' not null comment '平台 binance、huobi、okx',
-    option_choose       varchar(64)            not null comment '方向 positive, reverse',
+    option_choose       varchar(64)            not null comment '方向 positive, reverse, funding',
     coin                varchar(64)            not null comment '币种',
     from_market         varchar(64) default '' not null comment 'From 市场',
     from_symbol         varchar(64) default '' not null comment 'From 交易对',
-    from_price_truncate tinyint     default 2  not null comment '价格小数点保留位数',
-    from_amt_truncate   tinyint     default 2  not null comment '数量小数点保留位数',
+    from_price_truncate tinyint     default 2  not null comment '价格小数点保留位数, 仅回测使用, 实盘按交易所 tickSize',
+    from_amt_truncate   tinyint     default 2  not null comment '数量小数点保留位数, 仅划转和回测使用, 实盘按交易所 stepSize',
     to_market           varchar(64) default '' not null comment 'To 市场',
     to_symbol           varchar(64) default '' not null comment 'To 交易对',
-    to_price_truncate   tinyint     default 2  not null comment '价格小数点保留位数',
-    to_amt_truncate     tinyint     default 2  not null comment '数量小数点保留位数',
+    to_price_truncate   tinyint     default 2  not null comment '价格小数点保留位数, 仅回测使用, 实盘按交易所 tickSize',
+    to_amt_truncate     tinyint     default 2  not null comment '数量小数点保留位数, 仅划转和回测使用, 实盘按交易所 stepSize',
     from_to_desc        varchar(256)           not null comment 'from->to',
     to_from_desc        varchar(256)           not null comment 'to->from',
     option_open         decimal(20, 4)         not null comment '入场阀值',
     option_close        decimal(20, 4)         not null comment '出场阀值',
     option_amt          decimal(20, 4)         not null comment '操作数量 ',
-    contract_mul        int                    not null comment '合约面值、合约乘数',
+    contract_mul        int                    not null comment '合约面值、合约乘数, 仅回测使用, 实盘按交易所 contractSize',
     margin_mul          int         default 1  not null comment '杠杆倍数',
     fok_diff            decimal(20, 4)         null comment 'FOK单子冗余处理，最新成交价格+-FOK',
     spot_fee            decimal(20, 6)         not null comment '现货手续费',
     futures_fee         decimal(20, 6)         not null comment 'U本位合约手续费',
     delivery_fee        decimal(20, 6)         not null comment '币本位合约手续费',
-    doing_status        tinyint     default 0  not null comment '策略状态 0、不执行 1、执行 2、已完成',
+    doing_status        tinyint     default 0  not null comment '策略状态 0、不执行 1、执行 2、已完成 3、需人工处理(原因见 bak)',
+    funding_income      decimal(20, 8) default 0 not null comment '资金费率策略累计资金费收入',
+    funding_time        bigint      default 0  not null comment '已累计到的资金费结算时间(毫秒)',
     created             int         default 0  null comment '创建时间',
     updated             int         default 0  null comment '更新时间',
     bak                 varchar(255)           null comment '备注'
@@ -101,6 +105,7 @@ create table arbitrage.arb_strategy_ex
     symbol              varchar(64) default '' not null comment '交易对 BTCUSDT、BTCUSDT_230630',
     option_type         varchar(64)            not null comment 'positive
     操作类型, spot_buy, transfer_spot_to_delivery, delivery_sell, delivery_buy, transfer_delivery_to_spot, spot_sell
+    季度展期, roll_delivery_buy, roll_delivery_sell
     reverse
     操作类型, spot_sell, transfer_spot_to_futures, futures_buy, futures_sell, transfer_futures_to_spot, spot_buy',
     option_status       tinyint     default 0  null comment '操作状态 0. 未完成、1. 已完成',
@@ -127,16 +132,19 @@ create table arbitrage.arb_strategy_ex_info
     symbol             varchar(64)            not null comment '交易对/币种...',
     option_type        varchar(64)            not null comment 'positive
     操作类型, spot_buy, transfer_spot_to_delivery, delivery_sell, delivery_buy, transfer_delivery_to_spot, spot_sell
+    季度展期, roll_delivery_buy, roll_delivery_sell
     reverse
     操作类型, spot_sell, transfer_spot_to_futures, futures_buy, futures_sell, transfer_futures_to_spot, spot_buy',
     price              decimal(20, 4)         not null comment '价格',
     amount             decimal(20, 8)         not null comment '数量 现货、U本位期货代表数量，币本位代表合约张数',
     executed_amt       decimal(20, 8)         null comment '真实执行数量',
     order_id           varchar(64)            null comment '委托单ID',
+    client_order_id    varchar(64) default '' not null comment '客户端订单ID arb_{策略执行表ID}_{下单次数}, 下单前写入',
     is_ok              tinyint     default 0  not null comment '0 未完成 1 已完成 2 已失效',
     created            int         default 0  null comment '创建时间',
     updated            int         default 0  null comment '更新时间',
-    bak                varchar(255)           null comment '备注'
+    bak                varchar(255)           null comment '备注',
+    simulated          tinyint     default 0  not null comment '0 实盘 1 模拟盘'
 )
     comment '策略执行记录表' charset = utf8;
 
@@ -149,11 +157,11 @@ create table arb_stable_coin
     coin           varchar(64)              not null comment '币种',
     market         varchar(64) default ''   not null comment '市场',
     symbol         varchar(64) default ''   not null comment '交易对',
-    price_truncate tinyint     default 2    not null comment '价格小数点保留位数',
-    amt_truncate   tinyint     default 2    not null comment '数量小数点保留位数',
+    price_truncate tinyint     default 2    not null comment '价格小数点保留位数, 实盘下单按交易所 tickSize',
+    amt_truncate   tinyint     default 2    not null comment '数量小数点保留位数, 实盘下单按交易所 stepSize',
     strategy       varchar(64) default '11' not null comment '策略方式  11、boll 21、百分比 31、固定阈值',
-    option_open    decimal(20, 4)           not null comment '入场阀值',
-    option_close   decimal(20, 4)           not null comment '出场阀值',
+    option_open    decimal(20, 4)           not null comment '入场阀值 31、买入价格 21、较参考价涨跌比率',
+    option_close   decimal(20, 4)           not null comment '出场阀值 31、卖出价格 21、较买入价涨跌比率',
     option_amt     decimal(20, 4)           not null comment '操作数量 ',
     fok_diff       decimal(20, 4)           null comment 'FOK单子冗余处理，最新成交价格+-FOK',
     doing_status   tinyint     default 0    not null comment '策略状态 0、不执行 1、执行 2、已完成',
@@ -0,0 +1,19 @@
+-- 已按旧版 1.init_tables.sql 建表的库执行本文件升级, 新建库直接执行 1.init_tables.sql 即可
+
+alter table arbitrage.arb_diff_rate_info
+    add column open_diff_rate  decimal(20, 4) default 0.0000 not null comment '可成交开仓比率 正向 (to买一 - from卖一) / from卖一, 反向 (from卖一 - to买一) / to买一' after diff_rate,
+    add column close_diff_rate decimal(20, 4) default 0.0000 not null comment '可成交平仓比率 正向 (to卖一 - from买一) / from买一, 反向 (from买一 - to卖一) / to卖一' after open_diff_rate;
+
+alter table arbitrage.arb_strategy
+    modify column option_choose varchar(64) not null comment '方向 positive, reverse, funding',
+    modify column doing_status tinyint default 0 not null comment '策略状态 0、不执行 1、执行 2、已完成 3、需人工处理(原因见 bak)',
+    add column funding_income decimal(20, 8) default 0 not null comment '资金费率策略累计资金费收入' after doing_status,
+    add column funding_time   bigint         default 0 not null comment '已累计到的资金费结算时间(毫秒)' after funding_income;
+
+alter table arbitrage.arb_strategy_ex_info
+    add column client_order_id varchar(64) default '' not null comment '客户端订单ID arb_{策略执行表ID}_{下单次数}, 下单前写入' after order_id,
+    add column simulated       tinyint     default 0  not null comment '0 实盘 1 模拟盘';
+
+alter table arbitrage.arb_stable_coin
+    modify column option_open  decimal(20, 4) not null comment '入场阀值 31、买入价格 21、较参考价涨跌比率',
+    modify column option_close decimal(20, 4) not null comment '出场阀值 31、卖出价格 21、较买入价涨跌比率';
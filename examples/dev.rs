@@ -4,15 +4,14 @@ use redis::AsyncCommands;
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // 初始化配置文件
-    lazy_static::initialize(&conf::C);
+    let config = conf::load_from_args()?;
 
     println!(
         "{:?} {:?}",
-        conf::C.binance_api_config.api_key,
-        conf::C.mysql.url
+        config.binance_api_config.api_key, config.mysql.url
     );
 
-    let env = db::Db::new().await?;
+    let env = db::Db::new(&config).await?;
 
     let data = sqlx::query_as::<_, model::ArbDiffRate>("select * from arb_diff_rate")
         .fetch_all(env.database())
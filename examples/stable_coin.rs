@@ -6,11 +6,11 @@ use rust_decimal::Decimal;
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // 初始化配置文件
-    lazy_static::initialize(&conf::C);
+    let config = conf::load_from_args()?;
     // 初始化Db
-    db::init_env().await?;
+    db::init_env(&config).await?;
     // 初始化日志
-    helper::log::init_log();
+    helper::log::init_log(&config.log);
 
     let last_id = sql::insert_arb_stable_coin_info(model::ArbStableCoinInfo {
         id: 0,
@@ -0,0 +1,747 @@
+use super::http::Request;
+use super::{Action, ApiError, Resource, Route};
+use crate::binance::websockets::all_stream_metrics;
+use crate::conf::Config;
+use crate::model::{arb_diff_rate, arb_stable_coin, arb_strategy, arb_strategy_ex};
+use crate::service::binance_strategy::order_in_flight;
+use crate::service::state_machine::StrategyMachine;
+use crate::service::{halt, rollover};
+use crate::{model, sql};
+use rust_decimal::Decimal;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+type Reply = Result<(u16, Value), ApiError>;
+
+/// 由服务维护的字段, 请求体中出现时拒绝
+const READ_ONLY: [&str; 7] = [
+    "id",
+    "doing_status",
+    "diff_status",
+    "funding_income",
+    "funding_time",
+    "created",
+    "updated",
+];
+
+/// 已生成 arb_strategy_ex 后不可修改的策略字段
+const STRATEGY_FROZEN: [&str; 10] = [
+    "diff_rate_id",
+    "user_id",
+    "option_choose",
+    "coin",
+    "from_market",
+    "from_symbol",
+    "to_market",
+    "to_symbol",
+    "option_amt",
+    "margin_mul",
+];
+
+/// 有策略引用后不可修改的差价记录字段
+const DIFF_RATE_FROZEN: [&str; 5] = [
+    "option_choose",
+    "from_market",
+    "from_symbol",
+    "to_market",
+    "to_symbol",
+];
+
+/// 有成交记录后不可修改的稳定币策略字段
+const STABLE_COIN_FROZEN: [&str; 4] = ["user_id", "coin", "market", "symbol"];
+
+/// 成交记录默认条数
+const DEFAULT_FILLS_LIMIT: u32 = 100;
+
+pub async fn handle(config: &Config, route: Route, req: &Request) -> Reply {
+    match route {
+        Route::List(Resource::DiffRate) => ok(&sql::get_arb_diff_rate_list().await?),
+        Route::List(Resource::Strategy) => ok(&sql::get_arb_strategy_list().await?),
+        Route::List(Resource::StableCoin) => ok(&sql::get_arb_stable_coin_list().await?),
+        Route::Get(Resource::DiffRate, id) => ok(&sql::get_arb_diff_rate_by_id(id).await?),
+        Route::Get(Resource::Strategy, id) => ok(&sql::get_arb_strategy_by_id(id).await?),
+        Route::Get(Resource::StableCoin, id) => ok(&sql::get_arb_stable_coin_by_id(id).await?),
+        Route::Create(Resource::DiffRate) => create_diff_rate(&req.json().map_err(bad_json)?).await,
+        Route::Create(Resource::Strategy) => {
+            create_strategy(config, &req.json().map_err(bad_json)?).await
+        }
+        Route::Create(Resource::StableCoin) => {
+            create_stable_coin(config, &req.json().map_err(bad_json)?).await
+        }
+        Route::Update(Resource::DiffRate, id) => {
+            update_diff_rate(id, &req.json().map_err(bad_json)?).await
+        }
+        Route::Update(Resource::Strategy, id) => {
+            update_strategy(config, id, &req.json().map_err(bad_json)?).await
+        }
+        Route::Update(Resource::StableCoin, id) => {
+            update_stable_coin(config, id, &req.json().map_err(bad_json)?).await
+        }
+        Route::Delete(Resource::DiffRate, id) => delete_diff_rate(id).await,
+        Route::Delete(Resource::Strategy, id) => delete_strategy(id).await,
+        Route::Delete(Resource::StableCoin, id) => delete_stable_coin(id).await,
+        Route::Transition(Resource::DiffRate, id, action) => transition_diff_rate(id, action).await,
+        Route::Transition(Resource::Strategy, id, action) => {
+            transition_strategy(config, id, action).await
+        }
+        Route::Transition(Resource::StableCoin, id, action) => {
+            transition_stable_coin(config, id, action).await
+        }
+        Route::DiffRateInfo(id) => {
+            sql::get_arb_diff_rate_by_id(id).await?;
+            ok(&sql::get_arb_diff_rate_info_by_diff_rate_id(id).await?)
+        }
+        Route::StrategyProgress(id) => strategy_progress(id).await,
+        Route::StableCoinFills(id) => {
+            let limit = match req.query.get("limit") {
+                Some(limit) => limit
+                    .parse()
+                    .map_err(|_| ApiError::bad_request("limit must be a positive integer"))?,
+                None => DEFAULT_FILLS_LIMIT,
+            };
+            sql::get_arb_stable_coin_by_id(id).await?;
+            ok(&sql::get_arb_stable_coin_info_list_by_stable_coin_id(id, limit).await?)
+        }
+        Route::StreamMetrics => ok(&all_stream_metrics()),
+        Route::Halt => ok(&halt::halt_status(&config.shutdown.halt_file).await?),
+        Route::SetHalt => {
+            let body = req.json().map_err(bad_json)?;
+            let reason = body
+                .get("reason")
+                .and_then(Value::as_str)
+                .unwrap_or("admin api");
+            halt::set_halt(reason).await?;
+            ok(&halt::halt_status(&config.shutdown.halt_file).await?)
+        }
+        Route::ClearHalt => {
+            halt::clear_halt().await?;
+            ok(&halt::halt_status(&config.shutdown.halt_file).await?)
+        }
+    }
+}
+
+fn ok<T: Serialize>(value: &T) -> Reply {
+    Ok((200, to_value(value)?))
+}
+
+fn created(id: u64) -> Reply {
+    Ok((201, json!({ "id": id })))
+}
+
+fn to_value<T: Serialize>(value: &T) -> Result<Value, ApiError> {
+    serde_json::to_value(value).map_err(|e| ApiError::from(anyhow::Error::from(e)))
+}
+
+fn bad_json(e: anyhow::Error) -> ApiError {
+    ApiError::bad_request(format!("invalid json body: {}", e))
+}
+
+/// 把请求体中的字段合并到 base 上, 只读字段和未知字段返回 400
+pub fn merge<T: Serialize + DeserializeOwned>(base: &T, patch: &Value) -> Result<T, ApiError> {
+    let Value::Object(patch) = patch else {
+        return Err(ApiError::bad_request("body must be a json object"));
+    };
+    let mut value = to_value(base)?;
+    let Value::Object(fields) = &mut value else {
+        return Err(ApiError::bad_request("body must be a json object"));
+    };
+    for (key, v) in patch {
+        if READ_ONLY.contains(&key.as_str()) {
+            return Err(ApiError::bad_request(format!("{} is read only", key)));
+        }
+        if !fields.contains_key(key) {
+            return Err(ApiError::bad_request(format!("unknown field {}", key)));
+        }
+        fields.insert(key.clone(), v.clone());
+    }
+    serde_json::from_value(value).map_err(|e| ApiError::bad_request(e.to_string()))
+}
+
+/// frozen 中的字段在 before/after 间有变化时返回 409
+pub fn check_frozen<T: Serialize>(before: &T, after: &T, frozen: &[&str]) -> Result<(), ApiError> {
+    let before = to_value(before)?;
+    let after = to_value(after)?;
+    match frozen.iter().find(|f| before.get(**f) != after.get(**f)) {
+        Some(field) => Err(ApiError::conflict(format!(
+            "{} can not be changed once trading started",
+            field
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn check_market(field: &str, market: &str) -> Result<(), ApiError> {
+    match market {
+        "spot" | "futures" | "delivery" => Ok(()),
+        _ => Err(ApiError::bad_request(format!(
+            "{} must be spot, futures or delivery, got {:?}",
+            field, market
+        ))),
+    }
+}
+
+fn check_not_empty(field: &str, value: &str) -> Result<(), ApiError> {
+    if value.trim().is_empty() {
+        return Err(ApiError::bad_request(format!("{} is required", field)));
+    }
+    Ok(())
+}
+
+fn check_amount(field: &str, value: Decimal) -> Result<(), ApiError> {
+    if value <= Decimal::ZERO {
+        return Err(ApiError::bad_request(format!("{} must be > 0", field)));
+    }
+    Ok(())
+}
+
+fn check_account(config: &Config, user_id: i64) -> Result<(), ApiError> {
+    if config.credentials(user_id).is_none() {
+        return Err(ApiError::bad_request(format!(
+            "no account configured for user_id {}",
+            user_id
+        )));
+    }
+    Ok(())
+}
+
+pub fn validate_diff_rate(diff_rate: &model::ArbDiffRate) -> Result<(), ApiError> {
+    check_not_empty("coin", &diff_rate.coin)?;
+    check_market("from_market", &diff_rate.from_market)?;
+    check_market("to_market", &diff_rate.to_market)?;
+    check_not_empty("from_symbol", &diff_rate.from_symbol)?;
+    check_not_empty("to_symbol", &diff_rate.to_symbol)?;
+    check_not_empty("option_choose", &diff_rate.option_choose)
+}
+
+/// 校验策略配置, 账户和差价记录需查询后单独校验
+pub fn validate_strategy(strategy: &model::ArbStrategy) -> Result<(), ApiError> {
+    check_not_empty("coin", &strategy.coin)?;
+    check_market("from_market", &strategy.from_market)?;
+    check_market("to_market", &strategy.to_market)?;
+    check_not_empty("from_symbol", &strategy.from_symbol)?;
+    check_not_empty("to_symbol", &strategy.to_symbol)?;
+    check_amount("option_amt", strategy.option_amt)?;
+    if StrategyMachine::of(strategy).is_none() {
+        return Err(ApiError::bad_request(format!(
+            "unsupported option_choose {} from {} to {}",
+            strategy.option_choose, strategy.from_market, strategy.to_market
+        )));
+    }
+    Ok(())
+}
+
+pub fn validate_stable_coin(stable_coin: &model::ArbStableCoin) -> Result<(), ApiError> {
+    check_not_empty("coin", &stable_coin.coin)?;
+    check_market("market", &stable_coin.market)?;
+    check_not_empty("symbol", &stable_coin.symbol)?;
+    check_amount("option_amt", stable_coin.option_amt)?;
+    match stable_coin.strategy.as_str() {
+        // 百分比策略: 较参考价下跌 option_open 买入, 较买入价上涨 option_close 卖出
+        "21" if stable_coin.option_open >= Decimal::ZERO => Err(ApiError::bad_request(
+            "option_open must be < 0 for strategy 21, e.g. -0.005 buys 0.5% below the reference",
+        )),
+        "21" if stable_coin.option_close <= Decimal::ZERO => Err(ApiError::bad_request(
+            "option_close must be > 0 for strategy 21, e.g. 0.005 sells 0.5% above the buy price",
+        )),
+        "11" | "21" | "31" => Ok(()),
+        s => Err(ApiError::bad_request(format!(
+            "strategy must be 11, 21 or 31, got {:?}",
+            s
+        ))),
+    }
+}
+
+/// 是否持有未平的仓位: 开仓腿部分完成, 或有腿已部分成交
+pub fn holds_position(ex_list: &[model::ArbStrategyEx]) -> bool {
+    let legs: Vec<&model::ArbStrategyEx> =
+        ex_list.iter().filter(|ex| !rollover::is_roll(ex)).collect();
+    let done = legs
+        .iter()
+        .filter(|ex| ex.option_status == arb_strategy_ex::OPTION_STATUS_DONE)
+        .count();
+    let partially_filled = legs.iter().any(|ex| {
+        ex.option_status == arb_strategy_ex::OPTION_STATUS_UN_DONE
+            && ex.option_executed_amt > Decimal::ZERO
+    });
+    (done > 0 && done < legs.len()) || partially_filled || order_in_flight(ex_list)
+}
+
+/// 策略状态迁移, 返回新的 doing_status
+pub fn strategy_transition(
+    doing_status: i8,
+    action: Action,
+    ex_list: &[model::ArbStrategyEx],
+) -> Result<i8, ApiError> {
+    use arb_strategy::*;
+    if doing_status == DOING_STATUS_DONE {
+        return Err(ApiError::conflict("strategy is already stopped"));
+    }
+    match action {
+        Action::Start => match doing_status {
+            DOING_STATUS_UN_RUN | DOING_STATUS_MANUAL => Ok(DOING_STATUS_RUN),
+            _ => Err(ApiError::conflict("strategy is already running")),
+        },
+        Action::Pause => match doing_status {
+            DOING_STATUS_RUN | DOING_STATUS_MANUAL if order_in_flight(ex_list) => Err(
+                ApiError::conflict("strategy has an order in flight, try again later"),
+            ),
+            DOING_STATUS_RUN | DOING_STATUS_MANUAL => Ok(DOING_STATUS_UN_RUN),
+            _ => Err(ApiError::conflict("strategy is not running")),
+        },
+        Action::Stop if holds_position(ex_list) => Err(ApiError::conflict(
+            "strategy holds a position, close it before stopping",
+        )),
+        Action::Stop => Ok(DOING_STATUS_DONE),
+    }
+}
+
+/// 稳定币策略状态迁移, 返回新的 doing_status
+pub fn stable_coin_transition(doing_status: i8, action: Action) -> Result<i8, ApiError> {
+    use arb_stable_coin::*;
+    match (doing_status, action) {
+        (DOING_STATUS_DONE, _) => Err(ApiError::conflict("stable coin is already stopped")),
+        (DOING_STATUS_UN_RUN, Action::Start) => Ok(DOING_STATUS_RUN),
+        (DOING_STATUS_RUN, Action::Pause) => Ok(DOING_STATUS_UN_RUN),
+        (_, Action::Stop) => Ok(DOING_STATUS_DONE),
+        (_, Action::Start) => Err(ApiError::conflict("stable coin is already running")),
+        (_, Action::Pause) => Err(ApiError::conflict("stable coin is not running")),
+    }
+}
+
+/// 差价记录状态迁移, 有运行中的策略引用时不可暂停
+pub fn diff_rate_transition(
+    diff_status: i8,
+    action: Action,
+    strategies: &[model::ArbStrategy],
+) -> Result<i8, ApiError> {
+    use arb_diff_rate::*;
+    match (diff_status, action) {
+        (DIFF_STATUS_UN_RUN, Action::Start) => Ok(DIFF_STATUS_RUN),
+        (DIFF_STATUS_RUN, Action::Pause)
+            if strategies
+                .iter()
+                .any(|s| s.doing_status == arb_strategy::DOING_STATUS_RUN) =>
+        {
+            Err(ApiError::conflict(
+                "diff rate is used by running strategies, pause them first",
+            ))
+        }
+        (DIFF_STATUS_RUN, Action::Pause) => Ok(DIFF_STATUS_UN_RUN),
+        (_, Action::Start) => Err(ApiError::conflict("diff rate is already running")),
+        (_, Action::Pause) => Err(ApiError::conflict("diff rate is not running")),
+        (_, Action::Stop) => Err(ApiError::bad_request("diff rate can not be stopped")),
+    }
+}
+
+async fn create_diff_rate(body: &Value) -> Reply {
+    let diff_rate: model::ArbDiffRate = merge(&model::ArbDiffRate::default(), body)?;
+    validate_diff_rate(&diff_rate)?;
+    created(sql::insert_arb_diff_rate(diff_rate).await?)
+}
+
+async fn update_diff_rate(id: i64, body: &Value) -> Reply {
+    let before = sql::get_arb_diff_rate_by_id(id).await?;
+    let after = merge(&before, body)?;
+    validate_diff_rate(&after)?;
+    if before.diff_status == arb_diff_rate::DIFF_STATUS_RUN {
+        return Err(ApiError::conflict("pause the diff rate before updating it"));
+    }
+    if !sql::get_arb_strategy_list_by_diff_rate_id(id)
+        .await?
+        .is_empty()
+    {
+        check_frozen(&before, &after, &DIFF_RATE_FROZEN)?;
+    }
+    sql::update_arb_diff_rate(after).await?;
+    ok(&sql::get_arb_diff_rate_by_id(id).await?)
+}
+
+async fn delete_diff_rate(id: i64) -> Reply {
+    let diff_rate = sql::get_arb_diff_rate_by_id(id).await?;
+    if diff_rate.diff_status != arb_diff_rate::DIFF_STATUS_UN_RUN {
+        return Err(ApiError::conflict("pause the diff rate before deleting it"));
+    }
+    if !sql::get_arb_strategy_list_by_diff_rate_id(id)
+        .await?
+        .is_empty()
+    {
+        return Err(ApiError::conflict("diff rate is used by strategies"));
+    }
+    sql::delete_arb_diff_rate(id).await?;
+    ok(&json!({ "id": id }))
+}
+
+async fn transition_diff_rate(id: i64, action: Action) -> Reply {
+    let diff_rate = sql::get_arb_diff_rate_by_id(id).await?;
+    let strategies = sql::get_arb_strategy_list_by_diff_rate_id(id).await?;
+    let status = diff_rate_transition(diff_rate.diff_status, action, &strategies)?;
+    sql::update_arb_diff_rate_status(id, status).await?;
+    ok(&sql::get_arb_diff_rate_by_id(id).await?)
+}
+
+/// 策略引用的差价记录需存在, 且与策略的方向和交易对一致
+async fn check_strategy_diff_rate(strategy: &model::ArbStrategy) -> Result<(), ApiError> {
+    let diff_rate = sql::get_arb_diff_rate_by_id(strategy.diff_rate_id)
+        .await
+        .map_err(|_| {
+            ApiError::bad_request(format!("diff_rate_id {} not found", strategy.diff_rate_id))
+        })?;
+    let same = diff_rate.option_choose == strategy.option_choose
+        && diff_rate.from_market == strategy.from_market
+        && diff_rate.from_symbol == strategy.from_symbol
+        && diff_rate.to_market == strategy.to_market
+        && diff_rate.to_symbol == strategy.to_symbol;
+    if !same {
+        return Err(ApiError::bad_request(format!(
+            "strategy does not match diff_rate_id {}",
+            strategy.diff_rate_id
+        )));
+    }
+    Ok(())
+}
+
+async fn create_strategy(config: &Config, body: &Value) -> Reply {
+    let strategy: model::ArbStrategy = merge(&model::ArbStrategy::default(), body)?;
+    validate_strategy(&strategy)?;
+    check_account(config, strategy.user_id)?;
+    check_strategy_diff_rate(&strategy).await?;
+    created(sql::insert_arb_strategy(strategy).await?)
+}
+
+async fn update_strategy(config: &Config, id: i64, body: &Value) -> Reply {
+    let before = sql::get_arb_strategy_by_id(id).await?;
+    let after = merge(&before, body)?;
+    validate_strategy(&after)?;
+    check_account(config, after.user_id)?;
+    if before.doing_status == arb_strategy::DOING_STATUS_RUN {
+        return Err(ApiError::conflict("pause the strategy before updating it"));
+    }
+    if !sql::get_arb_strategy_ex_list_by_strategy_id(id)
+        .await?
+        .is_empty()
+    {
+        check_frozen(&before, &after, &STRATEGY_FROZEN)?;
+    }
+    check_strategy_diff_rate(&after).await?;
+    sql::update_arb_strategy(after).await?;
+    ok(&sql::get_arb_strategy_by_id(id).await?)
+}
+
+async fn delete_strategy(id: i64) -> Reply {
+    let strategy = sql::get_arb_strategy_by_id(id).await?;
+    if strategy.doing_status == arb_strategy::DOING_STATUS_RUN {
+        return Err(ApiError::conflict("pause the strategy before deleting it"));
+    }
+    if !sql::get_arb_strategy_ex_list_by_strategy_id(id)
+        .await?
+        .is_empty()
+    {
+        return Err(ApiError::conflict(
+            "strategy has traded, stop it instead of deleting it",
+        ));
+    }
+    sql::delete_arb_strategy(id).await?;
+    ok(&json!({ "id": id }))
+}
+
+async fn transition_strategy(config: &Config, id: i64, action: Action) -> Reply {
+    let strategy = sql::get_arb_strategy_by_id(id).await?;
+    let ex_list = sql::get_arb_strategy_ex_list_by_strategy_id(id).await?;
+    let status = strategy_transition(strategy.doing_status, action, &ex_list)?;
+    if status == arb_strategy::DOING_STATUS_RUN {
+        check_account(config, strategy.user_id)?;
+        let diff_rate = sql::get_arb_diff_rate_by_id(strategy.diff_rate_id).await?;
+        if diff_rate.diff_status != arb_diff_rate::DIFF_STATUS_RUN {
+            return Err(ApiError::conflict(format!(
+                "diff_rate_id {} is not running",
+                strategy.diff_rate_id
+            )));
+        }
+    }
+    sql::update_strategy_by_id(id, status).await?;
+    ok(&sql::get_arb_strategy_by_id(id).await?)
+}
+
+async fn strategy_progress(id: i64) -> Reply {
+    let strategy = sql::get_arb_strategy_by_id(id).await?;
+    let mut legs = Vec::new();
+    for ex in sql::get_arb_strategy_ex_list_by_strategy_id(id).await? {
+        let infos = sql::get_arb_strategy_ex_info_list_by_ex_id(ex.id).await?;
+        legs.push(json!({ "ex": to_value(&ex)?, "infos": to_value(&infos)? }));
+    }
+    ok(&json!({
+        "strategy_id": strategy.id,
+        "doing_status": strategy.doing_status,
+        "legs": legs,
+    }))
+}
+
+async fn create_stable_coin(config: &Config, body: &Value) -> Reply {
+    let stable_coin: model::ArbStableCoin = merge(&model::ArbStableCoin::default(), body)?;
+    validate_stable_coin(&stable_coin)?;
+    check_account(config, stable_coin.user_id)?;
+    created(sql::insert_arb_stable_coin(stable_coin).await?)
+}
+
+async fn has_fills(id: i64) -> anyhow::Result<bool> {
+    Ok(!sql::get_arb_stable_coin_info_list_by_stable_coin_id(id, 1)
+        .await?
+        .is_empty())
+}
+
+async fn update_stable_coin(config: &Config, id: i64, body: &Value) -> Reply {
+    let before = sql::get_arb_stable_coin_by_id(id).await?;
+    let after = merge(&before, body)?;
+    validate_stable_coin(&after)?;
+    check_account(config, after.user_id)?;
+    if before.doing_status == arb_stable_coin::DOING_STATUS_RUN {
+        return Err(ApiError::conflict(
+            "pause the stable coin before updating it",
+        ));
+    }
+    if has_fills(id).await? {
+        check_frozen(&before, &after, &STABLE_COIN_FROZEN)?;
+    }
+    sql::update_arb_stable_coin(after).await?;
+    ok(&sql::get_arb_stable_coin_by_id(id).await?)
+}
+
+async fn delete_stable_coin(id: i64) -> Reply {
+    let stable_coin = sql::get_arb_stable_coin_by_id(id).await?;
+    if stable_coin.doing_status == arb_stable_coin::DOING_STATUS_RUN {
+        return Err(ApiError::conflict(
+            "pause the stable coin before deleting it",
+        ));
+    }
+    if has_fills(id).await? {
+        return Err(ApiError::conflict(
+            "stable coin has traded, stop it instead of deleting it",
+        ));
+    }
+    sql::delete_arb_stable_coin(id).await?;
+    ok(&json!({ "id": id }))
+}
+
+async fn transition_stable_coin(config: &Config, id: i64, action: Action) -> Reply {
+    let stable_coin = sql::get_arb_stable_coin_by_id(id).await?;
+    let status = stable_coin_transition(stable_coin.doing_status, action)?;
+    if status == arb_stable_coin::DOING_STATUS_RUN {
+        check_account(config, stable_coin.user_id)?;
+    }
+    sql::update_arb_stable_coin_status(id, status).await?;
+    ok(&sql::get_arb_stable_coin_by_id(id).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    fn strategy() -> model::ArbStrategy {
+        model::ArbStrategy {
+            diff_rate_id: 1,
+            option_choose: "positive".to_string(),
+            coin: "BTC".to_string(),
+            from_market: "spot".to_string(),
+            from_symbol: "BTCUSDT".to_string(),
+            to_market: "delivery".to_string(),
+            to_symbol: "BTCUSD_240628".to_string(),
+            option_amt: dec("0.1"),
+            ..Default::default()
+        }
+    }
+
+    fn ex(
+        option_type: &str,
+        option_status: i8,
+        executed: Decimal,
+        order_id: &str,
+    ) -> model::ArbStrategyEx {
+        model::ArbStrategyEx {
+            option_type: option_type.to_string(),
+            option_status,
+            option_executed_amt: executed,
+            current_order_id: order_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let base = strategy();
+        let merged = merge(&base, &json!({ "option_open": "0.05", "bak": "note" })).unwrap();
+        assert_eq!(merged.option_open, dec("0.05"));
+        assert_eq!(merged.bak.as_deref(), Some("note"));
+        assert_eq!(merged.coin, "BTC");
+
+        let err = merge(&base, &json!({ "doing_status": 1 })).unwrap_err();
+        assert_eq!(err.status, 400);
+        assert!(err.msg.contains("read only"));
+        assert_eq!(
+            merge(&base, &json!({ "leverage": 3 })).unwrap_err().status,
+            400
+        );
+        assert_eq!(merge(&base, &json!([1])).unwrap_err().status, 400);
+        assert_eq!(
+            merge(&base, &json!({ "margin_mul": "x" }))
+                .unwrap_err()
+                .status,
+            400
+        );
+    }
+
+    #[test]
+    fn test_check_frozen() {
+        let before = strategy();
+        let mut after = before.clone();
+        after.option_open = dec("0.05");
+        check_frozen(&before, &after, &STRATEGY_FROZEN).unwrap();
+        after.to_symbol = "BTCUSD_240927".to_string();
+        let err = check_frozen(&before, &after, &STRATEGY_FROZEN).unwrap_err();
+        assert_eq!(err.status, 409);
+        assert!(err.msg.contains("to_symbol"));
+    }
+
+    #[test]
+    fn test_validate() {
+        validate_strategy(&strategy()).unwrap();
+        let mut s = strategy();
+        s.to_market = "margin".to_string();
+        assert!(validate_strategy(&s).unwrap_err().msg.contains("to_market"));
+        let mut s = strategy();
+        s.option_amt = Decimal::ZERO;
+        assert!(validate_strategy(&s)
+            .unwrap_err()
+            .msg
+            .contains("option_amt"));
+        let mut s = strategy();
+        s.option_choose = "reverse".to_string();
+        assert!(validate_strategy(&s)
+            .unwrap_err()
+            .msg
+            .contains("unsupported"));
+
+        let mut coin = model::ArbStableCoin {
+            coin: "USDC".to_string(),
+            market: "spot".to_string(),
+            symbol: "USDCUSDT".to_string(),
+            strategy: "31".to_string(),
+            option_amt: dec("100"),
+            ..Default::default()
+        };
+        validate_stable_coin(&coin).unwrap();
+        coin.strategy = "41".to_string();
+        assert!(validate_stable_coin(&coin).is_err());
+        // 百分比策略入场比率为正时会立即买入
+        coin.strategy = "21".to_string();
+        coin.option_open = dec("0.005");
+        coin.option_close = dec("0.005");
+        assert!(validate_stable_coin(&coin)
+            .unwrap_err()
+            .msg
+            .contains("option_open"));
+        coin.option_open = dec("-0.005");
+        coin.option_close = Decimal::ZERO;
+        assert!(validate_stable_coin(&coin)
+            .unwrap_err()
+            .msg
+            .contains("option_close"));
+        coin.option_close = dec("0.005");
+        validate_stable_coin(&coin).unwrap();
+    }
+
+    #[test]
+    fn test_strategy_transition() {
+        use arb_strategy::*;
+        use arb_strategy_ex::{OPTION_STATUS_DONE, OPTION_STATUS_UN_DONE};
+        let none: Vec<model::ArbStrategyEx> = vec![];
+        assert_eq!(
+            strategy_transition(DOING_STATUS_UN_RUN, Action::Start, &none),
+            Ok(DOING_STATUS_RUN)
+        );
+        assert_eq!(
+            strategy_transition(DOING_STATUS_MANUAL, Action::Start, &none),
+            Ok(DOING_STATUS_RUN)
+        );
+        assert_eq!(
+            strategy_transition(DOING_STATUS_RUN, Action::Start, &none)
+                .unwrap_err()
+                .status,
+            409
+        );
+        assert_eq!(
+            strategy_transition(DOING_STATUS_RUN, Action::Pause, &none),
+            Ok(DOING_STATUS_UN_RUN)
+        );
+        assert_eq!(
+            strategy_transition(DOING_STATUS_DONE, Action::Start, &none)
+                .unwrap_err()
+                .status,
+            409
+        );
+
+        // 下单中不可暂停或停止
+        let in_flight = vec![ex("spot_buy", OPTION_STATUS_UN_DONE, Decimal::ZERO, "123")];
+        assert!(strategy_transition(DOING_STATUS_RUN, Action::Pause, &in_flight).is_err());
+        assert!(strategy_transition(DOING_STATUS_RUN, Action::Stop, &in_flight).is_err());
+
+        // 开仓完成一半持有仓位, 不可停止
+        let half = vec![
+            ex("spot_buy", OPTION_STATUS_DONE, dec("1"), ""),
+            ex("delivery_sell", OPTION_STATUS_UN_DONE, Decimal::ZERO, ""),
+        ];
+        assert!(strategy_transition(DOING_STATUS_RUN, Action::Stop, &half).is_err());
+        let partial = vec![ex("spot_buy", OPTION_STATUS_UN_DONE, dec("0.5"), "")];
+        assert!(strategy_transition(DOING_STATUS_RUN, Action::Stop, &partial).is_err());
+
+        // 全部完成, 展期记录不参与判断
+        let closed = vec![
+            ex("spot_buy", OPTION_STATUS_DONE, dec("1"), ""),
+            ex("delivery_sell", OPTION_STATUS_DONE, dec("1"), ""),
+            ex(rollover::ROLL_BUY, OPTION_STATUS_UN_DONE, Decimal::ZERO, ""),
+        ];
+        assert_eq!(
+            strategy_transition(DOING_STATUS_RUN, Action::Stop, &closed),
+            Ok(DOING_STATUS_DONE)
+        );
+    }
+
+    #[test]
+    fn test_stable_coin_and_diff_rate_transition() {
+        use arb_stable_coin::*;
+        assert_eq!(
+            stable_coin_transition(DOING_STATUS_UN_RUN, Action::Start),
+            Ok(DOING_STATUS_RUN)
+        );
+        assert_eq!(
+            stable_coin_transition(DOING_STATUS_RUN, Action::Stop),
+            Ok(DOING_STATUS_DONE)
+        );
+        assert!(stable_coin_transition(DOING_STATUS_UN_RUN, Action::Pause).is_err());
+        assert!(stable_coin_transition(DOING_STATUS_DONE, Action::Start).is_err());
+
+        use arb_diff_rate::*;
+        let mut running = strategy();
+        running.doing_status = arb_strategy::DOING_STATUS_RUN;
+        assert_eq!(
+            diff_rate_transition(DIFF_STATUS_UN_RUN, Action::Start, &[]),
+            Ok(DIFF_STATUS_RUN)
+        );
+        assert_eq!(
+            diff_rate_transition(DIFF_STATUS_RUN, Action::Pause, &[running.clone()])
+                .unwrap_err()
+                .status,
+            409
+        );
+        running.doing_status = arb_strategy::DOING_STATUS_UN_RUN;
+        assert_eq!(
+            diff_rate_transition(DIFF_STATUS_RUN, Action::Pause, &[running]),
+            Ok(DIFF_STATUS_UN_RUN)
+        );
+    }
+}
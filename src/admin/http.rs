@@ -0,0 +1,158 @@
+use anyhow::anyhow;
+use serde_json::Value;
+use std::collections::HashMap;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// 请求头最大长度
+const MAX_HEADER_LEN: usize = 16 * 1024;
+/// 请求体最大长度
+const MAX_BODY_LEN: usize = 1024 * 1024;
+
+/// 简单的 HTTP/1.1 请求, 每个连接只处理一个请求
+#[derive(Debug, Default)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    /// header 名称统一小写
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(|v| v.as_str())
+    }
+
+    /// 请求体解析为 json, 空请求体视为 {}
+    pub fn json(&self) -> anyhow::Result<Value> {
+        if self.body.iter().all(u8::is_ascii_whitespace) {
+            return Ok(Value::Object(Default::default()));
+        }
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+}
+
+/// 读取一个请求, 连接在请求头结束前关闭时返回 None
+pub async fn read_request<R: AsyncRead + Unpin>(stream: &mut R) -> anyhow::Result<Option<Request>> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        if buf.len() > MAX_HEADER_LEN {
+            return Err(anyhow!("request header too large"));
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default().to_uppercase();
+    let target = request_line.next().unwrap_or_default().to_string();
+    let headers: HashMap<String, String> = lines
+        .filter_map(|l| l.split_once(':'))
+        .map(|(k, v)| (k.trim().to_lowercase(), v.trim().to_string()))
+        .collect();
+
+    let content_length: usize = headers
+        .get("content-length")
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(0);
+    if content_length > MAX_BODY_LEN {
+        return Err(anyhow!("request body too large"));
+    }
+    let mut body = buf.split_off(header_end);
+    while body.len() < content_length {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+    body.truncate(content_length);
+
+    let (path, query) = target.split_once('?').unwrap_or((target.as_str(), ""));
+    let query = url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect();
+    Ok(Some(Request {
+        method,
+        path: path.to_string(),
+        query,
+        headers,
+        body,
+    }))
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        _ => "Internal Server Error",
+    }
+}
+
+/// 写出 json 响应并关闭连接
+pub async fn write_response<W: AsyncWrite + Unpin>(
+    stream: &mut W,
+    status: u16,
+    body: &Value,
+) -> std::io::Result<()> {
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason(status),
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_read_request() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        let body = r#"{"coin":"BTC"}"#;
+        let raw = format!(
+            "PUT /strategies/3?limit=10&x=a%20b HTTP/1.1\r\nAuthorization: Bearer t\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let writer = tokio::spawn(async move {
+            client.write_all(raw.as_bytes()).await.unwrap();
+            client
+        });
+        let req = read_request(&mut server).await.unwrap().unwrap();
+        writer.await.unwrap();
+        assert_eq!(req.method, "PUT");
+        assert_eq!(req.path, "/strategies/3");
+        assert_eq!(req.query.get("limit").map(String::as_str), Some("10"));
+        assert_eq!(req.query.get("x").map(String::as_str), Some("a b"));
+        assert_eq!(req.header("authorization"), Some("Bearer t"));
+        assert_eq!(req.json().unwrap()["coin"], "BTC");
+    }
+
+    #[tokio::test]
+    async fn test_read_request_closed() {
+        let (client, mut server) = tokio::io::duplex(64);
+        drop(client);
+        assert!(read_request(&mut server).await.unwrap().is_none());
+    }
+}
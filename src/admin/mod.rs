@@ -0,0 +1,267 @@
+//! 管理接口
+//!
+//! 在 admin.listen 上提供 HTTP JSON 接口, 管理 arb_diff_rate / arb_strategy / arb_stable_coin,
+//! 启动、暂停、停止策略并查看执行进度; 所有请求需带 Authorization: Bearer <admin.token>
+
+pub mod handler;
+pub mod http;
+
+use crate::conf::Config;
+use http::Request;
+use log::{info, warn};
+use ring::constant_time::verify_slices_are_equal;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::Duration;
+
+/// 单个请求从连接到响应的最长时间, 超时断开, 避免慢连接一直占用
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource {
+    DiffRate,
+    Strategy,
+    StableCoin,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Start,
+    Pause,
+    Stop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    List(Resource),
+    Create(Resource),
+    Get(Resource, i64),
+    Update(Resource, i64),
+    Delete(Resource, i64),
+    Transition(Resource, i64, Action),
+    /// 差价记录的最新差价
+    DiffRateInfo(i64),
+    /// 策略的 arb_strategy_ex 及每条的下单记录
+    StrategyProgress(i64),
+    /// 稳定币策略的成交记录
+    StableCoinFills(i64),
+    /// websocket 连接统计
+    StreamMetrics,
+    /// 停止交易开关状态
+    Halt,
+    /// 停止执行新的腿, 所有进程生效
+    SetHalt,
+    /// 恢复交易
+    ClearHalt,
+}
+
+/// 解析请求路径, 不存在的路径返回 None
+pub fn route(method: &str, path: &str) -> Option<Route> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match (segments.as_slice(), method) {
+        (["metrics", "streams"], "GET") => return Some(Route::StreamMetrics),
+        (["halt"], "GET") => return Some(Route::Halt),
+        (["halt"], "POST") => return Some(Route::SetHalt),
+        (["halt"], "DELETE") => return Some(Route::ClearHalt),
+        _ => {}
+    }
+    let (resource, rest) = segments.split_first()?;
+    let resource = match *resource {
+        "diff_rates" => Resource::DiffRate,
+        "strategies" => Resource::Strategy,
+        "stable_coins" => Resource::StableCoin,
+        _ => return None,
+    };
+    let Some((id, rest)) = rest.split_first() else {
+        return match method {
+            "GET" => Some(Route::List(resource)),
+            "POST" => Some(Route::Create(resource)),
+            _ => None,
+        };
+    };
+    let id: i64 = id.parse().ok()?;
+    match (rest, method) {
+        ([], "GET") => Some(Route::Get(resource, id)),
+        ([], "PUT") => Some(Route::Update(resource, id)),
+        ([], "DELETE") => Some(Route::Delete(resource, id)),
+        (["start"], "POST") => Some(Route::Transition(resource, id, Action::Start)),
+        (["pause"], "POST") => Some(Route::Transition(resource, id, Action::Pause)),
+        (["stop"], "POST") if resource != Resource::DiffRate => {
+            Some(Route::Transition(resource, id, Action::Stop))
+        }
+        (["info"], "GET") if resource == Resource::DiffRate => Some(Route::DiffRateInfo(id)),
+        (["progress"], "GET") if resource == Resource::Strategy => {
+            Some(Route::StrategyProgress(id))
+        }
+        (["fills"], "GET") if resource == Resource::StableCoin => Some(Route::StableCoinFills(id)),
+        _ => None,
+    }
+}
+
+/// 接口错误, 响应为 {"error": msg}
+#[derive(Debug, PartialEq, Eq)]
+pub struct ApiError {
+    pub status: u16,
+    pub msg: String,
+}
+
+impl ApiError {
+    pub fn bad_request(msg: impl Into<String>) -> Self {
+        ApiError {
+            status: 400,
+            msg: msg.into(),
+        }
+    }
+
+    pub fn not_found(msg: impl Into<String>) -> Self {
+        ApiError {
+            status: 404,
+            msg: msg.into(),
+        }
+    }
+
+    pub fn conflict(msg: impl Into<String>) -> Self {
+        ApiError {
+            status: 409,
+            msg: msg.into(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        if let Some(sqlx::Error::RowNotFound) = e.downcast_ref::<sqlx::Error>() {
+            return ApiError::not_found("not found");
+        }
+        ApiError {
+            status: 500,
+            msg: e.to_string(),
+        }
+    }
+}
+
+/// Authorization: Bearer <token> 是否与配置的 token 一致, 按常量时间比较
+pub fn authorized(req: &Request, token: &str) -> bool {
+    !token.is_empty()
+        && req
+            .header("authorization")
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(|t| verify_slices_are_equal(t.trim().as_bytes(), token.as_bytes()).is_ok())
+            .unwrap_or(false)
+}
+
+/// 管理接口服务, admin.enabled 为 false 时直接返回
+pub async fn admin_server(config: Arc<Config>) {
+    if !config.admin.enabled {
+        return;
+    }
+    let listener = match TcpListener::bind(&config.admin.listen).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("admin server bind {} failed: {}", config.admin.listen, e);
+            return;
+        }
+    };
+    info!("admin server listening on {}", config.admin.listen);
+    loop {
+        match listener.accept().await {
+            Ok((stream, addr)) => {
+                let config = config.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(REQUEST_TIMEOUT, serve(stream, &config)).await {
+                        Ok(Ok(())) => {}
+                        Ok(Err(e)) => warn!("admin request from {} failed: {}", addr, e),
+                        Err(_) => warn!("admin request from {} timed out", addr),
+                    }
+                });
+            }
+            Err(e) => warn!("admin server accept failed: {}", e),
+        }
+    }
+}
+
+async fn serve(mut stream: TcpStream, config: &Config) -> anyhow::Result<()> {
+    let req = match http::read_request(&mut stream).await {
+        Ok(Some(req)) => req,
+        Ok(None) => return Ok(()),
+        Err(e) => {
+            let body = json!({ "error": e.to_string() });
+            http::write_response(&mut stream, 400, &body).await?;
+            return Ok(());
+        }
+    };
+    let (status, body) = dispatch(&req, config).await;
+    info!("admin {} {} {}", req.method, req.path, status);
+    http::write_response(&mut stream, status, &body).await?;
+    Ok(())
+}
+
+async fn dispatch(req: &Request, config: &Config) -> (u16, Value) {
+    if !authorized(req, &config.admin.token) {
+        return (401, json!({ "error": "unauthorized" }));
+    }
+    let Some(route) = route(&req.method, &req.path) else {
+        return (404, json!({ "error": "no such route" }));
+    };
+    match handler::handle(config, route, req).await {
+        Ok(result) => result,
+        Err(e) => (e.status, json!({ "error": e.msg })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route() {
+        assert_eq!(
+            route("GET", "/strategies"),
+            Some(Route::List(Resource::Strategy))
+        );
+        assert_eq!(
+            route("POST", "/diff_rates/"),
+            Some(Route::Create(Resource::DiffRate))
+        );
+        assert_eq!(
+            route("PUT", "/stable_coins/7"),
+            Some(Route::Update(Resource::StableCoin, 7))
+        );
+        assert_eq!(
+            route("POST", "/strategies/3/stop"),
+            Some(Route::Transition(Resource::Strategy, 3, Action::Stop))
+        );
+        assert_eq!(
+            route("GET", "/strategies/3/progress"),
+            Some(Route::StrategyProgress(3))
+        );
+        assert_eq!(
+            route("GET", "/stable_coins/2/fills"),
+            Some(Route::StableCoinFills(2))
+        );
+        assert_eq!(route("GET", "/metrics/streams"), Some(Route::StreamMetrics));
+        assert_eq!(route("POST", "/halt"), Some(Route::SetHalt));
+        assert_eq!(route("DELETE", "/halt"), Some(Route::ClearHalt));
+        assert_eq!(route("PUT", "/halt"), None);
+        // 差价记录没有 stop, 子资源只挂在对应的资源下
+        assert_eq!(route("POST", "/diff_rates/1/stop"), None);
+        assert_eq!(route("GET", "/strategies/1/fills"), None);
+        assert_eq!(route("GET", "/strategies/x"), None);
+        assert_eq!(route("PATCH", "/strategies/1"), None);
+        assert_eq!(route("GET", "/users"), None);
+    }
+
+    #[test]
+    fn test_authorized() {
+        let mut req = Request::default();
+        assert!(!authorized(&req, "t"));
+        req.headers
+            .insert("authorization".to_string(), "Bearer t".to_string());
+        assert!(authorized(&req, "t"));
+        assert!(!authorized(&req, "u"));
+        assert!(!authorized(&req, "tt"));
+        // 未配置 token 时拒绝所有请求
+        assert!(!authorized(&req, ""));
+    }
+}
@@ -0,0 +1,364 @@
+//! 运维工具: 查看和人工干预套利策略
+//!
+//! cargo run --bin arbctl -- [--config <path>] <command>
+//!
+//! list                                         运行中和待人工处理的策略, 当前腿及最新差价
+//! timeline <strategy_id>                       策略的 arb_strategy_ex 及每次下单/划转记录
+//! retry <strategy_id>                          撤销当前腿未完成的订单, 未成交的下次轮询重新下单
+//! advance <strategy_id> [--amount <n>]         当前腿已在币安手工完成时标记完成, 策略需先暂停
+//! done <strategy_id> [--force]                 标记策略完成, 持有仓位时需 --force
+//! prices <market> [symbol...]                  redis 中缓存的最新价及买一卖一
+//! transfer <user_id> <asset> <amount> <type>   手动万向划转, type 如 MAIN_UMFUTURE
+//!
+//! RocksDB 以只读方式打开, 可以和运行中的 arbitrage 同时使用
+
+use anyhow::anyhow;
+use arbitrage::admin::handler::holds_position;
+use arbitrage::binance::rest_model::UniversalTransferType;
+use arbitrage::binance::ApiPool;
+use arbitrage::service::{account, binance_strategy, price};
+use arbitrage::{conf, db, helper, model, sql};
+use chrono::{Local, TimeZone};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use std::str::FromStr;
+use std::sync::Arc;
+
+const USAGE: &str = "usage: arbctl [--config <path>] <list | timeline <id> | retry <id> | advance <id> [--amount <n>] | done <id> [--force] | prices <market> [symbol...] | transfer <user_id> <asset> <amount> <type>>";
+
+enum Command {
+    List,
+    Timeline(i64),
+    Retry(i64),
+    Advance(i64, Option<Decimal>),
+    Done(i64, bool),
+    Prices(String, Vec<String>),
+    Transfer(i64, String, Decimal, UniversalTransferType),
+}
+
+fn parse_id(value: Option<String>) -> anyhow::Result<i64> {
+    let value = value.ok_or(anyhow!("missing id\n{}", USAGE))?;
+    value
+        .parse()
+        .map_err(|_| anyhow!("invalid id: {}\n{}", value, USAGE))
+}
+
+fn parse_args(args: Vec<String>) -> anyhow::Result<Command> {
+    // 配置文件路径由 conf::load_from_args 读取
+    let mut rest = Vec::new();
+    let mut iter = args.into_iter().skip(1);
+    while let Some(arg) = iter.next() {
+        if arg == "--config" {
+            iter.next();
+        } else if !arg.starts_with("--config=") {
+            rest.push(arg);
+        }
+    }
+    let mut iter = rest.into_iter();
+    let command = iter.next().ok_or(anyhow!(USAGE))?;
+    let command = match command.as_str() {
+        "list" => Command::List,
+        "timeline" => Command::Timeline(parse_id(iter.next())?),
+        "retry" => Command::Retry(parse_id(iter.next())?),
+        "advance" => {
+            let id = parse_id(iter.next())?;
+            let amount = match (iter.next().as_deref(), iter.next()) {
+                (Some("--amount"), Some(amount)) => Some(Decimal::from_str(&amount)?),
+                (None, _) => None,
+                _ => return Err(anyhow!(USAGE)),
+            };
+            Command::Advance(id, amount)
+        }
+        "done" => {
+            let id = parse_id(iter.next())?;
+            let force = match iter.next().as_deref() {
+                Some("--force") => true,
+                None => false,
+                _ => return Err(anyhow!(USAGE)),
+            };
+            Command::Done(id, force)
+        }
+        "prices" => {
+            let market = iter.next().ok_or(anyhow!(USAGE))?;
+            if !matches!(market.as_str(), "spot" | "futures" | "delivery") {
+                return Err(anyhow!("market must be spot, futures or delivery"));
+            }
+            Command::Prices(market, iter.by_ref().collect())
+        }
+        "transfer" => {
+            let user_id = parse_id(iter.next())?;
+            let (Some(asset), Some(amount), Some(transfer_type)) =
+                (iter.next(), iter.next(), iter.next())
+            else {
+                return Err(anyhow!(USAGE));
+            };
+            let amount = Decimal::from_str(&amount)?;
+            if amount <= Decimal::ZERO {
+                return Err(anyhow!("amount must be > 0"));
+            }
+            let transfer_type: UniversalTransferType =
+                serde_json::from_value(serde_json::Value::String(transfer_type.to_uppercase()))
+                    .map_err(|_| anyhow!("unknown transfer type: {}", transfer_type))?;
+            Command::Transfer(user_id, asset.to_uppercase(), amount, transfer_type)
+        }
+        _ => return Err(anyhow!(USAGE)),
+    };
+    if let Some(arg) = iter.next() {
+        return Err(anyhow!("unexpected argument: {}\n{}", arg, USAGE));
+    }
+    Ok(command)
+}
+
+fn status_name(doing_status: i8) -> &'static str {
+    match doing_status {
+        model::arb_strategy::DOING_STATUS_UN_RUN => "paused",
+        model::arb_strategy::DOING_STATUS_RUN => "running",
+        model::arb_strategy::DOING_STATUS_DONE => "done",
+        model::arb_strategy::DOING_STATUS_MANUAL => "manual",
+        _ => "unknown",
+    }
+}
+
+fn time(ts: Option<i64>) -> String {
+    ts.and_then(|ts| Local.timestamp_opt(ts, 0).single())
+        .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+fn print_ex(ex: &model::ArbStrategyEx) {
+    println!(
+        "ex {:<6} {:<26} {:<9} {:<16} {:<5} amount: {} executed: {} order: {} updated: {}",
+        ex.id,
+        ex.option_type,
+        ex.market,
+        ex.symbol,
+        if ex.option_status == model::arb_strategy_ex::OPTION_STATUS_DONE {
+            "done"
+        } else {
+            "todo"
+        },
+        ex.option_amount,
+        ex.option_executed_amt,
+        if ex.current_order_id.is_empty() {
+            "-"
+        } else {
+            ex.current_order_id.as_str()
+        },
+        time(ex.updated)
+    );
+}
+
+async fn list() -> anyhow::Result<()> {
+    let mut strategy_list =
+        sql::get_arb_strategy_list_by_doing_status(model::arb_strategy::DOING_STATUS_RUN).await?;
+    strategy_list.extend(
+        sql::get_arb_strategy_list_by_doing_status(model::arb_strategy::DOING_STATUS_MANUAL)
+            .await?,
+    );
+    println!(
+        "{:<6} {:<6} {:<9} {:<16} {:<16} {:<8} {:<26} {:<14} {:>12} {:>10} {:>10} {:<19}",
+        "id",
+        "user",
+        "option",
+        "from",
+        "to",
+        "status",
+        "leg",
+        "order",
+        "diff_rate",
+        "open",
+        "close",
+        "diff_updated"
+    );
+    for strategy in strategy_list {
+        let ex_list = sql::get_arb_strategy_ex_list_by_strategy_id(strategy.id).await?;
+        let (leg, order) = if ex_list.is_empty() {
+            ("-".to_string(), "-".to_string())
+        } else {
+            match binance_strategy::current_ex(&strategy, ex_list) {
+                Ok(Some(ex)) if ex.current_order_id.is_empty() => (ex.option_type, "-".to_string()),
+                Ok(Some(ex)) => (ex.option_type, ex.current_order_id),
+                Ok(None) => ("all done".to_string(), "-".to_string()),
+                Err(e) => (format!("err: {}", e), "-".to_string()),
+            }
+        };
+        let (diff_rate, updated) =
+            match sql::get_arb_diff_rate_info_by_diff_rate_id(strategy.diff_rate_id).await {
+                Ok(info) => (info.diff_rate.to_string(), time(info.updated)),
+                Err(_) => ("-".to_string(), "-".to_string()),
+            };
+        println!(
+            "{:<6} {:<6} {:<9} {:<16} {:<16} {:<8} {:<26} {:<14} {:>12} {:>10} {:>10} {:<19}",
+            strategy.id,
+            strategy.user_id,
+            strategy.option_choose,
+            strategy.from_symbol,
+            strategy.to_symbol,
+            status_name(strategy.doing_status),
+            leg,
+            order,
+            diff_rate,
+            strategy.option_open,
+            strategy.option_close,
+            updated
+        );
+        if strategy.doing_status == model::arb_strategy::DOING_STATUS_MANUAL {
+            if let Some(bak) = &strategy.bak {
+                println!("       {}", bak);
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn timeline(strategy_id: i64) -> anyhow::Result<()> {
+    let strategy = sql::get_arb_strategy_by_id(strategy_id).await?;
+    println!(
+        "strategy {} {} {} {} -> {} {}, status: {}, amount: {}, created: {}",
+        strategy.id,
+        strategy.option_choose,
+        strategy.from_market,
+        strategy.from_symbol,
+        strategy.to_market,
+        strategy.to_symbol,
+        status_name(strategy.doing_status),
+        strategy.option_amt,
+        time(strategy.created)
+    );
+    for ex in sql::get_arb_strategy_ex_list_by_strategy_id(strategy_id).await? {
+        print_ex(&ex);
+        for info in sql::get_arb_strategy_ex_info_list_by_ex_id(ex.id).await? {
+            let is_ok = match info.is_ok {
+                model::arb_strategy_ex_info::IS_OK_DONE => "done",
+                model::arb_strategy_ex_info::IS_OK_EXPIRED => "expired",
+                _ => "open",
+            };
+            println!(
+                "    {} {:<8} price: {} amount: {} executed: {} order: {} client: {}{}{}",
+                time(info.created),
+                is_ok,
+                info.price,
+                info.amount,
+                info.executed_amt,
+                if info.order_id.is_empty() {
+                    "-"
+                } else {
+                    info.order_id.as_str()
+                },
+                if info.client_order_id.is_empty() {
+                    "-"
+                } else {
+                    info.client_order_id.as_str()
+                },
+                if info.simulated == 1 { " (paper)" } else { "" },
+                info.bak.map(|b| format!(" [{}]", b)).unwrap_or_default()
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn done(strategy_id: i64, force: bool) -> anyhow::Result<()> {
+    let strategy = sql::get_arb_strategy_by_id(strategy_id).await?;
+    if strategy.doing_status == model::arb_strategy::DOING_STATUS_DONE {
+        return Err(anyhow!("strategy {} is already done", strategy_id));
+    }
+    let ex_list = sql::get_arb_strategy_ex_list_by_strategy_id(strategy_id).await?;
+    if !force && holds_position(&ex_list) {
+        return Err(anyhow!(
+            "strategy {} holds a position or has an order in flight, close it first or pass --force",
+            strategy_id
+        ));
+    }
+    sql::update_strategy_by_id(strategy_id, model::arb_strategy::DOING_STATUS_DONE).await?;
+    println!("strategy {} marked done", strategy_id);
+    Ok(())
+}
+
+async fn prices(market: String, symbols: Vec<String>) -> anyhow::Result<()> {
+    let now = Local::now().timestamp_millis();
+    println!(
+        "{:<18} {:>16} {:>16} {:>16} {:>10}",
+        "symbol", "price", "bid", "ask", "age_ms"
+    );
+    for info in price::get_binance_price_list(market.clone()).await? {
+        let symbol = info.ticker.symbol.clone();
+        if !symbols.is_empty() && !symbols.contains(&symbol) {
+            continue;
+        }
+        let (bid, ask) = match price::get_binance_book_ticker(market.clone(), symbol.clone()).await
+        {
+            Ok(book) => (book.ticker.best_bid, book.ticker.best_ask),
+            Err(_) => ("-".to_string(), "-".to_string()),
+        };
+        println!(
+            "{:<18} {:>16} {:>16} {:>16} {:>10}",
+            symbol,
+            info.ticker.current_close,
+            bid,
+            ask,
+            now - info.local_time
+        );
+    }
+    Ok(())
+}
+
+async fn transfer(
+    pool: &ApiPool,
+    user_id: i64,
+    asset: String,
+    amount: Decimal,
+    transfer_type: UniversalTransferType,
+) -> anyhow::Result<()> {
+    let api = account::user_api(pool, user_id)?;
+    account::check_transfer_funds(&api, &asset, amount, &transfer_type).await?;
+    let transfer = api
+        .universal_transfer(
+            asset.clone(),
+            amount
+                .to_f64()
+                .ok_or(anyhow!("invalid amount: {}", amount))?,
+            transfer_type.clone(),
+        )
+        .await?;
+    println!(
+        "user_id {} transferred {} {} ({:?}), transfer_id: {}",
+        user_id, amount, asset, transfer_type, transfer.tran_id
+    );
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let command = parse_args(std::env::args().collect())?;
+    // 初始化配置文件
+    let config = Arc::new(conf::load_from_args()?);
+    // 初始化Db
+    db::init_env_read_only(&config).await?;
+    // 初始化日志
+    helper::log::init_log(&config.log);
+    let pool = ApiPool::new(config);
+
+    match command {
+        Command::List => list().await,
+        Command::Timeline(id) => timeline(id).await,
+        Command::Retry(id) => {
+            let strategy = sql::get_arb_strategy_by_id(id).await?;
+            let api = account::user_api(&pool, strategy.user_id)?;
+            let ex = binance_strategy::retry_leg(&api, &strategy).await?;
+            print_ex(&ex);
+            Ok(())
+        }
+        Command::Advance(id, amount) => {
+            let strategy = sql::get_arb_strategy_by_id(id).await?;
+            let ex = binance_strategy::advance_leg(&strategy, amount).await?;
+            print_ex(&ex);
+            Ok(())
+        }
+        Command::Done(id, force) => done(id, force).await,
+        Command::Prices(market, symbols) => prices(market, symbols).await,
+        Command::Transfer(user_id, asset, amount, transfer_type) => {
+            transfer(&pool, user_id, asset, amount, transfer_type).await
+        }
+    }
+}
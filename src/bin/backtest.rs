@@ -0,0 +1,248 @@
+//! 回测期现套利策略
+//!
+//! cargo run --bin backtest -- [--config <path>] [--strategy <id>] [--source his|klines] [--interval 1m] [--start <秒>] [--end <秒>]
+//!
+//! his: 回放 arb_diff_rate_his (默认), klines: 通过币安 k 线拉取两腿收盘价
+//!
+//! 回测稳定币 boll 策略, 按 period x multiplier 网格扫描, 输出每组参数的汇总和逐笔成交
+//!
+//! cargo run --bin backtest -- boll --stable <id> [--periods 10,20,30] [--multipliers 1.5,2.0] [--fill limit|next_bar] [--fee 0] [--interval 15m] [--start <秒>] [--end <秒>]
+//!
+//! k线优先使用 RocksDB 中的持久化数据, 缺失的区间通过币安接口补齐
+
+use anyhow::anyhow;
+use arbitrage::binance::MyApi;
+use arbitrage::service::backtest::{self, BollFill};
+use arbitrage::service::kline;
+use arbitrage::{conf, db, helper, sql};
+use chrono::Local;
+use log::error;
+use rust_decimal::Decimal;
+use std::str::FromStr;
+
+struct Args {
+    boll: bool,
+    strategy_id: Option<i64>,
+    source: String,
+    interval: String,
+    start: i64,
+    end: i64,
+    periods: Vec<usize>,
+    multipliers: Vec<f64>,
+    fill: BollFill,
+    fee: Decimal,
+}
+
+fn parse_list<T: FromStr>(value: &str) -> anyhow::Result<Vec<T>>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    let mut list = Vec::new();
+    for v in value.split(',') {
+        list.push(v.trim().parse::<T>()?);
+    }
+    Ok(list)
+}
+
+fn parse_args() -> anyhow::Result<Args> {
+    let now = Local::now().timestamp();
+    let mut iter = std::env::args().skip(1).peekable();
+    let boll = iter.peek().map(|a| a.as_str()) == Some("boll");
+    if boll {
+        iter.next();
+    }
+    let mut args = Args {
+        boll,
+        strategy_id: None,
+        source: "his".to_string(),
+        interval: if boll { "15m" } else { "1m" }.to_string(),
+        start: now - 7 * 24 * 3600,
+        end: now,
+        periods: vec![20],
+        multipliers: vec![2.0],
+        fill: BollFill::Limit,
+        fee: Decimal::ZERO,
+    };
+    while let Some(flag) = iter.next() {
+        let value = iter.next().ok_or(anyhow!("missing value for {}", flag))?;
+        match flag.as_str() {
+            "--strategy" | "--stable" => args.strategy_id = Some(value.parse()?),
+            // 配置文件路径, 由 conf::load_from_args 读取
+            "--config" => {}
+            "--source" => args.source = value,
+            "--interval" => args.interval = value,
+            "--start" => args.start = value.parse()?,
+            "--end" => args.end = value.parse()?,
+            "--periods" => args.periods = parse_list(&value)?,
+            "--multipliers" => args.multipliers = parse_list(&value)?,
+            "--fee" => args.fee = Decimal::from_str(&value)?,
+            "--fill" => {
+                args.fill = match value.as_str() {
+                    "limit" => BollFill::Limit,
+                    "next_bar" => BollFill::NextBar,
+                    _ => return Err(anyhow!("--fill must be limit or next_bar")),
+                }
+            }
+            _ => return Err(anyhow!("unknown argument: {}", flag)),
+        }
+    }
+    if args.source != "his" && args.source != "klines" {
+        return Err(anyhow!("--source must be his or klines"));
+    }
+    if args.boll && args.strategy_id.is_none() {
+        return Err(anyhow!("boll backtest needs --stable <id>"));
+    }
+    Ok(args)
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = parse_args()?;
+    // 初始化配置文件
+    let config = conf::load_from_args()?;
+    // 初始化Db
+    db::init_env(&config).await?;
+    // 初始化日志
+    helper::log::init_log(&config.log);
+
+    let api = MyApi::from_config(&config);
+    if args.boll {
+        return boll(&api, &args).await;
+    }
+
+    let strategy_list: Vec<_> = sql::get_arb_strategy_list()
+        .await?
+        .into_iter()
+        .filter(|s| match args.strategy_id {
+            Some(id) => s.id == id,
+            None => true,
+        })
+        .collect();
+
+    println!(
+        "{:<6} {:<10} {:<16} {:<16} {:>8} {:>6} {:>14} {:>14} {:>14} {:>10}",
+        "id", "option", "from", "to", "ticks", "trips", "pnl", "unrealized", "max_dd", "in_pos%"
+    );
+    for strategy in strategy_list {
+        let ticks = if args.source == "his" {
+            let his_list = sql::get_arb_diff_rate_his_list_by_diff_rate_id(
+                strategy.diff_rate_id,
+                args.start,
+                args.end,
+            )
+            .await?;
+            backtest::ticks_from_his(&strategy, &his_list)
+        } else {
+            let (start, end) = (args.start * 1000, args.end * 1000);
+            let from = kline::klines_between(
+                &api,
+                &strategy.from_market,
+                &strategy.from_symbol,
+                &args.interval,
+                start,
+                end,
+            )
+            .await?;
+            let to = kline::klines_between(
+                &api,
+                &strategy.to_market,
+                &strategy.to_symbol,
+                &args.interval,
+                start,
+                end,
+            )
+            .await?;
+            backtest::ticks_from_klines(&strategy, &from, &to)
+        };
+
+        match backtest::backtest(&strategy, &ticks) {
+            Ok(report) => {
+                let in_position = if report.duration > 0 {
+                    report.time_in_position as f64 * 100.0 / report.duration as f64
+                } else {
+                    0.0
+                };
+                println!(
+                    "{:<6} {:<10} {:<16} {:<16} {:>8} {:>6} {:>14.4} {:>14.4} {:>14.4} {:>9.2}%",
+                    report.strategy_id,
+                    report.option_choose,
+                    report.from_symbol,
+                    report.to_symbol,
+                    report.ticks,
+                    report.round_trips,
+                    report.pnl,
+                    report.unrealized_pnl,
+                    report.max_drawdown,
+                    in_position
+                );
+            }
+            Err(e) => error!("strategy {} err: {:?}", strategy.id, e),
+        }
+    }
+
+    Ok(())
+}
+
+async fn boll(api: &MyApi, args: &Args) -> anyhow::Result<()> {
+    let stable = sql::get_arb_stable_coin_by_id(args.strategy_id.unwrap_or_default()).await?;
+    let (start, end) = (args.start * 1000, args.end * 1000);
+
+    // 与实盘共用 RocksDB 中的k线
+    let klines =
+        kline::klines_between(api, "spot", &stable.symbol, &args.interval, start, end).await?;
+
+    println!(
+        "{} {} klines, fill: {:?}, fee: {}",
+        stable.symbol,
+        klines.len(),
+        args.fill,
+        args.fee
+    );
+    println!(
+        "{:>6} {:>10} {:>8} {:>6} {:>8} {:>14} {:>10} {:>8}",
+        "period", "multiplier", "trades", "trips", "missed", "pnl", "return%", "holding"
+    );
+    let reports = backtest::boll_sweep(
+        &stable,
+        &klines,
+        &args.periods,
+        &args.multipliers,
+        args.fill,
+        args.fee,
+    )?;
+    for report in &reports {
+        println!(
+            "{:>6} {:>10} {:>8} {:>6} {:>8} {:>14.4} {:>9.4}% {:>8}",
+            report.period,
+            report.multiplier,
+            report.trades.len(),
+            report.round_trips,
+            report.missed,
+            report.pnl,
+            report.return_rate * Decimal::from(100),
+            report.holding
+        );
+    }
+
+    // 逐笔成交, 用于核对信号和成交价
+    println!();
+    println!(
+        "{:>6} {:>10} {:>14} {:>6} {:>14} {:>14}",
+        "period", "multiplier", "open_time", "side", "amount", "price"
+    );
+    for report in &reports {
+        for trade in &report.trades {
+            println!(
+                "{:>6} {:>10} {:>14} {:>6} {:>14} {:>14}",
+                report.period,
+                report.multiplier,
+                trade.time,
+                trade.option_type,
+                trade.amount,
+                trade.price
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -1,19 +1,27 @@
 #[macro_use]
 extern crate tokio;
 
+use arbitrage::binance::ApiPool;
 use arbitrage::{conf, db, helper, service};
 use futures::future::BoxFuture;
 use log::warn;
 use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::signal::unix::{signal, SignalKind};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // 初始化配置文件
-    lazy_static::initialize(&conf::C);
+    let config = Arc::new(conf::load_from_args()?);
     // 初始化Db
-    db::init_env().await?;
+    db::init_env(&config).await?;
     // 初始化日志
-    helper::log::init_log();
+    helper::log::init_log(&config.log);
+    // 每个用户的账户 api
+    let pool = Arc::new(ApiPool::new(config.clone()));
+
+    // 容器和 systemd 停止时发送 SIGTERM, 和 ctrl_c 一样优雅退出
+    let mut terminate = signal(SignalKind::terminate())?;
 
     let (close_tx, mut close_rx) = tokio::sync::mpsc::unbounded_channel::<bool>();
 
@@ -35,24 +43,31 @@ async fn main() -> anyhow::Result<()> {
     }
 
     let streams: Vec<BoxFuture<'static, ()>> = vec![
-        Box::pin(service::inspect_stable_coin(txs.clone())), // 轮训策略
+        Box::pin(service::halt::watch_halt(config.clone())), // 停止交易开关
     ];
 
     for stream in streams {
         tokio::spawn(stream);
     }
+    // 轮训策略, 退出时停止分发
+    let dispatcher = tokio::spawn(service::inspect_stable_coin(txs));
 
     // 开始线程池
-    service::event_stable_coin_start(rxs).await;
+    let workers = service::event_stable_coin_start(pool, rxs).await;
 
     select! {
         _ = wait_loop => { warn!("Finished!") }
         _ = tokio::signal::ctrl_c() => {
             warn!("Closing stream...");
             close_tx.send(true).unwrap();
-            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        }
+        _ = terminate.recv() => {
+            warn!("SIGTERM, closing stream...");
+            close_tx.send(true).unwrap();
         }
     }
+    // 停止分发, 等待进行中的下单/划转完成
+    service::halt::shutdown(&config.shutdown, dispatcher, workers).await;
 
     Ok(())
 }
@@ -1,10 +1,14 @@
 use crate::binance::client::Client;
 use crate::binance::config::Config;
 use crate::binance::errors::*;
+use crate::binance::paper::PaperExchange;
 use crate::binance::rest_model::*;
-use crate::binance::util::{build_request, build_signed_request_p, to_f64, to_i64};
-use crate::conf::C;
+use crate::binance::util::{
+    build_request, build_signed_request, build_signed_request_p, to_f64, to_i64,
+};
+use crate::conf;
 use serde_json::Value;
+use std::sync::Arc;
 
 #[derive(Default, Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -16,6 +20,8 @@ pub struct FuturesOrderRequest {
     pub quantity: Option<f64>,
     pub price: Option<f64>,
     pub time_in_force: Option<TimeInForce>,
+    /// A unique id among open orders, automatically generated if not sent.
+    pub new_client_order_id: Option<String>,
     pub recv_window: Option<u64>,
 }
 
@@ -75,36 +81,85 @@ pub struct OrderStatusRequest {
     pub recv_window: Option<u64>,
 }
 
+const NO_PARAMS: [(&str, &str); 0] = [];
+
+#[derive(Serialize)]
+struct SymbolRequest {
+    symbol: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TransferHistoryRequest {
+    #[serde(rename = "type")]
+    transfer_type: UniversalTransferType,
+    start_time: Option<u64>,
+    size: u64,
+}
+
 #[derive(Clone)]
 pub struct MyApi {
     pub client: Client,
     pub futures_client: Client,
     pub delivery_client: Client,
     pub recv_window: u64,
+    /// Orders and transfers are served by the paper exchange when set
+    pub paper: Option<Arc<PaperExchange>>,
 }
 
 impl MyApi {
-    pub fn new() -> Self {
+    /// Api with the `binance_api_config` credentials of `config`, for market data and account
+    /// streams; it always talks to binance, trading apis come from the [`ApiPool`](crate::binance::pool::ApiPool)
+    pub fn from_config(config: &conf::Config) -> Self {
+        Self::with_credentials(
+            &config.binance_api_config.api_key,
+            &config.binance_api_config.secret_key,
+            None,
+        )
+    }
+
+    /// Api with the given credentials, orders go to `paper` when set
+    pub fn with_credentials(
+        api_key: &str,
+        secret_key: &str,
+        paper: Option<Arc<PaperExchange>>,
+    ) -> Self {
+        let mut api = Self::new_with_config(
+            api_key.to_string(),
+            secret_key.to_string(),
+            &Config::default().set_timeout(5),
+        );
+        api.paper = paper;
+        api
+    }
+
+    pub fn is_paper(&self) -> bool {
+        self.paper.is_some()
+    }
+
+    /// Api with the given credentials against the endpoints of `config`
+    pub fn new_with_config(api_key: String, secret_key: String, config: &Config) -> Self {
         MyApi {
             client: Client::new(
-                Some(C.binance_api_config.api_key.clone()),
-                Some(C.binance_api_config.secret_key.clone()),
-                Config::default().rest_api_endpoint,
-                Some(5),
+                Some(api_key.clone()),
+                Some(secret_key.clone()),
+                config.rest_api_endpoint.clone(),
+                config.timeout,
             ),
             futures_client: Client::new(
-                Some(C.binance_api_config.api_key.clone()),
-                Some(C.binance_api_config.secret_key.clone()),
-                Config::default().futures_rest_api_endpoint,
-                Some(5),
+                Some(api_key.clone()),
+                Some(secret_key.clone()),
+                config.futures_rest_api_endpoint.clone(),
+                config.timeout,
             ),
             delivery_client: Client::new(
-                Some(C.binance_api_config.api_key.clone()),
-                Some(C.binance_api_config.secret_key.clone()),
-                Config::default().delivery_rest_api_endpoint,
-                Some(5),
+                Some(api_key),
+                Some(secret_key),
+                config.delivery_rest_api_endpoint.clone(),
+                config.timeout,
             ),
-            recv_window: 5000,
+            recv_window: config.recv_window,
+            paper: None,
         }
     }
 
@@ -126,40 +181,135 @@ impl MyApi {
         S3: Into<Option<u16>>,
         S4: Into<Option<u64>>,
         S5: Into<Option<u64>>,
+    {
+        let request = klines_request(symbol, interval, limit, start_time, end_time);
+        klines(&self.client, "/api/v3/klines", &request).await
+    }
+
+    /// USDⓈ-M klines, same parameters as `get_klines`
+    pub async fn futures_get_klines<S1, S2, S3, S4, S5>(
+        &self,
+        symbol: S1,
+        interval: S2,
+        limit: S3,
+        start_time: S4,
+        end_time: S5,
+    ) -> Result<KlineSummaries>
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+        S3: Into<Option<u16>>,
+        S4: Into<Option<u64>>,
+        S5: Into<Option<u64>>,
+    {
+        let request = klines_request(symbol, interval, limit, start_time, end_time);
+        klines(&self.futures_client, "/fapi/v1/klines", &request).await
+    }
+
+    /// COIN-M klines, same parameters as `get_klines`
+    pub async fn delivery_get_klines<S1, S2, S3, S4, S5>(
+        &self,
+        symbol: S1,
+        interval: S2,
+        limit: S3,
+        start_time: S4,
+        end_time: S5,
+    ) -> Result<KlineSummaries>
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+        S3: Into<Option<u16>>,
+        S4: Into<Option<u64>>,
+        S5: Into<Option<u64>>,
+    {
+        let request = klines_request(symbol, interval, limit, start_time, end_time);
+        klines(&self.delivery_client, "/dapi/v1/klines", &request).await
+    }
+
+    /// Order book snapshot, limit 5, 10, 20, 50, 100, 500, 1000 or 5000
+    pub async fn get_depth<S>(&self, symbol: S, limit: u16) -> Result<OrderBook>
+    where
+        S: Into<String>,
+    {
+        let request = build_request([("symbol", symbol.into()), ("limit", limit.to_string())]);
+        self.client.get("/api/v3/depth", Some(&request)).await
+    }
+
+    /// USDⓈ-M order book snapshot, limit 5, 10, 20, 50, 100, 500 or 1000
+    pub async fn futures_get_depth<S>(&self, symbol: S, limit: u16) -> Result<OrderBook>
+    where
+        S: Into<String>,
+    {
+        let request = build_request([("symbol", symbol.into()), ("limit", limit.to_string())]);
+        self.futures_client
+            .get("/fapi/v1/depth", Some(&request))
+            .await
+    }
+
+    /// COIN-M order book snapshot, quantities are contracts
+    pub async fn delivery_get_depth<S>(&self, symbol: S, limit: u16) -> Result<OrderBook>
+    where
+        S: Into<String>,
+    {
+        let request = build_request([("symbol", symbol.into()), ("limit", limit.to_string())]);
+        self.delivery_client
+            .get("/dapi/v1/depth", Some(&request))
+            .await
+    }
+
+    /// Spot symbols
+    pub async fn exchange_info(&self) -> Result<ExchangeInformation> {
+        self.client.get("/api/v3/exchangeInfo", None).await
+    }
+
+    /// USDⓈ-M contracts
+    pub async fn futures_exchange_info(&self) -> Result<FuturesExchangeInfo> {
+        self.futures_client.get("/fapi/v1/exchangeInfo", None).await
+    }
+
+    /// COIN-M contracts
+    pub async fn delivery_exchange_info(&self) -> Result<FuturesExchangeInfo> {
+        self.delivery_client
+            .get("/dapi/v1/exchangeInfo", None)
+            .await
+    }
+
+    /// Mark price and predicted funding rate of a USDⓈ-M symbol
+    pub async fn futures_premium_index<S>(&self, symbol: S) -> Result<PremiumIndex>
+    where
+        S: Into<String>,
+    {
+        let request = build_request([("symbol", symbol.into())]);
+        self.futures_client
+            .get("/fapi/v1/premiumIndex", Some(&request))
+            .await
+    }
+
+    /// Settled fundings of a USDⓈ-M symbol, oldest first, limit defaults to 100 and is at most 1000
+    pub async fn futures_funding_rate<S1, S2, S3, S4>(
+        &self,
+        symbol: S1,
+        start_time: S2,
+        end_time: S3,
+        limit: S4,
+    ) -> Result<Vec<FundingRate>>
+    where
+        S1: Into<String>,
+        S2: Into<Option<u64>>,
+        S3: Into<Option<u64>>,
+        S4: Into<Option<u16>>,
     {
         let parameters = IntoIterator::into_iter([
             Some(("symbol", symbol.into())),
-            Some(("interval", interval.into())),
-            limit.into().map(|l| ("limit", l.to_string())),
             start_time.into().map(|s| ("startTime", s.to_string())),
             end_time.into().map(|e| ("endTime", e.to_string())),
+            limit.into().map(|l| ("limit", l.to_string())),
         ])
         .flatten();
-
         let request = build_request(parameters);
-
-        let parsed_data: Vec<Vec<Value>> =
-            self.client.get("/api/v3/klines", Some(&request)).await?;
-
-        let klines = KlineSummaries::AllKlineSummaries(
-            parsed_data
-                .iter()
-                .map(|row| KlineSummary {
-                    open_time: to_i64(&row[0]),
-                    open: to_f64(&row[1]),
-                    high: to_f64(&row[2]),
-                    low: to_f64(&row[3]),
-                    close: to_f64(&row[4]),
-                    volume: to_f64(&row[5]),
-                    close_time: to_i64(&row[6]),
-                    quote_asset_volume: to_f64(&row[7]),
-                    number_of_trades: to_i64(&row[8]),
-                    taker_buy_base_asset_volume: to_f64(&row[9]),
-                    taker_buy_quote_asset_volume: to_f64(&row[10]),
-                })
-                .collect(),
-        );
-        Ok(klines)
+        self.futures_client
+            .get("/fapi/v1/fundingRate", Some(&request))
+            .await
     }
 
     pub async fn universal_transfer(
@@ -168,6 +318,9 @@ impl MyApi {
         amount: f64,
         transfer_type: UniversalTransferType,
     ) -> Result<TransactionId> {
+        if let Some(paper) = &self.paper {
+            return paper.universal_transfer(asset, amount, transfer_type).await;
+        }
         let transfer = UniversalTransfer {
             asset,
             amount,
@@ -181,7 +334,29 @@ impl MyApi {
             .await
     }
 
+    /// Universal transfers of `transfer_type` since `start_time` (ms), newest first, at most 100
+    pub async fn transfer_history(
+        &self,
+        transfer_type: UniversalTransferType,
+        start_time: Option<u64>,
+    ) -> Result<TransferHistory> {
+        if let Some(paper) = &self.paper {
+            return paper.transfer_history(transfer_type, start_time).await;
+        }
+        let request = TransferHistoryRequest {
+            transfer_type,
+            start_time,
+            size: 100,
+        };
+        self.client
+            .get_signed_p("/sapi/v1/asset/transfer", Some(request), self.recv_window)
+            .await
+    }
+
     pub async fn place_order(&self, order: OrderRequest) -> Result<Transaction> {
+        if let Some(paper) = &self.paper {
+            return paper.place_order(order).await;
+        }
         order.valid()?;
         let recv_window = order.recv_window.unwrap_or(self.recv_window);
         let request = build_signed_request_p(order, recv_window)?;
@@ -189,16 +364,69 @@ impl MyApi {
     }
 
     pub async fn order_status(&self, osr: OrderStatusRequest) -> Result<Order> {
+        if let Some(paper) = &self.paper {
+            return paper.order_status(osr).await;
+        }
         let recv_window = osr.recv_window.unwrap_or(self.recv_window);
         let request = build_signed_request_p(osr, recv_window)?;
         self.client.get_signed("/api/v3/order", &request).await
     }
 
+    /// Cancel an active order
+    pub async fn cancel_order(&self, osr: OrderStatusRequest) -> Result<OrderCanceled> {
+        if let Some(paper) = &self.paper {
+            return paper.cancel_order(osr).await;
+        }
+        let recv_window = osr.recv_window.unwrap_or(self.recv_window);
+        self.client
+            .delete_signed_p("/api/v3/order", osr, recv_window)
+            .await
+    }
+
+    /// Cancel all active orders of a symbol
+    pub async fn cancel_all_open_orders<S>(&self, symbol: S) -> Result<Vec<OrderCanceled>>
+    where
+        S: Into<String>,
+    {
+        let symbol = symbol.into();
+        if let Some(paper) = &self.paper {
+            return paper.cancel_all_open_orders(&symbol).await;
+        }
+        self.client
+            .delete_signed_p(
+                "/api/v3/openOrders",
+                SymbolRequest { symbol },
+                self.recv_window,
+            )
+            .await
+    }
+
+    /// Active orders of a symbol
+    pub async fn open_orders<S>(&self, symbol: S) -> Result<Vec<Order>>
+    where
+        S: Into<String>,
+    {
+        let symbol = symbol.into();
+        if let Some(paper) = &self.paper {
+            return paper.open_orders(&symbol).await;
+        }
+        self.client
+            .get_signed_p(
+                "/api/v3/openOrders",
+                Some(SymbolRequest { symbol }),
+                self.recv_window,
+            )
+            .await
+    }
+
     /// Get an order
     pub async fn futures_order_status(
         &self,
         order: FuturesGetOrderRequest,
     ) -> Result<FuturesTransaction> {
+        if let Some(paper) = &self.paper {
+            return paper.futures_order_status(order).await;
+        }
         self.futures_client
             .get_signed_p("/fapi/v1/order", Some(order), self.recv_window)
             .await
@@ -209,16 +437,71 @@ impl MyApi {
         &self,
         order: FuturesOrderRequest,
     ) -> Result<FuturesTransaction> {
+        if let Some(paper) = &self.paper {
+            return paper.futures_place_order(order).await;
+        }
         self.futures_client
             .post_signed_p("/fapi/v1/order", order, self.recv_window)
             .await
     }
 
+    /// Cancel an active order
+    pub async fn futures_cancel_order(
+        &self,
+        order: FuturesGetOrderRequest,
+    ) -> Result<FuturesTransaction> {
+        if let Some(paper) = &self.paper {
+            return paper.futures_cancel_order(order).await;
+        }
+        self.futures_client
+            .delete_signed_p("/fapi/v1/order", order, self.recv_window)
+            .await
+    }
+
+    /// Cancel all active orders of a symbol
+    pub async fn futures_cancel_all_open_orders<S>(&self, symbol: S) -> Result<Success>
+    where
+        S: Into<String>,
+    {
+        let symbol = symbol.into();
+        if let Some(paper) = &self.paper {
+            return paper.futures_cancel_all_open_orders(&symbol).await;
+        }
+        self.futures_client
+            .delete_signed_p(
+                "/fapi/v1/allOpenOrders",
+                SymbolRequest { symbol },
+                self.recv_window,
+            )
+            .await
+    }
+
+    /// Active orders of a symbol
+    pub async fn futures_open_orders<S>(&self, symbol: S) -> Result<Vec<FuturesTransaction>>
+    where
+        S: Into<String>,
+    {
+        let symbol = symbol.into();
+        if let Some(paper) = &self.paper {
+            return paper.futures_open_orders(&symbol).await;
+        }
+        self.futures_client
+            .get_signed_p(
+                "/fapi/v1/openOrders",
+                Some(SymbolRequest { symbol }),
+                self.recv_window,
+            )
+            .await
+    }
+
     /// Get an order
     pub async fn delivery_order_status(
         &self,
         order: FuturesGetOrderRequest,
     ) -> Result<FuturesTransaction> {
+        if let Some(paper) = &self.paper {
+            return paper.delivery_order_status(order).await;
+        }
         self.delivery_client
             .get_signed_p("/dapi/v1/order", Some(order), self.recv_window)
             .await
@@ -229,19 +512,239 @@ impl MyApi {
         &self,
         order: FuturesOrderRequest,
     ) -> Result<FuturesTransaction> {
+        if let Some(paper) = &self.paper {
+            return paper.delivery_place_order(order).await;
+        }
         self.delivery_client
             .post_signed_p("/dapi/v1/order", order, self.recv_window)
             .await
     }
+
+    /// Cancel an active order
+    pub async fn delivery_cancel_order(
+        &self,
+        order: FuturesGetOrderRequest,
+    ) -> Result<FuturesTransaction> {
+        if let Some(paper) = &self.paper {
+            return paper.delivery_cancel_order(order).await;
+        }
+        self.delivery_client
+            .delete_signed_p("/dapi/v1/order", order, self.recv_window)
+            .await
+    }
+
+    /// Cancel all active orders of a symbol
+    pub async fn delivery_cancel_all_open_orders<S>(&self, symbol: S) -> Result<Success>
+    where
+        S: Into<String>,
+    {
+        let symbol = symbol.into();
+        if let Some(paper) = &self.paper {
+            return paper.delivery_cancel_all_open_orders(&symbol).await;
+        }
+        self.delivery_client
+            .delete_signed_p(
+                "/dapi/v1/allOpenOrders",
+                SymbolRequest { symbol },
+                self.recv_window,
+            )
+            .await
+    }
+
+    /// Active orders of a symbol
+    pub async fn delivery_open_orders<S>(&self, symbol: S) -> Result<Vec<FuturesTransaction>>
+    where
+        S: Into<String>,
+    {
+        let symbol = symbol.into();
+        if let Some(paper) = &self.paper {
+            return paper.delivery_open_orders(&symbol).await;
+        }
+        self.delivery_client
+            .get_signed_p(
+                "/dapi/v1/openOrders",
+                Some(SymbolRequest { symbol }),
+                self.recv_window,
+            )
+            .await
+    }
+
+    /// Spot balances
+    pub async fn account_information(&self) -> Result<AccountInformation> {
+        if let Some(paper) = &self.paper {
+            return paper.account_information().await;
+        }
+        let request = build_signed_request(NO_PARAMS, self.recv_window)?;
+        self.client.get_signed("/api/v3/account", &request).await
+    }
+
+    /// USDⓈ-M balances and margin
+    pub async fn futures_account(&self) -> Result<FuturesAccount> {
+        if let Some(paper) = &self.paper {
+            return paper.futures_account().await;
+        }
+        let request = build_signed_request(NO_PARAMS, self.recv_window)?;
+        self.futures_client
+            .get_signed("/fapi/v2/account", &request)
+            .await
+    }
+
+    /// USDⓈ-M position of a symbol
+    pub async fn futures_position_risk<S>(&self, symbol: S) -> Result<Vec<PositionRisk>>
+    where
+        S: Into<String>,
+    {
+        let symbol = symbol.into();
+        if let Some(paper) = &self.paper {
+            return paper.futures_position_risk(&symbol).await;
+        }
+        let request = build_signed_request([("symbol", symbol)], self.recv_window)?;
+        self.futures_client
+            .get_signed("/fapi/v2/positionRisk", &request)
+            .await
+    }
+
+    /// COIN-M balances and margin
+    pub async fn delivery_account(&self) -> Result<FuturesAccount> {
+        if let Some(paper) = &self.paper {
+            return paper.delivery_account().await;
+        }
+        let request = build_signed_request(NO_PARAMS, self.recv_window)?;
+        self.delivery_client
+            .get_signed("/dapi/v1/account", &request)
+            .await
+    }
+
+    /// COIN-M positions of every contract of a pair, `ETHUSD`
+    pub async fn delivery_position_risk<S>(&self, pair: S) -> Result<Vec<PositionRisk>>
+    where
+        S: Into<String>,
+    {
+        let pair = pair.into();
+        if let Some(paper) = &self.paper {
+            return paper.delivery_position_risk(&pair).await;
+        }
+        let request = build_signed_request([("pair", pair)], self.recv_window)?;
+        self.delivery_client
+            .get_signed("/dapi/v1/positionRisk", &request)
+            .await
+    }
+
+    /// Start a spot user data stream, the listen key expires after 60 minutes without keepalive
+    pub async fn start_user_data_stream(&self) -> Result<UserDataStream> {
+        self.client.post("/api/v3/userDataStream", None).await
+    }
+
+    /// Extend the listen key validity by 60 minutes, binance recommends every 30 minutes
+    pub async fn keep_alive_user_data_stream(&self, listen_key: &str) -> Result<Success> {
+        self.client
+            .put("/api/v3/userDataStream", listen_key, None)
+            .await
+    }
+
+    pub async fn close_user_data_stream(&self, listen_key: &str) -> Result<Success> {
+        self.client
+            .delete("/api/v3/userDataStream", listen_key, None)
+            .await
+    }
+
+    /// Start a USDⓈ-M user data stream
+    pub async fn futures_start_user_data_stream(&self) -> Result<UserDataStream> {
+        self.futures_client.post("/fapi/v1/listenKey", None).await
+    }
+
+    pub async fn futures_keep_alive_user_data_stream(&self, listen_key: &str) -> Result<Success> {
+        self.futures_client
+            .put("/fapi/v1/listenKey", listen_key, None)
+            .await
+    }
+
+    pub async fn futures_close_user_data_stream(&self, listen_key: &str) -> Result<Success> {
+        self.futures_client
+            .delete("/fapi/v1/listenKey", listen_key, None)
+            .await
+    }
+
+    /// Start a COIN-M user data stream
+    pub async fn delivery_start_user_data_stream(&self) -> Result<UserDataStream> {
+        self.delivery_client.post("/dapi/v1/listenKey", None).await
+    }
+
+    pub async fn delivery_keep_alive_user_data_stream(&self, listen_key: &str) -> Result<Success> {
+        self.delivery_client
+            .put("/dapi/v1/listenKey", listen_key, None)
+            .await
+    }
+
+    pub async fn delivery_close_user_data_stream(&self, listen_key: &str) -> Result<Success> {
+        self.delivery_client
+            .delete("/dapi/v1/listenKey", listen_key, None)
+            .await
+    }
+}
+
+fn klines_request<S1, S2, S3, S4, S5>(
+    symbol: S1,
+    interval: S2,
+    limit: S3,
+    start_time: S4,
+    end_time: S5,
+) -> String
+where
+    S1: Into<String>,
+    S2: Into<String>,
+    S3: Into<Option<u16>>,
+    S4: Into<Option<u64>>,
+    S5: Into<Option<u64>>,
+{
+    let parameters = IntoIterator::into_iter([
+        Some(("symbol", symbol.into())),
+        Some(("interval", interval.into())),
+        limit.into().map(|l| ("limit", l.to_string())),
+        start_time.into().map(|s| ("startTime", s.to_string())),
+        end_time.into().map(|e| ("endTime", e.to_string())),
+    ])
+    .flatten();
+
+    build_request(parameters)
+}
+
+async fn klines(client: &Client, endpoint: &str, request: &str) -> Result<KlineSummaries> {
+    let parsed_data: Vec<Vec<Value>> = client.get(endpoint, Some(request)).await?;
+
+    let klines = KlineSummaries::AllKlineSummaries(
+        parsed_data
+            .iter()
+            .map(|row| KlineSummary {
+                open_time: to_i64(&row[0]),
+                open: to_f64(&row[1]),
+                high: to_f64(&row[2]),
+                low: to_f64(&row[3]),
+                close: to_f64(&row[4]),
+                volume: to_f64(&row[5]),
+                close_time: to_i64(&row[6]),
+                quote_asset_volume: to_f64(&row[7]),
+                number_of_trades: to_i64(&row[8]),
+                taker_buy_base_asset_volume: to_f64(&row[9]),
+                taker_buy_quote_asset_volume: to_f64(&row[10]),
+            })
+            .collect(),
+    );
+    Ok(klines)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn api() -> MyApi {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/config.toml");
+        MyApi::from_config(&conf::Config::load(path, std::env::vars()).unwrap())
+    }
+
     #[tokio::test]
     async fn test_binance_api() {
-        let api = MyApi::new();
+        let api = api();
         // let time = api.get_server_time().await;
         // println!("{:?}", time);
         /*let transfer = api
@@ -275,7 +778,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_binance_futures_place_order() {
-        let api = MyApi::new();
+        let api = api();
         let transaction = api
             .futures_place_order(FuturesOrderRequest {
                 symbol: "ETHUSDT".to_string(),
@@ -284,6 +787,7 @@ mod tests {
                 quantity: Some(0.1),
                 price: Some(3950.0),
                 time_in_force: Some(TimeInForce::GTC),
+                new_client_order_id: None,
                 recv_window: None,
             })
             .await;
@@ -292,7 +796,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_binance_futures_order_status() {
-        let api = MyApi::new();
+        let api = api();
         let transaction = api
             .futures_order_status(FuturesGetOrderRequest {
                 symbol: "ETHUSDT".to_string(),
@@ -305,7 +809,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_binance_delivery_place_order() {
-        let api = MyApi::new();
+        let api = api();
         let transaction = api
             .delivery_place_order(FuturesOrderRequest {
                 symbol: "ETHUSD_PERP".to_string(),
@@ -314,6 +818,7 @@ mod tests {
                 quantity: Some(1.0),
                 price: Some(140.0),
                 time_in_force: Some(TimeInForce::GTC),
+                new_client_order_id: None,
                 recv_window: None,
             })
             .await;
@@ -322,7 +827,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_binance_delivery_order_status() {
-        let api = MyApi::new();
+        let api = api();
         let transaction = api
             .delivery_order_status(FuturesGetOrderRequest {
                 symbol: "ETHUSD_PERP".to_string(),
@@ -268,10 +268,20 @@ impl Client {
             StatusCode::INTERNAL_SERVER_ERROR => Err(Error::InternalServerError),
             StatusCode::SERVICE_UNAVAILABLE => Err(Error::ServiceUnavailable),
             StatusCode::UNAUTHORIZED => Err(Error::Unauthorized),
+            // 418 is returned once an IP keeps sending after a 429
+            StatusCode::TOO_MANY_REQUESTS | StatusCode::IM_A_TEAPOT => {
+                let retry_after = response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse().ok());
+                Err(Error::TooManyRequests { retry_after })
+            }
             StatusCode::BAD_REQUEST => {
                 let error: BinanceContentError = response.json().await?;
                 Err(handle_content_error(error))
             }
+            s if s.is_server_error() => Err(Error::ServiceUnavailable),
             s => Err(Error::Msg(format!("Received response: {s:?}"))),
         }
     }
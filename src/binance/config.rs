@@ -113,6 +113,46 @@ impl Config {
         self
     }
 
+    /// Sets the delivery rest api endpoint. Defaults to <https://dapi.binance.com>.
+    ///
+    /// # Arguments
+    ///
+    /// * `delivery_rest_api_endpoint`:
+    ///
+    /// returns: Config
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use binance::config::Config;
+    /// let config = Config::default();
+    /// config.set_delivery_rest_api_endpoint("http://myendpoint:8080");
+    /// ```
+    pub fn set_delivery_rest_api_endpoint<T: Into<String>>(mut self, delivery_rest_api_endpoint: T) -> Self {
+        self.delivery_rest_api_endpoint = delivery_rest_api_endpoint.into();
+        self
+    }
+
+    /// Sets the delivery websocket endpoint. Defaults to "wss://dstream.binance.com".
+    ///
+    /// # Arguments
+    ///
+    /// * `delivery_ws_endpoint`:
+    ///
+    /// returns: Config
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use binance::config::Config;
+    /// let config = Config::default();
+    /// config.set_delivery_ws_endpoint("ws://myendpoint:8080");
+    /// ```
+    pub fn set_delivery_ws_endpoint<T: Into<String>>(mut self, delivery_ws_endpoint: T) -> Self {
+        self.delivery_ws_endpoint = delivery_ws_endpoint.into();
+        self
+    }
+
     /// Sets the 'receive window'. The receive window is the number of milliseconds after timestamp
     /// the request is valid for.
     ///
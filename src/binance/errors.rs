@@ -13,6 +13,16 @@ pub struct BinanceContentError {
     extra: HashMap<String, Value>,
 }
 
+impl BinanceContentError {
+    pub fn new(code: i32, msg: String) -> Self {
+        BinanceContentError {
+            code,
+            msg,
+            extra: HashMap::new(),
+        }
+    }
+}
+
 /// First errors are technical errors
 /// All unhandled binance content errors are BinanceError
 /// The rest are binance content errors that are properly handled
@@ -60,10 +70,29 @@ pub enum Error {
     ServiceUnavailable,
     #[error("Unauthorized")]
     Unauthorized,
+    #[error("too many requests, retry after {retry_after:?}s")]
+    TooManyRequests { retry_after: Option<u64> },
     #[error("{0}")]
     Msg(String),
 }
 
+impl Error {
+    /// Timeouts, rate limits and server side failures, the same request may succeed when retried
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::ReqError(e) => e.is_timeout() || e.is_connect(),
+            Error::InternalServerError
+            | Error::ServiceUnavailable
+            | Error::TooManyRequests { .. } => true,
+            // UNKNOWN, DISCONNECTED, TOO_MANY_REQUESTS, UNEXPECTED_RESP, TIMEOUT, SERVER_BUSY
+            Error::BinanceError { response } => {
+                matches!(response.code, -1000 | -1001 | -1003 | -1006 | -1007 | -1008)
+            }
+            _ => false,
+        }
+    }
+}
+
 /// Custom error messages
 pub mod error_messages {
     pub const INVALID_PRICE: &str = "Invalid price.";
@@ -2,9 +2,14 @@ pub mod api;
 pub mod client;
 pub mod config;
 pub mod errors;
+pub mod paper;
+pub mod pool;
 pub mod rest_model;
+#[cfg(any(test, feature = "simulator"))]
+pub mod simulator;
 pub mod util;
 pub mod websockets;
 pub mod ws_model;
 
 pub use api::MyApi;
+pub use pool::ApiPool;
@@ -0,0 +1,1371 @@
+//! Paper trading engine.
+//!
+//! When `[paper] enabled = true` in the config, [`MyApi`](crate::binance::MyApi) routes order
+//! placement, order queries, cancels and universal transfers here instead of to Binance. Limit
+//! orders are matched against the last mini-ticker price cached in Redis, FOK/IOC orders that
+//! can't be matched expire and GTC orders rest until a later query finds them marketable or they
+//! are canceled.
+//!
+//! Balances are tracked per wallet (spot / USDⓈ-M / COIN-M) and persisted to Redis so a restart
+//! resumes with the same virtual account.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+
+use crate::binance::api::{
+    FuturesGetOrderRequest, FuturesOrderRequest, OrderRequest, OrderStatusRequest,
+};
+use crate::binance::errors::*;
+use crate::binance::rest_model::*;
+use crate::binance::util::get_timestamp;
+use crate::conf::{redis_key, PaperConfig};
+use crate::db;
+use crate::service::price::get_binance_price;
+
+/// Leverage reported for paper positions, binance's default for new symbols
+pub const PAPER_LEVERAGE: f64 = 20.0;
+
+const SPOT_QUOTES: [&str; 8] = ["FDUSD", "USDT", "USDC", "TUSD", "BUSD", "BTC", "ETH", "BNB"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Wallet {
+    Spot,
+    Futures,
+    Delivery,
+}
+
+impl Wallet {
+    /// Same names as the markets cached by `set_binance_price`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Wallet::Spot => "spot",
+            Wallet::Futures => "futures",
+            Wallet::Delivery => "delivery",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaperOrder {
+    pub wallet: Wallet,
+    pub order_id: u64,
+    pub client_order_id: String,
+    pub symbol: String,
+    pub side: OrderSide,
+    pub order_type: OrderType,
+    pub time_in_force: TimeInForce,
+    pub price: f64,
+    pub orig_qty: f64,
+    pub executed_qty: f64,
+    pub avg_price: f64,
+    pub commission: f64,
+    pub commission_asset: String,
+    pub status: OrderStatus,
+    pub time: u64,
+    pub update_time: u64,
+}
+
+impl PaperOrder {
+    fn is_open(&self) -> bool {
+        self.status == OrderStatus::New || self.status == OrderStatus::PartiallyFilled
+    }
+
+    fn marketable(&self, last_price: f64) -> bool {
+        match (&self.order_type, &self.side) {
+            (OrderType::Market, _) => true,
+            (_, OrderSide::Buy) => self.price >= last_price,
+            (_, OrderSide::Sell) => self.price <= last_price,
+        }
+    }
+
+    fn status_str(&self) -> String {
+        serde_json::to_value(&self.status)
+            .ok()
+            .and_then(|v| v.as_str().map(|s| s.to_string()))
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Position {
+    /// Signed quantity, contracts for COIN-M
+    pub qty: f64,
+    pub entry_price: f64,
+}
+
+impl Position {
+    /// Applies a signed fill and returns the signed quantity it closed
+    fn apply(&mut self, qty: f64, price: f64) -> f64 {
+        if self.qty == 0.0 || self.qty.signum() == qty.signum() {
+            let total = self.qty + qty;
+            self.entry_price =
+                (self.entry_price * self.qty.abs() + price * qty.abs()) / total.abs();
+            self.qty = total;
+            return 0.0;
+        }
+        let closed = if qty.abs() <= self.qty.abs() {
+            -qty
+        } else {
+            self.qty
+        };
+        self.qty += qty;
+        if self.qty == 0.0 {
+            self.entry_price = 0.0;
+        } else if self.qty.signum() == qty.signum() {
+            // flipped, the remainder opens at the fill price
+            self.entry_price = price;
+        }
+        closed
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PaperState {
+    /// `"{wallet}:{asset}"` -> balance
+    balances: HashMap<String, f64>,
+    /// `"{wallet}:{symbol}"` -> position
+    positions: HashMap<String, Position>,
+    orders: HashMap<u64, PaperOrder>,
+    /// Absent in states persisted before transfers were recorded
+    #[serde(default)]
+    transfers: Vec<TransferRecord>,
+    next_id: u64,
+}
+
+/// Balances, positions and orders of the virtual account
+pub struct PaperExchange {
+    state: Mutex<PaperState>,
+    /// Redis key of the persisted account, one per user
+    state_key: String,
+    restored: OnceCell<()>,
+    spot_fee: f64,
+    futures_fee: f64,
+    delivery_fee: f64,
+}
+
+/// Redis key of the paper account of `user_id`
+pub fn state_key(user_id: i64) -> String {
+    format!("{}_{}", user_id, redis_key::PAPER_STATE_KEY)
+}
+
+fn key(wallet: Wallet, name: &str) -> String {
+    format!("{}:{}", wallet.as_str(), name)
+}
+
+fn reject(code: i32, msg: &str) -> Error {
+    Error::BinanceError {
+        response: BinanceContentError::new(code, msg.to_string()),
+    }
+}
+
+/// Splits a spot symbol into base and quote asset
+pub fn spot_assets(symbol: &str) -> Result<(String, String)> {
+    SPOT_QUOTES
+        .iter()
+        .find(|quote| symbol.len() > quote.len() && symbol.ends_with(*quote))
+        .map(|quote| {
+            (
+                symbol[..symbol.len() - quote.len()].to_string(),
+                quote.to_string(),
+            )
+        })
+        .ok_or_else(|| Error::UnknownSymbol(symbol.to_string()))
+}
+
+/// Margin asset of a USDⓈ-M symbol
+pub fn futures_margin_asset(symbol: &str) -> &'static str {
+    if symbol.ends_with("USDC") {
+        "USDC"
+    } else {
+        "USDT"
+    }
+}
+
+/// Margin coin of a COIN-M symbol, `ETHUSD_240628` -> `ETH`
+pub fn delivery_margin_asset(symbol: &str) -> Result<String> {
+    symbol
+        .split_once("USD_")
+        .map(|(coin, _)| coin.to_string())
+        .ok_or_else(|| Error::UnknownSymbol(symbol.to_string()))
+}
+
+/// COIN-M contract face value in USD
+pub fn contract_size(symbol: &str) -> f64 {
+    if symbol.starts_with("BTC") {
+        100.0
+    } else {
+        10.0
+    }
+}
+
+impl PaperExchange {
+    /// Account of `user_id` starting with the configured balances
+    pub fn new(user_id: i64, config: &PaperConfig) -> Self {
+        let mut state = PaperState {
+            next_id: get_timestamp().unwrap_or_default(),
+            ..PaperState::default()
+        };
+        for (wallet, balances) in [
+            (Wallet::Spot, &config.spot),
+            (Wallet::Futures, &config.futures),
+            (Wallet::Delivery, &config.delivery),
+        ] {
+            for (asset, amount) in balances {
+                state.balances.insert(key(wallet, asset), *amount);
+            }
+        }
+        PaperExchange {
+            state: Mutex::new(state),
+            state_key: state_key(user_id),
+            restored: OnceCell::new(),
+            spot_fee: config.spot_fee,
+            futures_fee: config.futures_fee,
+            delivery_fee: config.delivery_fee,
+        }
+    }
+
+    pub fn balance(&self, wallet: Wallet, asset: &str) -> f64 {
+        let state = self.state.lock().unwrap();
+        state
+            .balances
+            .get(&key(wallet, asset))
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    pub fn position(&self, wallet: Wallet, symbol: &str) -> Position {
+        let state = self.state.lock().unwrap();
+        state
+            .positions
+            .get(&key(wallet, symbol))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn order(&self, order_id: u64) -> Option<PaperOrder> {
+        self.state.lock().unwrap().orders.get(&order_id).cloned()
+    }
+
+    /// The order addressed by `order_id` or the latest one with `client_order_id`
+    pub fn order_id_of(
+        &self,
+        wallet: Wallet,
+        order_id: Option<u64>,
+        client_order_id: Option<&str>,
+    ) -> Result<Option<u64>> {
+        if order_id.is_some() {
+            return Ok(order_id);
+        }
+        let client_order_id = client_order_id.ok_or_else(|| {
+            reject(
+                -1102,
+                "Param 'origClientOrderId' or 'orderId' must be sent, but both were empty/null!",
+            )
+        })?;
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .orders
+            .values()
+            .filter(|o| o.wallet == wallet && o.client_order_id == client_order_id)
+            .map(|o| o.order_id)
+            .max())
+    }
+
+    /// Places an order and matches it against `last_price`
+    #[allow(clippy::too_many_arguments)]
+    pub fn submit(
+        &self,
+        wallet: Wallet,
+        symbol: &str,
+        side: OrderSide,
+        order_type: OrderType,
+        time_in_force: Option<TimeInForce>,
+        quantity: Option<f64>,
+        price: Option<f64>,
+        client_order_id: Option<String>,
+        last_price: f64,
+    ) -> Result<PaperOrder> {
+        let orig_qty = quantity
+            .filter(|q| *q > 0.0)
+            .ok_or_else(|| reject(-1102, "Mandatory parameter 'quantity' was not sent."))?;
+        let (price, time_in_force) = match order_type {
+            OrderType::Market => (0.0, TimeInForce::IOC),
+            OrderType::Limit => (
+                price.ok_or_else(|| reject(-1102, "Mandatory parameter 'price' was not sent."))?,
+                time_in_force.unwrap_or(TimeInForce::GTC),
+            ),
+            _ => return Err(reject(-1116, "Invalid orderType.")),
+        };
+
+        let mut state = self.state.lock().unwrap();
+        // client order ids are unique among open orders
+        if client_order_id.as_ref().is_some_and(|id| {
+            state
+                .orders
+                .values()
+                .any(|o| o.wallet == wallet && o.is_open() && &o.client_order_id == id)
+        }) {
+            return Err(reject(-2010, "Duplicate order sent."));
+        }
+        state.next_id += 1;
+        let order_id = state.next_id;
+        let now = get_timestamp().unwrap_or_default();
+        let mut order = PaperOrder {
+            wallet,
+            order_id,
+            client_order_id: client_order_id.unwrap_or_else(|| format!("paper_{order_id}")),
+            symbol: symbol.to_string(),
+            side,
+            order_type,
+            time_in_force,
+            price,
+            orig_qty,
+            executed_qty: 0.0,
+            avg_price: 0.0,
+            commission: 0.0,
+            commission_asset: String::new(),
+            status: OrderStatus::New,
+            time: now,
+            update_time: now,
+        };
+        // an order the account can't pay for is rejected up front
+        self.check_funds(
+            &state,
+            &order,
+            if order.price > 0.0 {
+                order.price
+            } else {
+                last_price
+            },
+        )?;
+        self.match_order(&mut state, &mut order, last_price);
+        state.orders.insert(order_id, order.clone());
+        Ok(order)
+    }
+
+    /// Re-matches a resting order against `last_price` and returns it
+    pub fn refresh(
+        &self,
+        wallet: Wallet,
+        order_id: u64,
+        last_price: Option<f64>,
+    ) -> Result<PaperOrder> {
+        let mut state = self.state.lock().unwrap();
+        let mut order = state
+            .orders
+            .get(&order_id)
+            .filter(|o| o.wallet == wallet)
+            .cloned()
+            .ok_or_else(|| reject(-2013, "Order does not exist."))?;
+        if let Some(last_price) = last_price.filter(|_| order.is_open()) {
+            match self.check_funds(&state, &order, order.price) {
+                Ok(_) => self.match_order(&mut state, &mut order, last_price),
+                Err(_) => {
+                    order.status = OrderStatus::Expired;
+                    order.update_time = get_timestamp().unwrap_or_default();
+                }
+            }
+            state.orders.insert(order_id, order.clone());
+        }
+        Ok(order)
+    }
+
+    /// Balances of a wallet, sorted by asset
+    pub fn balances(&self, wallet: Wallet) -> Vec<(String, f64)> {
+        let state = self.state.lock().unwrap();
+        let prefix = key(wallet, "");
+        let mut balances: Vec<(String, f64)> = state
+            .balances
+            .iter()
+            .filter_map(|(k, v)| k.strip_prefix(&prefix).map(|asset| (asset.to_string(), *v)))
+            .collect();
+        balances.sort_by(|a, b| a.0.cmp(&b.0));
+        balances
+    }
+
+    /// Open positions of a wallet whose symbol matches `filter`, sorted by symbol
+    pub fn positions(
+        &self,
+        wallet: Wallet,
+        filter: impl Fn(&str) -> bool,
+    ) -> Vec<(String, Position)> {
+        let state = self.state.lock().unwrap();
+        let prefix = key(wallet, "");
+        let mut positions: Vec<(String, Position)> = state
+            .positions
+            .iter()
+            .filter_map(|(k, p)| {
+                k.strip_prefix(&prefix)
+                    .map(|symbol| (symbol.to_string(), *p))
+            })
+            .filter(|(symbol, p)| p.qty != 0.0 && filter(symbol))
+            .collect();
+        positions.sort_by(|a, b| a.0.cmp(&b.0));
+        positions
+    }
+
+    /// Cancels a resting order
+    pub fn cancel(&self, wallet: Wallet, order_id: u64) -> Result<PaperOrder> {
+        let mut state = self.state.lock().unwrap();
+        let order = state
+            .orders
+            .get_mut(&order_id)
+            .filter(|o| o.wallet == wallet && o.is_open())
+            .ok_or_else(|| reject(-2011, "Unknown order sent."))?;
+        order.status = OrderStatus::Canceled;
+        order.update_time = get_timestamp().unwrap_or_default();
+        Ok(order.clone())
+    }
+
+    /// Resting orders of `symbol`, oldest first
+    pub fn open_orders_of(&self, wallet: Wallet, symbol: &str) -> Vec<PaperOrder> {
+        let state = self.state.lock().unwrap();
+        let mut orders: Vec<PaperOrder> = state
+            .orders
+            .values()
+            .filter(|o| o.wallet == wallet && o.symbol == symbol && o.is_open())
+            .cloned()
+            .collect();
+        orders.sort_by_key(|o| o.order_id);
+        orders
+    }
+
+    /// Cancels every resting order of `symbol`
+    pub fn cancel_all(&self, wallet: Wallet, symbol: &str) -> Vec<PaperOrder> {
+        self.open_orders_of(wallet, symbol)
+            .into_iter()
+            .filter_map(|o| self.cancel(wallet, o.order_id).ok())
+            .collect()
+    }
+
+    /// Moves `amount` of `asset` between the spot and futures wallets
+    pub fn transfer(
+        &self,
+        asset: &str,
+        amount: f64,
+        transfer_type: UniversalTransferType,
+    ) -> Result<u64> {
+        let (from, to) = match &transfer_type {
+            UniversalTransferType::MainUmfuture => (Wallet::Spot, Wallet::Futures),
+            UniversalTransferType::MainCmfuture => (Wallet::Spot, Wallet::Delivery),
+            UniversalTransferType::UmfutureMain => (Wallet::Futures, Wallet::Spot),
+            UniversalTransferType::CmfutureMain => (Wallet::Delivery, Wallet::Spot),
+            other => {
+                return Err(Error::Msg(format!(
+                    "paper trading does not support {other:?} transfers"
+                )))
+            }
+        };
+        let mut state = self.state.lock().unwrap();
+        let available = state
+            .balances
+            .get(&key(from, asset))
+            .copied()
+            .unwrap_or(0.0);
+        if amount <= 0.0 || available < amount {
+            return Err(reject(-5013, "Asset transfer failed: insufficient balance"));
+        }
+        *state.balances.entry(key(from, asset)).or_default() -= amount;
+        *state.balances.entry(key(to, asset)).or_default() += amount;
+        state.next_id += 1;
+        let tran_id = state.next_id;
+        state.transfers.push(TransferRecord {
+            asset: asset.to_string(),
+            amount,
+            transfer_type,
+            status: "CONFIRMED".to_string(),
+            tran_id,
+            timestamp: get_timestamp().unwrap_or_default(),
+        });
+        Ok(tran_id)
+    }
+
+    /// Transfers of `transfer_type` since `start_time`, newest first
+    pub fn transfers(
+        &self,
+        transfer_type: &UniversalTransferType,
+        start_time: Option<u64>,
+    ) -> Vec<TransferRecord> {
+        let state = self.state.lock().unwrap();
+        state
+            .transfers
+            .iter()
+            .rev()
+            .filter(|t| {
+                &t.transfer_type == transfer_type && t.timestamp >= start_time.unwrap_or_default()
+            })
+            .cloned()
+            .collect()
+    }
+
+    fn check_funds(&self, state: &PaperState, order: &PaperOrder, price: f64) -> Result<()> {
+        let qty = order.orig_qty - order.executed_qty;
+        let (asset, needed) = match order.wallet {
+            Wallet::Spot => {
+                let (base, quote) = spot_assets(&order.symbol)?;
+                match order.side {
+                    OrderSide::Buy => (quote, qty * price),
+                    OrderSide::Sell => (base, qty),
+                }
+            }
+            // no leverage model, only the fee has to be covered
+            Wallet::Futures => (
+                futures_margin_asset(&order.symbol).to_string(),
+                qty * price * self.futures_fee,
+            ),
+            Wallet::Delivery => (
+                delivery_margin_asset(&order.symbol)?,
+                qty * contract_size(&order.symbol) / price * self.delivery_fee,
+            ),
+        };
+        let available = state
+            .balances
+            .get(&key(order.wallet, &asset))
+            .copied()
+            .unwrap_or(0.0);
+        if available < needed {
+            let msg = match order.wallet {
+                Wallet::Spot => "Account has insufficient balance for requested action.",
+                _ => "Margin is insufficient.",
+            };
+            return Err(reject(
+                if order.wallet == Wallet::Spot {
+                    -2010
+                } else {
+                    -2019
+                },
+                msg,
+            ));
+        }
+        Ok(())
+    }
+
+    fn match_order(&self, state: &mut PaperState, order: &mut PaperOrder, last_price: f64) {
+        if order.marketable(last_price) {
+            let qty = order.orig_qty - order.executed_qty;
+            self.settle(state, order, qty, last_price);
+            order.avg_price = (order.avg_price * order.executed_qty + last_price * qty)
+                / (order.executed_qty + qty);
+            order.executed_qty += qty;
+            order.status = OrderStatus::Filled;
+            order.update_time = get_timestamp().unwrap_or_default();
+        } else if order.time_in_force != TimeInForce::GTC {
+            order.status = OrderStatus::Expired;
+        }
+    }
+
+    /// Books a fill of `qty` at `price` on the wallet balances
+    fn settle(&self, state: &mut PaperState, order: &mut PaperOrder, qty: f64, price: f64) {
+        let sign = match order.side {
+            OrderSide::Buy => 1.0,
+            OrderSide::Sell => -1.0,
+        };
+        match order.wallet {
+            Wallet::Spot => {
+                // the commission is taken from the asset received
+                let Ok((base, quote)) = spot_assets(&order.symbol) else {
+                    return;
+                };
+                let (paid, paid_amt, received, received_amt) = match order.side {
+                    OrderSide::Buy => (quote, qty * price, base, qty),
+                    OrderSide::Sell => (base, qty, quote, qty * price),
+                };
+                let commission = received_amt * self.spot_fee;
+                *state.balances.entry(key(Wallet::Spot, &paid)).or_default() -= paid_amt;
+                *state
+                    .balances
+                    .entry(key(Wallet::Spot, &received))
+                    .or_default() += received_amt - commission;
+                order.commission += commission;
+                order.commission_asset = received;
+            }
+            Wallet::Futures => {
+                let asset = futures_margin_asset(&order.symbol).to_string();
+                let position = state
+                    .positions
+                    .entry(key(Wallet::Futures, &order.symbol))
+                    .or_default();
+                let entry_price = position.entry_price;
+                let closed = position.apply(sign * qty, price);
+                let pnl = closed * (price - entry_price);
+                let commission = qty * price * self.futures_fee;
+                *state
+                    .balances
+                    .entry(key(Wallet::Futures, &asset))
+                    .or_default() += pnl - commission;
+                order.commission += commission;
+                order.commission_asset = asset;
+            }
+            Wallet::Delivery => {
+                let Ok(asset) = delivery_margin_asset(&order.symbol) else {
+                    return;
+                };
+                let size = contract_size(&order.symbol);
+                let position = state
+                    .positions
+                    .entry(key(Wallet::Delivery, &order.symbol))
+                    .or_default();
+                let entry_price = position.entry_price;
+                let closed = position.apply(sign * qty, price);
+                let pnl = if closed == 0.0 {
+                    0.0
+                } else {
+                    closed * size * (1.0 / entry_price - 1.0 / price)
+                };
+                let commission = qty * size / price * self.delivery_fee;
+                *state
+                    .balances
+                    .entry(key(Wallet::Delivery, &asset))
+                    .or_default() += pnl - commission;
+                order.commission += commission;
+                order.commission_asset = asset;
+            }
+        }
+    }
+
+    /// Loads the persisted account once, the configured balances are kept on first run
+    async fn restore(&self) {
+        self.restored
+            .get_or_init(|| async {
+                let saved: Option<String> = match db::get_db() {
+                    Ok(db) => match db.redis().await {
+                        Ok(mut redis) => redis.get(&self.state_key).await.unwrap_or(None),
+                        Err(_) => None,
+                    },
+                    Err(_) => None,
+                };
+                if let Some(state) = saved.and_then(|s| serde_json::from_str::<PaperState>(&s).ok())
+                {
+                    *self.state.lock().unwrap() = state;
+                }
+            })
+            .await;
+    }
+
+    async fn persist(&self) -> Result<()> {
+        let saved = serde_json::to_string(&*self.state.lock().unwrap())?;
+        let mut redis = db::get_db()
+            .map_err(|e| Error::Msg(e.to_string()))?
+            .redis()
+            .await
+            .map_err(|e| Error::Msg(e.to_string()))?;
+        redis
+            .set(&self.state_key, saved)
+            .await
+            .map_err(|e| Error::Msg(format!("persist paper state: {e}")))
+    }
+
+    async fn last_price(wallet: Wallet, symbol: &str) -> Result<f64> {
+        let info = get_binance_price(wallet.as_str().to_string(), symbol.to_string())
+            .await
+            .map_err(|e| {
+                Error::Msg(format!(
+                    "no {} price for {}: {}",
+                    wallet.as_str(),
+                    symbol,
+                    e
+                ))
+            })?;
+        info.ticker
+            .current_close
+            .parse::<f64>()
+            .map_err(|e| Error::Msg(e.to_string()))
+    }
+
+    async fn place(&self, wallet: Wallet, order: FuturesOrderRequest) -> Result<PaperOrder> {
+        self.restore().await;
+        let last_price = Self::last_price(wallet, &order.symbol).await?;
+        let placed = self.submit(
+            wallet,
+            &order.symbol,
+            order.side,
+            order.order_type,
+            order.time_in_force,
+            order.quantity,
+            order.price,
+            order.new_client_order_id,
+            last_price,
+        )?;
+        self.persist().await?;
+        Ok(placed)
+    }
+
+    async fn query(
+        &self,
+        wallet: Wallet,
+        symbol: &str,
+        order_id: Option<u64>,
+        client_order_id: Option<String>,
+    ) -> Result<PaperOrder> {
+        self.restore().await;
+        let order_id = self
+            .order_id_of(wallet, order_id, client_order_id.as_deref())?
+            .ok_or_else(|| reject(-2013, "Order does not exist."))?;
+        let last_price = Self::last_price(wallet, symbol).await.ok();
+        let order = self.refresh(wallet, order_id, last_price)?;
+        self.persist().await?;
+        Ok(order)
+    }
+
+    async fn cancel_one(
+        &self,
+        wallet: Wallet,
+        order_id: Option<u64>,
+        client_order_id: Option<String>,
+    ) -> Result<PaperOrder> {
+        self.restore().await;
+        let order_id = self
+            .order_id_of(wallet, order_id, client_order_id.as_deref())?
+            .ok_or_else(|| reject(-2011, "Unknown order sent."))?;
+        let order = self.cancel(wallet, order_id)?;
+        self.persist().await?;
+        Ok(order)
+    }
+
+    async fn cancel_symbol(&self, wallet: Wallet, symbol: &str) -> Result<Vec<PaperOrder>> {
+        self.restore().await;
+        let orders = self.cancel_all(wallet, symbol);
+        self.persist().await?;
+        Ok(orders)
+    }
+
+    pub async fn place_order(&self, order: OrderRequest) -> Result<Transaction> {
+        let request = FuturesOrderRequest {
+            symbol: order.symbol,
+            side: order.side,
+            order_type: order.order_type,
+            quantity: order.quantity,
+            price: order.price,
+            time_in_force: order.time_in_force,
+            new_client_order_id: order.new_client_order_id,
+            recv_window: order.recv_window,
+        };
+        let placed = self.place(Wallet::Spot, request).await?;
+        Ok(spot_transaction(&placed))
+    }
+
+    pub async fn order_status(&self, osr: OrderStatusRequest) -> Result<Order> {
+        let order = self
+            .query(
+                Wallet::Spot,
+                &osr.symbol,
+                osr.order_id,
+                osr.orig_client_order_id,
+            )
+            .await?;
+        Ok(spot_order(&order))
+    }
+
+    pub async fn cancel_order(&self, osr: OrderStatusRequest) -> Result<OrderCanceled> {
+        let order = self
+            .cancel_one(Wallet::Spot, osr.order_id, osr.orig_client_order_id)
+            .await?;
+        Ok(spot_canceled(&order))
+    }
+
+    pub async fn cancel_all_open_orders(&self, symbol: &str) -> Result<Vec<OrderCanceled>> {
+        let orders = self.cancel_symbol(Wallet::Spot, symbol).await?;
+        Ok(orders.iter().map(spot_canceled).collect())
+    }
+
+    pub async fn open_orders(&self, symbol: &str) -> Result<Vec<Order>> {
+        self.restore().await;
+        Ok(self
+            .open_orders_of(Wallet::Spot, symbol)
+            .iter()
+            .map(spot_order)
+            .collect())
+    }
+
+    pub async fn futures_place_order(
+        &self,
+        order: FuturesOrderRequest,
+    ) -> Result<FuturesTransaction> {
+        let placed = self.place(Wallet::Futures, order).await?;
+        Ok(futures_transaction(&placed))
+    }
+
+    pub async fn futures_order_status(
+        &self,
+        order: FuturesGetOrderRequest,
+    ) -> Result<FuturesTransaction> {
+        let order_id = order.order_id.and_then(|id| id.parse().ok());
+        let order = self
+            .query(
+                Wallet::Futures,
+                &order.symbol,
+                order_id,
+                order.orig_client_order_id,
+            )
+            .await?;
+        Ok(futures_transaction(&order))
+    }
+
+    pub async fn futures_cancel_order(
+        &self,
+        order: FuturesGetOrderRequest,
+    ) -> Result<FuturesTransaction> {
+        let order_id = order.order_id.and_then(|id| id.parse().ok());
+        let order = self
+            .cancel_one(Wallet::Futures, order_id, order.orig_client_order_id)
+            .await?;
+        Ok(futures_transaction(&order))
+    }
+
+    pub async fn futures_cancel_all_open_orders(&self, symbol: &str) -> Result<Success> {
+        self.cancel_symbol(Wallet::Futures, symbol).await?;
+        Ok(Success {})
+    }
+
+    pub async fn futures_open_orders(&self, symbol: &str) -> Result<Vec<FuturesTransaction>> {
+        self.restore().await;
+        Ok(self
+            .open_orders_of(Wallet::Futures, symbol)
+            .iter()
+            .map(futures_transaction)
+            .collect())
+    }
+
+    pub async fn delivery_place_order(
+        &self,
+        order: FuturesOrderRequest,
+    ) -> Result<FuturesTransaction> {
+        let placed = self.place(Wallet::Delivery, order).await?;
+        Ok(futures_transaction(&placed))
+    }
+
+    pub async fn delivery_order_status(
+        &self,
+        order: FuturesGetOrderRequest,
+    ) -> Result<FuturesTransaction> {
+        let order_id = order.order_id.and_then(|id| id.parse().ok());
+        let order = self
+            .query(
+                Wallet::Delivery,
+                &order.symbol,
+                order_id,
+                order.orig_client_order_id,
+            )
+            .await?;
+        Ok(futures_transaction(&order))
+    }
+
+    pub async fn delivery_cancel_order(
+        &self,
+        order: FuturesGetOrderRequest,
+    ) -> Result<FuturesTransaction> {
+        let order_id = order.order_id.and_then(|id| id.parse().ok());
+        let order = self
+            .cancel_one(Wallet::Delivery, order_id, order.orig_client_order_id)
+            .await?;
+        Ok(futures_transaction(&order))
+    }
+
+    pub async fn delivery_cancel_all_open_orders(&self, symbol: &str) -> Result<Success> {
+        self.cancel_symbol(Wallet::Delivery, symbol).await?;
+        Ok(Success {})
+    }
+
+    pub async fn delivery_open_orders(&self, symbol: &str) -> Result<Vec<FuturesTransaction>> {
+        self.restore().await;
+        Ok(self
+            .open_orders_of(Wallet::Delivery, symbol)
+            .iter()
+            .map(futures_transaction)
+            .collect())
+    }
+
+    pub async fn account_information(&self) -> Result<AccountInformation> {
+        self.restore().await;
+        let balances = self
+            .balances(Wallet::Spot)
+            .into_iter()
+            .map(|(asset, free)| Balance {
+                asset,
+                free,
+                locked: 0.0,
+            })
+            .collect();
+        Ok(AccountInformation {
+            can_trade: true,
+            can_withdraw: true,
+            can_deposit: true,
+            update_time: get_timestamp().unwrap_or_default(),
+            balances,
+        })
+    }
+
+    pub async fn futures_account(&self) -> Result<FuturesAccount> {
+        self.restore().await;
+        Ok(futures_account(self.balances(Wallet::Futures)))
+    }
+
+    pub async fn futures_position_risk(&self, symbol: &str) -> Result<Vec<PositionRisk>> {
+        self.restore().await;
+        let positions = self.positions(Wallet::Futures, |s| s == symbol);
+        Ok(positions.iter().map(|(s, p)| position_risk(s, p)).collect())
+    }
+
+    pub async fn delivery_account(&self) -> Result<FuturesAccount> {
+        self.restore().await;
+        Ok(futures_account(self.balances(Wallet::Delivery)))
+    }
+
+    pub async fn delivery_position_risk(&self, pair: &str) -> Result<Vec<PositionRisk>> {
+        self.restore().await;
+        let positions = self.positions(Wallet::Delivery, |s| s.split('_').next() == Some(pair));
+        Ok(positions.iter().map(|(s, p)| position_risk(s, p)).collect())
+    }
+
+    pub async fn universal_transfer(
+        &self,
+        asset: String,
+        amount: f64,
+        transfer_type: UniversalTransferType,
+    ) -> Result<TransactionId> {
+        self.restore().await;
+        let tran_id = self.transfer(&asset, amount, transfer_type)?;
+        self.persist().await?;
+        Ok(TransactionId { tran_id })
+    }
+
+    pub async fn transfer_history(
+        &self,
+        transfer_type: UniversalTransferType,
+        start_time: Option<u64>,
+    ) -> Result<TransferHistory> {
+        self.restore().await;
+        let rows = self.transfers(&transfer_type, start_time);
+        Ok(TransferHistory {
+            total: rows.len() as u64,
+            rows,
+        })
+    }
+}
+
+fn spot_transaction(order: &PaperOrder) -> Transaction {
+    let fills = if order.executed_qty > 0.0 {
+        vec![Fill {
+            price: order.avg_price,
+            qty: order.executed_qty,
+            commission: order.commission,
+            commission_asset: order.commission_asset.clone(),
+        }]
+    } else {
+        vec![]
+    };
+    Transaction {
+        symbol: order.symbol.clone(),
+        order_id: order.order_id,
+        client_order_id: order.client_order_id.clone(),
+        transact_time: order.time,
+        price: order.price,
+        orig_qty: order.orig_qty,
+        executed_qty: order.executed_qty,
+        cummulative_quote_qty: order.executed_qty * order.avg_price,
+        status: order.status.clone(),
+        time_in_force: order.time_in_force.clone(),
+        order_type: order.order_type.clone(),
+        side: order.side.clone(),
+        fills,
+    }
+}
+
+fn spot_order(order: &PaperOrder) -> Order {
+    Order {
+        symbol: order.symbol.clone(),
+        order_id: order.order_id,
+        order_list_id: -1,
+        client_order_id: order.client_order_id.clone(),
+        price: order.price,
+        orig_qty: order.orig_qty,
+        executed_qty: order.executed_qty,
+        cummulative_quote_qty: order.executed_qty * order.avg_price,
+        status: order.status.clone(),
+        time_in_force: order.time_in_force.clone(),
+        order_type: order.order_type.clone(),
+        side: order.side.clone(),
+        stop_price: 0.0,
+        iceberg_qty: 0.0,
+        time: order.time,
+        update_time: order.update_time,
+        is_working: order.is_open(),
+        orig_quote_order_qty: 0.0,
+    }
+}
+
+/// No margin is locked by the paper engine, the whole balance is available
+fn futures_account(balances: Vec<(String, f64)>) -> FuturesAccount {
+    FuturesAccount {
+        can_trade: true,
+        assets: balances
+            .into_iter()
+            .map(|(asset, balance)| FuturesAsset {
+                asset,
+                wallet_balance: balance,
+                margin_balance: balance,
+                available_balance: balance,
+                max_withdraw_amount: balance,
+            })
+            .collect(),
+    }
+}
+
+fn position_risk(symbol: &str, position: &Position) -> PositionRisk {
+    PositionRisk {
+        symbol: symbol.to_string(),
+        position_amt: position.qty,
+        entry_price: position.entry_price,
+        mark_price: 0.0,
+        un_realized_profit: 0.0,
+        liquidation_price: 0.0,
+        leverage: PAPER_LEVERAGE,
+        margin_type: "cross".to_string(),
+        position_side: "BOTH".to_string(),
+    }
+}
+
+fn spot_canceled(order: &PaperOrder) -> OrderCanceled {
+    OrderCanceled {
+        symbol: order.symbol.clone(),
+        orig_client_order_id: order.client_order_id.clone(),
+        order_id: order.order_id,
+        order_list_id: -1,
+        client_order_id: order.client_order_id.clone(),
+        price: order.price,
+        orig_qty: order.orig_qty,
+        executed_qty: order.executed_qty,
+        cummulative_quote_qty: order.executed_qty * order.avg_price,
+        status: order.status.clone(),
+        time_in_force: order.time_in_force.clone(),
+        order_type: order.order_type.clone(),
+        side: order.side.clone(),
+    }
+}
+
+fn futures_transaction(order: &PaperOrder) -> FuturesTransaction {
+    let cum_base = match order.wallet {
+        Wallet::Delivery if order.avg_price > 0.0 => {
+            order.executed_qty * contract_size(&order.symbol) / order.avg_price
+        }
+        _ => 0.0,
+    };
+    FuturesTransaction {
+        client_order_id: order.client_order_id.clone(),
+        cum_qty: Some(order.executed_qty.to_string()),
+        cum_quote: Some((order.executed_qty * order.avg_price).to_string()),
+        cum_base,
+        executed_qty: order.executed_qty,
+        order_id: order.order_id,
+        avg_price: order.avg_price,
+        orig_qty: order.orig_qty,
+        price: order.price,
+        reduce_only: false,
+        side: order.side.clone(),
+        position_side: "BOTH".to_string(),
+        status: order.status_str(),
+        stop_price: 0.0,
+        close_position: false,
+        symbol: order.symbol.clone(),
+        time_in_force: order.time_in_force.clone(),
+        order_type: order.order_type.clone(),
+        orig_type: serde_json::to_value(&order.order_type)
+            .ok()
+            .and_then(|v| v.as_str().map(|s| s.to_string()))
+            .unwrap_or_default(),
+        activate_price: None,
+        price_rate: None,
+        update_time: order.update_time,
+        working_type: "CONTRACT_PRICE".to_string(),
+        price_protect: false,
+        price_match: None,
+        self_trade_prevention_mode: None,
+        good_till_date: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exchange() -> PaperExchange {
+        PaperExchange::new(
+            1,
+            &PaperConfig {
+                enabled: true,
+                spot: HashMap::from([("USDT".to_string(), 10_000.0)]),
+                futures: HashMap::from([("USDT".to_string(), 1_000.0)]),
+                delivery: HashMap::new(),
+                spot_fee: 0.001,
+                futures_fee: 0.0005,
+                delivery_fee: 0.0005,
+            },
+        )
+    }
+
+    #[test]
+    fn test_spot_and_delivery_cycle() {
+        let paper = exchange();
+        let fok = Some(TimeInForce::FOK);
+
+        let buy = paper
+            .submit(
+                Wallet::Spot,
+                "ETHUSDT",
+                OrderSide::Buy,
+                OrderType::Limit,
+                fok.clone(),
+                Some(1.0),
+                Some(3505.0),
+                None,
+                3500.0,
+            )
+            .unwrap();
+        assert_eq!(buy.status, OrderStatus::Filled);
+        assert_eq!(buy.avg_price, 3500.0);
+        assert_eq!(paper.balance(Wallet::Spot, "USDT"), 6_500.0);
+        assert_eq!(paper.balance(Wallet::Spot, "ETH"), 0.999);
+
+        paper
+            .transfer("ETH", 0.999, UniversalTransferType::MainCmfuture)
+            .unwrap();
+        assert!(paper
+            .transfer("ETH", 0.1, UniversalTransferType::MainCmfuture)
+            .is_err());
+        let transfers = paper.transfers(&UniversalTransferType::MainCmfuture, None);
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].amount, 0.999);
+        assert!(paper
+            .transfers(&UniversalTransferType::CmfutureMain, None)
+            .is_empty());
+
+        let sell = paper
+            .submit(
+                Wallet::Delivery,
+                "ETHUSD_240628",
+                OrderSide::Sell,
+                OrderType::Limit,
+                fok.clone(),
+                Some(369.0),
+                Some(3695.0),
+                None,
+                3700.0,
+            )
+            .unwrap();
+        assert_eq!(sell.status, OrderStatus::Filled);
+        assert_eq!(
+            paper.position(Wallet::Delivery, "ETHUSD_240628").qty,
+            -369.0
+        );
+
+        let buy_back = paper
+            .submit(
+                Wallet::Delivery,
+                "ETHUSD_240628",
+                OrderSide::Buy,
+                OrderType::Limit,
+                fok,
+                Some(369.0),
+                Some(3605.0),
+                None,
+                3600.0,
+            )
+            .unwrap();
+        assert_eq!(buy_back.status, OrderStatus::Filled);
+        assert_eq!(paper.position(Wallet::Delivery, "ETHUSD_240628").qty, 0.0);
+
+        // short 3690 USD at 3700, bought back at 3600, less fees on both sides
+        let pnl = 3690.0 * (1.0 / 3600.0 - 1.0 / 3700.0);
+        let fees = 3690.0 / 3700.0 * 0.0005 + 3690.0 / 3600.0 * 0.0005;
+        let coin = paper.balance(Wallet::Delivery, "ETH");
+        assert!((coin - (0.999 + pnl - fees)).abs() < 1e-12);
+        assert!((futures_transaction(&buy_back).cum_base - 3690.0 / 3600.0).abs() < 1e-12);
+        assert!(paper.positions(Wallet::Delivery, |_| true).is_empty());
+        assert_eq!(
+            paper.balances(Wallet::Spot),
+            vec![("ETH".to_string(), 0.0), ("USDT".to_string(), 6_500.0)]
+        );
+    }
+
+    #[test]
+    fn test_unfilled_orders() {
+        let paper = exchange();
+
+        let fok = paper
+            .submit(
+                Wallet::Spot,
+                "ETHUSDT",
+                OrderSide::Buy,
+                OrderType::Limit,
+                Some(TimeInForce::FOK),
+                Some(1.0),
+                Some(3400.0),
+                None,
+                3500.0,
+            )
+            .unwrap();
+        assert_eq!(fok.status, OrderStatus::Expired);
+        assert_eq!(paper.balance(Wallet::Spot, "USDT"), 10_000.0);
+
+        let gtc = paper
+            .submit(
+                Wallet::Spot,
+                "ETHUSDT",
+                OrderSide::Buy,
+                OrderType::Limit,
+                Some(TimeInForce::GTC),
+                Some(1.0),
+                Some(3400.0),
+                None,
+                3500.0,
+            )
+            .unwrap();
+        assert_eq!(gtc.status, OrderStatus::New);
+        let still_open = paper
+            .refresh(Wallet::Spot, gtc.order_id, Some(3450.0))
+            .unwrap();
+        assert_eq!(still_open.status, OrderStatus::New);
+        let filled = paper
+            .refresh(Wallet::Spot, gtc.order_id, Some(3390.0))
+            .unwrap();
+        assert_eq!(filled.status, OrderStatus::Filled);
+        assert_eq!(filled.avg_price, 3390.0);
+        assert!(paper.refresh(Wallet::Futures, gtc.order_id, None).is_err());
+        assert!(paper.cancel(Wallet::Spot, gtc.order_id).is_err());
+
+        let resting: Vec<u64> = [3300.0, 3200.0]
+            .into_iter()
+            .map(|price| {
+                paper
+                    .submit(
+                        Wallet::Spot,
+                        "ETHUSDT",
+                        OrderSide::Buy,
+                        OrderType::Limit,
+                        Some(TimeInForce::GTC),
+                        Some(1.0),
+                        Some(price),
+                        None,
+                        3500.0,
+                    )
+                    .unwrap()
+                    .order_id
+            })
+            .collect();
+        let canceled = paper.cancel(Wallet::Spot, resting[0]).unwrap();
+        assert_eq!(canceled.status, OrderStatus::Canceled);
+        let open: Vec<u64> = paper
+            .open_orders_of(Wallet::Spot, "ETHUSDT")
+            .iter()
+            .map(|o| o.order_id)
+            .collect();
+        assert_eq!(open, vec![resting[1]]);
+        assert_eq!(paper.cancel_all(Wallet::Spot, "ETHUSDT").len(), 1);
+        assert!(paper.open_orders_of(Wallet::Spot, "ETHUSDT").is_empty());
+
+        // a client order id addresses the order and can't be reused while it rests
+        let submit_client = |client_order_id: &str| {
+            paper.submit(
+                Wallet::Spot,
+                "ETHUSDT",
+                OrderSide::Buy,
+                OrderType::Limit,
+                Some(TimeInForce::GTC),
+                Some(1.0),
+                Some(3100.0),
+                Some(client_order_id.to_string()),
+                3500.0,
+            )
+        };
+        let client = submit_client("arb_7_1").unwrap();
+        assert_eq!(
+            paper
+                .order_id_of(Wallet::Spot, None, Some("arb_7_1"))
+                .unwrap(),
+            Some(client.order_id)
+        );
+        assert!(submit_client("arb_7_1").is_err());
+        assert_eq!(
+            paper
+                .order_id_of(Wallet::Futures, None, Some("arb_7_1"))
+                .unwrap(),
+            None
+        );
+        assert!(paper.order_id_of(Wallet::Spot, None, None).is_err());
+        paper.cancel(Wallet::Spot, client.order_id).unwrap();
+
+        match paper.submit(
+            Wallet::Spot,
+            "ETHUSDT",
+            OrderSide::Buy,
+            OrderType::Limit,
+            None,
+            Some(10.0),
+            Some(3500.0),
+            None,
+            3500.0,
+        ) {
+            Err(Error::BinanceError { response }) => assert_eq!(response.code, -2010),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_futures_pnl() {
+        let paper = exchange();
+        let fok = Some(TimeInForce::FOK);
+
+        paper
+            .submit(
+                Wallet::Futures,
+                "ETHUSDT",
+                OrderSide::Buy,
+                OrderType::Limit,
+                fok.clone(),
+                Some(2.0),
+                Some(3500.0),
+                None,
+                3500.0,
+            )
+            .unwrap();
+        paper
+            .submit(
+                Wallet::Futures,
+                "ETHUSDT",
+                OrderSide::Sell,
+                OrderType::Limit,
+                fok.clone(),
+                Some(3.0),
+                Some(3550.0),
+                None,
+                3600.0,
+            )
+            .unwrap();
+        // 2 closed with 200 profit, 1 short opened at 3600
+        let position = paper.position(Wallet::Futures, "ETHUSDT");
+        assert_eq!(position.qty, -1.0);
+        assert_eq!(position.entry_price, 3600.0);
+        let fees = (2.0 * 3500.0 + 3.0 * 3600.0) * 0.0005;
+        assert!((paper.balance(Wallet::Futures, "USDT") - (1_000.0 + 200.0 - fees)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_symbols() {
+        assert_eq!(
+            spot_assets("FDUSDUSDT").unwrap(),
+            ("FDUSD".to_string(), "USDT".to_string())
+        );
+        assert_eq!(
+            spot_assets("BTCFDUSD").unwrap(),
+            ("BTC".to_string(), "FDUSD".to_string())
+        );
+        assert_eq!(delivery_margin_asset("BTCUSD_PERP").unwrap(), "BTC");
+        assert_eq!(contract_size("BTCUSD_PERP"), 100.0);
+        assert_eq!(contract_size("ETHUSD_240628"), 10.0);
+    }
+}
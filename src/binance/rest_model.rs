@@ -4,12 +4,14 @@ pub struct FuturesTransaction {
     pub client_order_id: String,
     pub cum_qty: Option<String>,
     pub cum_quote: Option<String>,
-    #[serde(with = "string_or_float")]
+    /// Only returned by COIN-M, USDⓈ-M orders report `cum_quote` instead
+    #[serde(with = "string_or_float", default)]
     pub cum_base: f64,
     #[serde(with = "string_or_float")]
     pub executed_qty: f64,
     pub order_id: u64,
-    #[serde(with = "string_or_float")]
+    /// Not returned when canceling
+    #[serde(with = "string_or_float", default)]
     pub avg_price: f64,
     #[serde(with = "string_or_float")]
     pub orig_qty: f64,
@@ -37,6 +39,132 @@ pub struct FuturesTransaction {
     pub good_till_date: Option<u64>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UserDataStream {
+    pub listen_key: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Success {}
+
+/// Depth snapshot of `/api/v3/depth`, `/fapi/v1/depth` or `/dapi/v1/depth`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderBook {
+    pub last_update_id: u64,
+    pub bids: Vec<PriceLevel>,
+    pub asks: Vec<PriceLevel>,
+}
+
+/// `/api/v3/exchangeInfo`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExchangeInformation {
+    pub symbols: Vec<Symbol>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Symbol {
+    pub symbol: String,
+    pub status: String,
+    pub base_asset: String,
+    pub quote_asset: String,
+    pub filters: Vec<Filters>,
+}
+
+/// Symbol filters, only the ones orders are checked against are kept
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "filterType")]
+pub enum Filters {
+    #[serde(rename = "PRICE_FILTER")]
+    #[serde(rename_all = "camelCase")]
+    PriceFilter {
+        min_price: String,
+        max_price: String,
+        tick_size: String,
+    },
+    #[serde(rename = "LOT_SIZE")]
+    #[serde(rename_all = "camelCase")]
+    LotSize {
+        min_qty: String,
+        max_qty: String,
+        step_size: String,
+    },
+    /// Spot `minNotional`, USDⓈ-M `notional`
+    #[serde(rename = "MIN_NOTIONAL")]
+    #[serde(rename_all = "camelCase")]
+    MinNotional {
+        #[serde(alias = "notional")]
+        min_notional: String,
+    },
+    /// Spot replacement of `MIN_NOTIONAL`
+    #[serde(rename = "NOTIONAL")]
+    #[serde(rename_all = "camelCase")]
+    Notional { min_notional: String },
+    #[serde(other)]
+    Others,
+}
+
+/// `/fapi/v1/exchangeInfo` or `/dapi/v1/exchangeInfo`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FuturesExchangeInfo {
+    pub symbols: Vec<FuturesSymbol>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FuturesSymbol {
+    pub symbol: String,
+    pub pair: String,
+    /// PERPETUAL, CURRENT_QUARTER, NEXT_QUARTER, ...
+    pub contract_type: String,
+    /// Delivery time in milliseconds, far in the future for perpetuals
+    pub delivery_date: i64,
+    pub onboard_date: i64,
+    /// Contract value in USD, COIN-M only
+    #[serde(default)]
+    pub contract_size: i64,
+    #[serde(default)]
+    pub filters: Vec<Filters>,
+}
+
+/// `/fapi/v1/premiumIndex` of one symbol
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PremiumIndex {
+    pub symbol: String,
+    pub mark_price: String,
+    pub index_price: String,
+    pub estimated_settle_price: String,
+    /// Funding rate of the next settlement, as predicted now
+    pub last_funding_rate: String,
+    pub interest_rate: String,
+    pub next_funding_time: u64,
+    pub time: u64,
+}
+
+/// A settled funding of `/fapi/v1/fundingRate`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FundingRate {
+    pub symbol: String,
+    pub funding_rate: String,
+    pub funding_time: u64,
+    /// Empty for fundings before the field was introduced
+    #[serde(default)]
+    pub mark_price: String,
+}
+
+/// A `[price, qty]` pair of a depth snapshot or a depth update
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct PriceLevel {
+    pub price: String,
+    pub qty: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ServerTime {
@@ -107,6 +235,28 @@ pub struct UniversalTransfer {
     pub transfer_type: UniversalTransferType,
 }
 
+/// Universal transfer history, newest first
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TransferHistory {
+    pub total: u64,
+    /// Missing when there are no transfers
+    #[serde(default)]
+    pub rows: Vec<TransferRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferRecord {
+    pub asset: String,
+    #[serde(with = "string_or_float")]
+    pub amount: f64,
+    #[serde(rename = "type")]
+    pub transfer_type: UniversalTransferType,
+    pub status: String,
+    pub tran_id: u64,
+    pub timestamp: u64,
+}
+
 /// How long will an order stay alive
 #[derive(Eq, PartialEq, Debug, Serialize, Deserialize, Clone)]
 pub enum TimeInForce {
@@ -255,6 +405,97 @@ pub struct Order {
     pub orig_quote_order_qty: f64,
 }
 
+/// `/api/v3/account`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountInformation {
+    pub can_trade: bool,
+    pub can_withdraw: bool,
+    pub can_deposit: bool,
+    pub update_time: u64,
+    pub balances: Vec<Balance>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Balance {
+    pub asset: String,
+    #[serde(with = "string_or_float")]
+    pub free: f64,
+    #[serde(with = "string_or_float")]
+    pub locked: f64,
+}
+
+/// `/fapi/v2/account` or `/dapi/v1/account`, only the per asset balances are kept
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FuturesAccount {
+    pub can_trade: bool,
+    pub assets: Vec<FuturesAsset>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FuturesAsset {
+    pub asset: String,
+    #[serde(with = "string_or_float")]
+    pub wallet_balance: f64,
+    #[serde(with = "string_or_float")]
+    pub margin_balance: f64,
+    /// Margin left for new positions
+    #[serde(with = "string_or_float")]
+    pub available_balance: f64,
+    /// Amount that can be transferred out
+    #[serde(with = "string_or_float")]
+    pub max_withdraw_amount: f64,
+}
+
+/// `/fapi/v2/positionRisk` or `/dapi/v1/positionRisk`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PositionRisk {
+    pub symbol: String,
+    /// Signed, contracts for COIN-M
+    #[serde(with = "string_or_float")]
+    pub position_amt: f64,
+    #[serde(with = "string_or_float")]
+    pub entry_price: f64,
+    #[serde(with = "string_or_float")]
+    pub mark_price: f64,
+    #[serde(with = "string_or_float")]
+    pub un_realized_profit: f64,
+    #[serde(with = "string_or_float")]
+    pub liquidation_price: f64,
+    #[serde(with = "string_or_float")]
+    pub leverage: f64,
+    pub margin_type: String,
+    pub position_side: String,
+}
+
+/// Response of `DELETE /api/v3/order`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderCanceled {
+    pub symbol: String,
+    pub orig_client_order_id: String,
+    pub order_id: u64,
+    pub order_list_id: i32,
+    pub client_order_id: String,
+    #[serde(with = "string_or_float")]
+    pub price: f64,
+    #[serde(with = "string_or_float")]
+    pub orig_qty: f64,
+    #[serde(with = "string_or_float")]
+    pub executed_qty: f64,
+    #[serde(with = "string_or_float")]
+    pub cummulative_quote_qty: f64,
+    pub status: OrderStatus,
+    pub time_in_force: TimeInForce,
+    #[serde(rename = "type")]
+    pub order_type: OrderType,
+    pub side: OrderSide,
+}
+
 pub mod string_or_float {
     use std::fmt;
 
@@ -0,0 +1,1878 @@
+//! In-process Binance exchange simulator.
+//!
+//! Serves the REST endpoints used by [`MyApi`] (`/api/v3/order`, `/fapi/v1/order`,
+//! `/dapi/v1/order`, the open order and cancel-all endpoints, `/sapi/v1/asset/transfer`,
+//! `/api/v3/klines`, `/api/v3/time`, the listen key endpoints, `exchangeInfo`, `account` and
+//! `positionRisk`) plus a `!miniTicker@arr` and a user data websocket per market, so strategies
+//! can be driven end to end without touching the real exchange. Point a [`Config`] at it with
+//! [`MockExchange::config`].
+//!
+//! Limit orders fill against the last price set for the symbol unless the next order of the
+//! market has been scripted with an [`OrderScript`]. Balances are whatever
+//! [`MockExchange::set_balance`] set, positions are the net filled quantity of the symbol.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use futures::{SinkExt, StreamExt};
+use hex::encode as hex_encode;
+use ring::hmac;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::binance::api::MyApi;
+use crate::binance::config::Config;
+use crate::binance::rest_model::{KlineSummary, OrderSide, OrderStatus, OrderType, TimeInForce};
+use crate::binance::util::get_timestamp;
+
+pub static MOCK_API_KEY: &str = "mock_api_key";
+pub static MOCK_SECRET_KEY: &str = "mock_secret_key";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Market {
+    Spot,
+    Futures,
+    Delivery,
+}
+
+impl Market {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Market::Spot => "spot",
+            Market::Futures => "futures",
+            Market::Delivery => "delivery",
+        }
+    }
+
+    fn from_rest_path(path: &str) -> Option<Market> {
+        match path.split('/').nth(1) {
+            Some("api") => Some(Market::Spot),
+            Some("fapi") => Some(Market::Futures),
+            Some("dapi") => Some(Market::Delivery),
+            _ => None,
+        }
+    }
+
+    fn from_ws_path(path: &str) -> Option<Market> {
+        match path.split('/').nth(1) {
+            Some("spot") => Some(Market::Spot),
+            Some("futures") => Some(Market::Futures),
+            Some("delivery") => Some(Market::Delivery),
+            _ => None,
+        }
+    }
+}
+
+/// How the next order (or transfer) is handled
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderScript {
+    /// Fill the whole quantity at the limit price
+    Fill,
+    /// Fill the given ratio of the quantity, FOK orders expire instead
+    PartialFill(f64),
+    /// Do not fill at all
+    NoFill,
+    /// Reject with a binance error code
+    Reject { code: i32, msg: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct MockOrder {
+    pub market: Market,
+    pub order_id: u64,
+    pub client_order_id: String,
+    pub symbol: String,
+    pub side: OrderSide,
+    pub order_type: OrderType,
+    pub time_in_force: TimeInForce,
+    pub price: f64,
+    pub orig_qty: f64,
+    pub executed_qty: f64,
+    pub avg_price: f64,
+    pub status: OrderStatus,
+    pub time: u64,
+    pub update_time: u64,
+}
+
+impl MockOrder {
+    fn is_open(&self) -> bool {
+        self.status == OrderStatus::New || self.status == OrderStatus::PartiallyFilled
+    }
+
+    fn fill(&mut self, qty: f64, price: f64) {
+        let filled = self.executed_qty + qty;
+        self.avg_price = (self.avg_price * self.executed_qty + price * qty) / filled;
+        self.executed_qty = filled;
+        self.status = if self.executed_qty >= self.orig_qty {
+            OrderStatus::Filled
+        } else {
+            OrderStatus::PartiallyFilled
+        };
+        self.update_time = get_timestamp().unwrap_or_default();
+    }
+
+    fn marketable(&self, last_price: f64) -> bool {
+        match self.side {
+            OrderSide::Buy => self.price >= last_price,
+            OrderSide::Sell => self.price <= last_price,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MockTransfer {
+    pub tran_id: u64,
+    pub asset: String,
+    pub amount: f64,
+    pub transfer_type: String,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    prices: HashMap<(Market, String), f64>,
+    price_paths: HashMap<(Market, String), VecDeque<f64>>,
+    contract_sizes: HashMap<String, f64>,
+    order_scripts: HashMap<Market, VecDeque<OrderScript>>,
+    transfer_scripts: VecDeque<OrderScript>,
+    orders: HashMap<u64, MockOrder>,
+    transfers: Vec<MockTransfer>,
+    klines: HashMap<String, Vec<KlineSummary>>,
+    /// (tickSize, stepSize) of the symbols listed in exchangeInfo
+    symbols: HashMap<(Market, String), (String, String)>,
+    balances: HashMap<(Market, String), f64>,
+    listen_keys: HashSet<String>,
+    next_id: u64,
+    user_tx: Option<broadcast::Sender<(Market, String)>>,
+}
+
+impl MockState {
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// Updates the last price and fills resting orders that became marketable
+    fn set_price(&mut self, market: Market, symbol: &str, price: f64) {
+        self.prices.insert((market, symbol.to_string()), price);
+        let mut filled = vec![];
+        for order in self.orders.values_mut() {
+            if order.market == market
+                && order.symbol == symbol
+                && order.is_open()
+                && order.marketable(price)
+            {
+                let qty = order.orig_qty - order.executed_qty;
+                order.fill(qty, price);
+                filled.push(order.clone());
+            }
+        }
+        for order in filled {
+            self.emit_order(&order);
+        }
+    }
+
+    /// Pushes the order on the user data stream of its market
+    fn emit_order(&self, order: &MockOrder) {
+        if let Some(tx) = &self.user_tx {
+            let event = match order.market {
+                Market::Spot => execution_report_json(order),
+                _ => order_trade_update_json(order),
+            };
+            // no subscriber is not an error
+            let _ = tx.send((order.market, event.to_string()));
+        }
+    }
+}
+
+type HttpResult = core::result::Result<Value, (i32, String)>;
+
+/// A running simulator, stopped when dropped
+pub struct MockExchange {
+    state: Arc<Mutex<MockState>>,
+    ticker_tx: broadcast::Sender<(Market, String)>,
+    user_tx: broadcast::Sender<(Market, String)>,
+    drop_tx: broadcast::Sender<()>,
+    http_addr: SocketAddr,
+    ws_addr: SocketAddr,
+    tasks: Vec<tokio::task::JoinHandle<()>>,
+}
+
+impl Drop for MockExchange {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+impl MockExchange {
+    /// Binds the REST and websocket listeners on random local ports
+    pub async fn start() -> std::io::Result<MockExchange> {
+        let (ticker_tx, _) = broadcast::channel(1024);
+        let (user_tx, _) = broadcast::channel(1024);
+        let (drop_tx, _) = broadcast::channel(16);
+        let state = Arc::new(Mutex::new(MockState {
+            user_tx: Some(user_tx.clone()),
+            ..MockState::default()
+        }));
+
+        let http = TcpListener::bind("127.0.0.1:0").await?;
+        let ws = TcpListener::bind("127.0.0.1:0").await?;
+        let http_addr = http.local_addr()?;
+        let ws_addr = ws.local_addr()?;
+
+        let http_state = state.clone();
+        let http_task = tokio::spawn(async move {
+            while let Ok((stream, _)) = http.accept().await {
+                let state = http_state.clone();
+                tokio::spawn(async move {
+                    let _ = serve_http(stream, state).await;
+                });
+            }
+        });
+
+        let ws_tx = ticker_tx.clone();
+        let ws_user_tx = user_tx.clone();
+        let ws_drop_tx = drop_tx.clone();
+        let ws_state = state.clone();
+        let ws_task = tokio::spawn(async move {
+            while let Ok((stream, _)) = ws.accept().await {
+                let ticker_rx = ws_tx.subscribe();
+                let user_rx = ws_user_tx.subscribe();
+                let drop_rx = ws_drop_tx.subscribe();
+                let state = ws_state.clone();
+                tokio::spawn(async move {
+                    let _ = serve_ws(stream, ticker_rx, user_rx, drop_rx, state).await;
+                });
+            }
+        });
+
+        Ok(MockExchange {
+            state,
+            ticker_tx,
+            user_tx,
+            drop_tx,
+            http_addr,
+            ws_addr,
+            tasks: vec![http_task, ws_task],
+        })
+    }
+
+    /// Config with every rest and websocket endpoint pointing at the simulator
+    pub fn config(&self) -> Config {
+        let http = format!("http://{}", self.http_addr);
+        let ws = format!("ws://{}", self.ws_addr);
+        Config::default()
+            .set_rest_api_endpoint(http.clone())
+            .set_futures_rest_api_endpoint(http.clone())
+            .set_delivery_rest_api_endpoint(http)
+            .set_ws_endpoint(format!("{ws}/spot"))
+            .set_futures_ws_endpoint(format!("{ws}/futures"))
+            .set_delivery_ws_endpoint(format!("{ws}/delivery"))
+            .set_timeout(5)
+    }
+
+    /// Api signed with the simulator credentials
+    pub fn api(&self) -> MyApi {
+        MyApi::new_with_config(
+            MOCK_API_KEY.to_string(),
+            MOCK_SECRET_KEY.to_string(),
+            &self.config(),
+        )
+    }
+
+    /// Sets the last price and publishes it on the market's mini ticker stream
+    pub fn set_price(&self, market: Market, symbol: &str, price: f64) {
+        self.state.lock().unwrap().set_price(market, symbol, price);
+        self.publish(market, symbol, price);
+    }
+
+    /// Queues prices that are applied one at a time by [`MockExchange::advance`]
+    pub fn set_price_path(&self, market: Market, symbol: &str, prices: Vec<f64>) {
+        self.state
+            .lock()
+            .unwrap()
+            .price_paths
+            .insert((market, symbol.to_string()), prices.into());
+    }
+
+    /// Applies the next price of every path, returns false once all paths are exhausted
+    pub fn advance(&self) -> bool {
+        let ticks: Vec<(Market, String, f64)> = {
+            let mut state = self.state.lock().unwrap();
+            let ticks: Vec<(Market, String, f64)> = state
+                .price_paths
+                .iter_mut()
+                .filter_map(|((market, symbol), path)| {
+                    path.pop_front().map(|p| (*market, symbol.clone(), p))
+                })
+                .collect();
+            for (market, symbol, price) in &ticks {
+                state.set_price(*market, symbol, *price);
+            }
+            ticks
+        };
+        for (market, symbol, price) in &ticks {
+            self.publish(*market, symbol, *price);
+        }
+        !ticks.is_empty()
+    }
+
+    /// Contract face value of a COIN-M symbol, used to compute `cumBase`
+    pub fn set_contract_size(&self, symbol: &str, size: f64) {
+        self.state
+            .lock()
+            .unwrap()
+            .contract_sizes
+            .insert(symbol.to_string(), size);
+    }
+
+    pub fn script_order(&self, market: Market, script: OrderScript) {
+        self.state
+            .lock()
+            .unwrap()
+            .order_scripts
+            .entry(market)
+            .or_default()
+            .push_back(script);
+    }
+
+    /// Only `Reject` is meaningful for transfers, anything else succeeds
+    pub fn script_transfer(&self, script: OrderScript) {
+        self.state
+            .lock()
+            .unwrap()
+            .transfer_scripts
+            .push_back(script);
+    }
+
+    pub fn set_klines(&self, symbol: &str, klines: Vec<KlineSummary>) {
+        self.state
+            .lock()
+            .unwrap()
+            .klines
+            .insert(symbol.to_string(), klines);
+    }
+
+    /// Lists the symbol in the exchangeInfo of the market, COIN-M symbols also need
+    /// [`MockExchange::set_contract_size`]
+    pub fn list_symbol(&self, market: Market, symbol: &str, tick_size: &str, step_size: &str) {
+        self.state.lock().unwrap().symbols.insert(
+            (market, symbol.to_string()),
+            (tick_size.to_string(), step_size.to_string()),
+        );
+    }
+
+    /// Free balance of the spot wallet, available balance of the futures wallets
+    pub fn set_balance(&self, market: Market, asset: &str, amount: f64) {
+        self.state
+            .lock()
+            .unwrap()
+            .balances
+            .insert((market, asset.to_string()), amount);
+    }
+
+    pub fn order(&self, order_id: u64) -> Option<MockOrder> {
+        self.state.lock().unwrap().orders.get(&order_id).cloned()
+    }
+
+    /// All orders sorted by id
+    pub fn orders(&self) -> Vec<MockOrder> {
+        let mut orders: Vec<MockOrder> = self
+            .state
+            .lock()
+            .unwrap()
+            .orders
+            .values()
+            .cloned()
+            .collect();
+        orders.sort_by_key(|o| o.order_id);
+        orders
+    }
+
+    pub fn transfers(&self) -> Vec<MockTransfer> {
+        self.state.lock().unwrap().transfers.clone()
+    }
+
+    /// Drops every open websocket without a close frame, like a network failure
+    pub fn drop_websockets(&self) {
+        let _ = self.drop_tx.send(());
+    }
+
+    /// Invalidates every listen key, open user data streams receive `listenKeyExpired`
+    pub fn expire_listen_keys(&self) {
+        let keys: Vec<String> = self.state.lock().unwrap().listen_keys.drain().collect();
+        for market in [Market::Spot, Market::Futures, Market::Delivery] {
+            for key in &keys {
+                let event = json!({
+                    "e": "listenKeyExpired",
+                    "E": get_timestamp().unwrap_or_default(),
+                    "listenKey": key,
+                });
+                let _ = self.user_tx.send((market, event.to_string()));
+            }
+        }
+    }
+
+    fn publish(&self, market: Market, symbol: &str, price: f64) {
+        let tick = json!([{
+            "e": "24hrMiniTicker",
+            "E": get_timestamp().unwrap_or_default(),
+            "s": symbol,
+            "c": price.to_string(),
+            "o": price.to_string(),
+            "h": price.to_string(),
+            "l": price.to_string(),
+            "v": "0",
+            "q": "0",
+        }]);
+        // no subscriber is not an error
+        let _ = self.ticker_tx.send((market, tick.to_string()));
+    }
+}
+
+async fn serve_http(mut stream: TcpStream, state: Arc<Mutex<MockState>>) -> std::io::Result<()> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default().to_string();
+    let target = request_line.next().unwrap_or_default().to_string();
+    let headers: HashMap<String, String> = lines
+        .filter_map(|l| l.split_once(':'))
+        .map(|(k, v)| (k.trim().to_lowercase(), v.trim().to_string()))
+        .collect();
+
+    // drain the body, every parameter travels in the query string
+    let content_length: usize = headers
+        .get("content-length")
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(0);
+    let mut body_read = buf.len() - header_end;
+    while body_read < content_length {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        body_read += n;
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((target.as_str(), ""));
+    let result = handle_request(&state, &method, path, query, headers.get("x-mbx-apikey"));
+    let (status, body) = match result {
+        Ok(body) => ("200 OK", body),
+        Err((code, msg)) => ("400 Bad Request", json!({ "code": code, "msg": msg })),
+    };
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+// the handshake callback signature is fixed by tungstenite
+#[allow(clippy::result_large_err)]
+async fn serve_ws(
+    stream: TcpStream,
+    ticker_rx: broadcast::Receiver<(Market, String)>,
+    user_rx: broadcast::Receiver<(Market, String)>,
+    mut drop_rx: broadcast::Receiver<()>,
+    state: Arc<Mutex<MockState>>,
+) -> crate::binance::errors::Result<()> {
+    let mut market = None;
+    let mut stream_name = String::new();
+    let callback =
+        |request: &Request, response: Response| -> core::result::Result<Response, ErrorResponse> {
+            market = Market::from_ws_path(request.uri().path());
+            stream_name = request
+                .uri()
+                .path()
+                .rsplit('/')
+                .next()
+                .unwrap_or_default()
+                .to_string();
+            Ok(response)
+        };
+    let mut socket = tokio_tungstenite::accept_hdr_async(stream, callback).await?;
+
+    // `/<market>/ws/<listenKey>` is a user data stream, anything else a ticker stream
+    let user_stream = state.lock().unwrap().listen_keys.contains(&stream_name);
+    let mut rx = if user_stream { user_rx } else { ticker_rx };
+
+    loop {
+        tokio::select! {
+            tick = rx.recv() => match tick {
+                Ok((m, text)) if Some(m) == market => socket.send(Message::Text(text)).await?,
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = drop_rx.recv() => return Ok(()),
+            incoming = socket.next() => match incoming {
+                Some(Ok(Message::Ping(payload))) => socket.send(Message::Pong(payload)).await?,
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    Ok(())
+}
+
+fn handle_request(
+    state: &Mutex<MockState>,
+    method: &str,
+    path: &str,
+    query: &str,
+    api_key: Option<&String>,
+) -> HttpResult {
+    let params: HashMap<String, String> = url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect();
+
+    match (method, path) {
+        ("GET", "/api/v3/time") => Ok(json!({ "serverTime": get_timestamp().unwrap_or_default() })),
+        ("GET", "/api/v3/klines") => klines(state, &params),
+        ("POST", "/sapi/v1/asset/transfer") => {
+            verify_signature(query, api_key)?;
+            transfer(state, &params)
+        }
+        ("GET", "/sapi/v1/asset/transfer") => {
+            verify_signature(query, api_key)?;
+            transfer_history(state, &params)
+        }
+        ("POST", "/api/v3/order") | ("POST", "/fapi/v1/order") | ("POST", "/dapi/v1/order") => {
+            verify_signature(query, api_key)?;
+            let market =
+                Market::from_rest_path(path).ok_or((-1000, "Unknown market.".to_string()))?;
+            place_order(state, market, &params)
+        }
+        ("GET", "/api/v3/order") | ("GET", "/fapi/v1/order") | ("GET", "/dapi/v1/order") => {
+            verify_signature(query, api_key)?;
+            let market =
+                Market::from_rest_path(path).ok_or((-1000, "Unknown market.".to_string()))?;
+            query_order(state, market, &params)
+        }
+        ("DELETE", "/api/v3/order")
+        | ("DELETE", "/fapi/v1/order")
+        | ("DELETE", "/dapi/v1/order") => {
+            verify_signature(query, api_key)?;
+            let market =
+                Market::from_rest_path(path).ok_or((-1000, "Unknown market.".to_string()))?;
+            cancel_order(state, market, &params)
+        }
+        ("GET", "/api/v3/openOrders")
+        | ("GET", "/fapi/v1/openOrders")
+        | ("GET", "/dapi/v1/openOrders") => {
+            verify_signature(query, api_key)?;
+            let market =
+                Market::from_rest_path(path).ok_or((-1000, "Unknown market.".to_string()))?;
+            open_orders(state, market, &params)
+        }
+        ("DELETE", "/api/v3/openOrders")
+        | ("DELETE", "/fapi/v1/allOpenOrders")
+        | ("DELETE", "/dapi/v1/allOpenOrders") => {
+            verify_signature(query, api_key)?;
+            let market =
+                Market::from_rest_path(path).ok_or((-1000, "Unknown market.".to_string()))?;
+            cancel_open_orders(state, market, &params)
+        }
+        ("GET", "/api/v3/exchangeInfo")
+        | ("GET", "/fapi/v1/exchangeInfo")
+        | ("GET", "/dapi/v1/exchangeInfo") => {
+            let market =
+                Market::from_rest_path(path).ok_or((-1000, "Unknown market.".to_string()))?;
+            exchange_info(state, market)
+        }
+        ("GET", "/api/v3/account") | ("GET", "/fapi/v2/account") | ("GET", "/dapi/v1/account") => {
+            verify_signature(query, api_key)?;
+            let market =
+                Market::from_rest_path(path).ok_or((-1000, "Unknown market.".to_string()))?;
+            account(state, market)
+        }
+        ("GET", "/fapi/v2/positionRisk") | ("GET", "/dapi/v1/positionRisk") => {
+            verify_signature(query, api_key)?;
+            let market =
+                Market::from_rest_path(path).ok_or((-1000, "Unknown market.".to_string()))?;
+            position_risk(state, market, &params)
+        }
+        (_, "/api/v3/userDataStream") | (_, "/fapi/v1/listenKey") | (_, "/dapi/v1/listenKey") => {
+            verify_api_key(api_key)?;
+            listen_key(state, method, &params)
+        }
+        _ => Err((-1000, format!("Unsupported endpoint {method} {path}"))),
+    }
+}
+
+fn verify_api_key(api_key: Option<&String>) -> core::result::Result<(), (i32, String)> {
+    if api_key.map(String::as_str) != Some(MOCK_API_KEY) {
+        return Err((-2014, "API-key format invalid.".to_string()));
+    }
+    Ok(())
+}
+
+fn verify_signature(
+    query: &str,
+    api_key: Option<&String>,
+) -> core::result::Result<(), (i32, String)> {
+    if api_key.map(|k| k.as_str()) != Some(MOCK_API_KEY) {
+        return Err((
+            -2015,
+            "Invalid API-key, IP, or permissions for action.".to_string(),
+        ));
+    }
+    let (payload, signature) = query.rsplit_once("&signature=").ok_or((
+        -1102,
+        "Mandatory parameter 'signature' was not sent.".to_string(),
+    ))?;
+    let key = hmac::Key::new(hmac::HMAC_SHA256, MOCK_SECRET_KEY.as_bytes());
+    if hex_encode(hmac::sign(&key, payload.as_bytes()).as_ref()) != signature {
+        return Err((
+            -1022,
+            "Signature for this request is not valid.".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn param<T: std::str::FromStr>(
+    params: &HashMap<String, String>,
+    name: &str,
+) -> core::result::Result<T, (i32, String)> {
+    params
+        .get(name)
+        .and_then(|v| v.parse().ok())
+        .ok_or((-1102, format!("Mandatory parameter '{name}' was not sent.")))
+}
+
+fn param_enum<T: DeserializeOwned>(
+    params: &HashMap<String, String>,
+    name: &str,
+) -> core::result::Result<T, (i32, String)> {
+    params
+        .get(name)
+        .and_then(|v| serde_json::from_value(Value::String(v.clone())).ok())
+        .ok_or((-1102, format!("Mandatory parameter '{name}' was not sent.")))
+}
+
+fn place_order(
+    state: &Mutex<MockState>,
+    market: Market,
+    params: &HashMap<String, String>,
+) -> HttpResult {
+    let symbol: String = param(params, "symbol")?;
+    let side: OrderSide = param_enum(params, "side")?;
+    let order_type: OrderType = param_enum(params, "type")?;
+    let orig_qty: f64 = param(params, "quantity")?;
+    let price: f64 = param(params, "price")?;
+    let time_in_force: TimeInForce = param_enum(params, "timeInForce").unwrap_or(TimeInForce::GTC);
+
+    let mut state = state.lock().unwrap();
+    let script = state
+        .order_scripts
+        .get_mut(&market)
+        .and_then(|scripts| scripts.pop_front());
+    if let Some(OrderScript::Reject { code, msg }) = script {
+        return Err((code, msg));
+    }
+    let last_price = match state.prices.get(&(market, symbol.clone())) {
+        Some(p) => *p,
+        None if script.is_some() => price,
+        None => return Err((-1121, "Invalid symbol.".to_string())),
+    };
+
+    let client_order_id = params.get("newClientOrderId").cloned();
+    // client order ids are unique among open orders
+    if client_order_id.as_ref().is_some_and(|id| {
+        state
+            .orders
+            .values()
+            .any(|o| o.market == market && o.is_open() && &o.client_order_id == id)
+    }) {
+        return Err((-2010, "Duplicate order sent.".to_string()));
+    }
+
+    let order_id = state.next_id();
+    let now = get_timestamp().unwrap_or_default();
+    let mut order = MockOrder {
+        market,
+        order_id,
+        client_order_id: client_order_id.unwrap_or_else(|| format!("mock_{order_id}")),
+        symbol,
+        side,
+        order_type,
+        time_in_force: time_in_force.clone(),
+        price,
+        orig_qty,
+        executed_qty: 0.0,
+        avg_price: 0.0,
+        status: OrderStatus::New,
+        time: now,
+        update_time: now,
+    };
+
+    let fill_qty = match script {
+        Some(OrderScript::Fill) => orig_qty,
+        Some(OrderScript::PartialFill(ratio)) if time_in_force != TimeInForce::FOK => {
+            orig_qty * ratio
+        }
+        Some(_) => 0.0,
+        None if order.marketable(last_price) => orig_qty,
+        None => 0.0,
+    };
+    if fill_qty > 0.0 {
+        let fill_price = if script.is_some() { price } else { last_price };
+        order.fill(fill_qty, fill_price);
+    }
+    // FOK/IOC never rest on the book
+    if order.is_open() && time_in_force != TimeInForce::GTC {
+        order.status = OrderStatus::Expired;
+    }
+
+    state.orders.insert(order_id, order.clone());
+    state.emit_order(&order);
+    let contract_size = state.contract_sizes.get(&order.symbol).copied();
+    Ok(match market {
+        Market::Spot => spot_transaction_json(&order),
+        _ => futures_transaction_json(&order, contract_size),
+    })
+}
+
+/// The order addressed by `orderId` or `origClientOrderId`, None when no order matches
+fn order_id_param(
+    state: &MockState,
+    market: Market,
+    params: &HashMap<String, String>,
+) -> core::result::Result<Option<u64>, (i32, String)> {
+    if params.contains_key("orderId") {
+        return param(params, "orderId").map(Some);
+    }
+    let client_order_id = params.get("origClientOrderId").ok_or((
+        -1102,
+        "Param 'origClientOrderId' or 'orderId' must be sent, but both were empty/null!"
+            .to_string(),
+    ))?;
+    Ok(state
+        .orders
+        .values()
+        .filter(|o| o.market == market && &o.client_order_id == client_order_id)
+        .map(|o| o.order_id)
+        .max())
+}
+
+fn query_order(
+    state: &Mutex<MockState>,
+    market: Market,
+    params: &HashMap<String, String>,
+) -> HttpResult {
+    let state = state.lock().unwrap();
+    let order = order_id_param(&state, market, params)?
+        .and_then(|order_id| state.orders.get(&order_id))
+        .filter(|o| o.market == market)
+        .ok_or((-2013, "Order does not exist.".to_string()))?;
+    let contract_size = state.contract_sizes.get(&order.symbol).copied();
+    Ok(match market {
+        Market::Spot => spot_order_json(order),
+        _ => futures_transaction_json(order, contract_size),
+    })
+}
+
+fn order_json(state: &MockState, order: &MockOrder) -> Value {
+    match order.market {
+        Market::Spot => spot_order_json(order),
+        _ => futures_transaction_json(order, state.contract_sizes.get(&order.symbol).copied()),
+    }
+}
+
+/// Spot cancels echo the client order id of the canceled order
+fn canceled_json(state: &MockState, order: &MockOrder) -> Value {
+    let mut value = order_json(state, order);
+    if order.market == Market::Spot {
+        value["origClientOrderId"] = json!(order.client_order_id);
+    }
+    value
+}
+
+fn cancel_order(
+    state: &Mutex<MockState>,
+    market: Market,
+    params: &HashMap<String, String>,
+) -> HttpResult {
+    let mut state = state.lock().unwrap();
+    let order_id = order_id_param(&state, market, params)?;
+    let order = order_id
+        .and_then(|order_id| state.orders.get_mut(&order_id))
+        .filter(|o| o.market == market && o.is_open())
+        .ok_or((-2011, "Unknown order sent.".to_string()))?;
+    order.status = OrderStatus::Canceled;
+    order.update_time = get_timestamp().unwrap_or_default();
+    let order = order.clone();
+    state.emit_order(&order);
+    Ok(canceled_json(&state, &order))
+}
+
+fn open_orders(
+    state: &Mutex<MockState>,
+    market: Market,
+    params: &HashMap<String, String>,
+) -> HttpResult {
+    let symbol: String = param(params, "symbol")?;
+    let state = state.lock().unwrap();
+    let mut orders: Vec<&MockOrder> = state
+        .orders
+        .values()
+        .filter(|o| o.market == market && o.symbol == symbol && o.is_open())
+        .collect();
+    orders.sort_by_key(|o| o.order_id);
+    Ok(Value::Array(
+        orders.into_iter().map(|o| order_json(&state, o)).collect(),
+    ))
+}
+
+/// Spot answers with the canceled orders, the futures venues with a code/msg pair
+fn cancel_open_orders(
+    state: &Mutex<MockState>,
+    market: Market,
+    params: &HashMap<String, String>,
+) -> HttpResult {
+    let symbol: String = param(params, "symbol")?;
+    let mut state = state.lock().unwrap();
+    let now = get_timestamp().unwrap_or_default();
+    let mut canceled = vec![];
+    for order in state.orders.values_mut() {
+        if order.market == market && order.symbol == symbol && order.is_open() {
+            order.status = OrderStatus::Canceled;
+            order.update_time = now;
+            canceled.push(order.clone());
+        }
+    }
+    canceled.sort_by_key(|o| o.order_id);
+    for order in &canceled {
+        state.emit_order(order);
+    }
+    Ok(match market {
+        Market::Spot => Value::Array(canceled.iter().map(|o| canceled_json(&state, o)).collect()),
+        _ => json!({ "code": 200, "msg": "The operation of cancel all open order is done." }),
+    })
+}
+
+fn transfer(state: &Mutex<MockState>, params: &HashMap<String, String>) -> HttpResult {
+    let asset: String = param(params, "asset")?;
+    let amount: f64 = param(params, "amount")?;
+    let transfer_type: String = param(params, "type")?;
+
+    let mut state = state.lock().unwrap();
+    if let Some(OrderScript::Reject { code, msg }) = state.transfer_scripts.pop_front() {
+        return Err((code, msg));
+    }
+    let tran_id = state.next_id();
+    state.transfers.push(MockTransfer {
+        tran_id,
+        asset,
+        amount,
+        transfer_type,
+        timestamp: get_timestamp().unwrap_or_default(),
+    });
+    Ok(json!({ "tranId": tran_id }))
+}
+
+/// Transfers of `type` since `startTime`, newest first
+fn transfer_history(state: &Mutex<MockState>, params: &HashMap<String, String>) -> HttpResult {
+    let transfer_type: String = param(params, "type")?;
+    let start_time: u64 = param(params, "startTime").unwrap_or_default();
+    let state = state.lock().unwrap();
+    let rows: Vec<Value> = state
+        .transfers
+        .iter()
+        .rev()
+        .filter(|t| t.transfer_type == transfer_type && t.timestamp >= start_time)
+        .map(|t| {
+            json!({
+                "asset": t.asset,
+                "amount": t.amount.to_string(),
+                "type": t.transfer_type,
+                "status": "CONFIRMED",
+                "tranId": t.tran_id,
+                "timestamp": t.timestamp,
+            })
+        })
+        .collect();
+    Ok(json!({ "total": rows.len(), "rows": rows }))
+}
+
+/// POST creates a listen key, PUT keeps it alive and DELETE closes it
+fn listen_key(
+    state: &Mutex<MockState>,
+    method: &str,
+    params: &HashMap<String, String>,
+) -> HttpResult {
+    let mut state = state.lock().unwrap();
+    if method == "POST" {
+        let listen_key = format!("mock_listen_key_{}", state.next_id());
+        state.listen_keys.insert(listen_key.clone());
+        return Ok(json!({ "listenKey": listen_key }));
+    }
+    let listen_key: String = param(params, "listenKey")?;
+    let exists = match method {
+        "PUT" => state.listen_keys.contains(&listen_key),
+        "DELETE" => state.listen_keys.remove(&listen_key),
+        _ => return Err((-1000, format!("Unsupported method {method}"))),
+    };
+    if !exists {
+        return Err((-1125, "This listenKey does not exist.".to_string()));
+    }
+    Ok(json!({}))
+}
+
+fn exchange_info(state: &Mutex<MockState>, market: Market) -> HttpResult {
+    let state = state.lock().unwrap();
+    let mut listed: Vec<(&String, &(String, String))> = state
+        .symbols
+        .iter()
+        .filter(|((m, _), _)| *m == market)
+        .map(|((_, symbol), sizes)| (symbol, sizes))
+        .collect();
+    listed.sort();
+    let symbols: Vec<Value> = listed
+        .into_iter()
+        .map(|(symbol, (tick_size, step_size))| {
+            let filters = json!([
+                {"filterType": "PRICE_FILTER", "minPrice": tick_size, "maxPrice": "1000000", "tickSize": tick_size},
+                {"filterType": "LOT_SIZE", "minQty": step_size, "maxQty": "1000000", "stepSize": step_size},
+            ]);
+            let pair = symbol.split('_').next().unwrap_or(symbol);
+            match market {
+                Market::Spot => json!({
+                    "symbol": symbol,
+                    "status": "TRADING",
+                    "baseAsset": symbol.trim_end_matches("USDT"),
+                    "quoteAsset": "USDT",
+                    "filters": filters,
+                }),
+                _ => json!({
+                    "symbol": symbol,
+                    "pair": pair,
+                    "contractType": if symbol.contains('_') && !symbol.ends_with("_PERP") {
+                        "CURRENT_QUARTER"
+                    } else {
+                        "PERPETUAL"
+                    },
+                    // far enough for a quarterly contract never to be rolled
+                    "deliveryDate": 4133404800000_i64,
+                    "onboardDate": 0,
+                    "contractSize": state.contract_sizes.get(symbol).copied().unwrap_or(0.0) as i64,
+                    "filters": filters,
+                }),
+            }
+        })
+        .collect();
+    Ok(json!({ "symbols": symbols }))
+}
+
+fn account(state: &Mutex<MockState>, market: Market) -> HttpResult {
+    let state = state.lock().unwrap();
+    let mut balances: Vec<(&String, f64)> = state
+        .balances
+        .iter()
+        .filter(|((m, _), _)| *m == market)
+        .map(|((_, asset), amount)| (asset, *amount))
+        .collect();
+    balances.sort_by(|a, b| a.0.cmp(b.0));
+    Ok(match market {
+        Market::Spot => json!({
+            "canTrade": true,
+            "canWithdraw": true,
+            "canDeposit": true,
+            "updateTime": get_timestamp().unwrap_or_default(),
+            "balances": balances
+                .iter()
+                .map(|(asset, free)| json!({ "asset": asset, "free": free.to_string(), "locked": "0" }))
+                .collect::<Vec<Value>>(),
+        }),
+        _ => json!({
+            "canTrade": true,
+            "assets": balances
+                .iter()
+                .map(|(asset, amount)| json!({
+                    "asset": asset,
+                    "walletBalance": amount.to_string(),
+                    "marginBalance": amount.to_string(),
+                    "availableBalance": amount.to_string(),
+                    "maxWithdrawAmount": amount.to_string(),
+                }))
+                .collect::<Vec<Value>>(),
+        }),
+    })
+}
+
+/// Net filled quantity per symbol, USDⓈ-M filters on `symbol` and COIN-M on `pair`
+fn position_risk(
+    state: &Mutex<MockState>,
+    market: Market,
+    params: &HashMap<String, String>,
+) -> HttpResult {
+    let (name, value): (&str, String) = match market {
+        Market::Delivery => ("pair", param(params, "pair")?),
+        _ => ("symbol", param(params, "symbol")?),
+    };
+    let state = state.lock().unwrap();
+    let mut positions: HashMap<&String, f64> = HashMap::new();
+    for order in state.orders.values().filter(|o| o.market == market) {
+        let matches = match name {
+            "pair" => order.symbol.split('_').next() == Some(value.as_str()),
+            _ => order.symbol == value,
+        };
+        if matches {
+            let qty = match order.side {
+                OrderSide::Buy => order.executed_qty,
+                OrderSide::Sell => -order.executed_qty,
+            };
+            *positions.entry(&order.symbol).or_default() += qty;
+        }
+    }
+    let mut positions: Vec<(&String, f64)> = positions.into_iter().collect();
+    positions.sort_by(|a, b| a.0.cmp(b.0));
+    Ok(Value::Array(
+        positions
+            .into_iter()
+            .map(|(symbol, amt)| {
+                let mark_price = state.prices.get(&(market, symbol.clone())).copied();
+                json!({
+                    "symbol": symbol,
+                    "positionAmt": amt.to_string(),
+                    "entryPrice": "0",
+                    "markPrice": mark_price.unwrap_or_default().to_string(),
+                    "unRealizedProfit": "0",
+                    "liquidationPrice": "0",
+                    "leverage": "1",
+                    "marginType": "cross",
+                    "positionSide": "BOTH",
+                })
+            })
+            .collect(),
+    ))
+}
+
+fn klines(state: &Mutex<MockState>, params: &HashMap<String, String>) -> HttpResult {
+    let symbol: String = param(params, "symbol")?;
+    let limit: usize = param(params, "limit").unwrap_or(500);
+    let start_time: i64 = param(params, "startTime").unwrap_or(i64::MIN);
+    let end_time: i64 = param(params, "endTime").unwrap_or(i64::MAX);
+
+    let state = state.lock().unwrap();
+    let rows: Vec<Value> = state
+        .klines
+        .get(&symbol)
+        .map(|klines| {
+            klines
+                .iter()
+                .filter(|k| k.open_time >= start_time && k.open_time <= end_time)
+                .take(limit)
+                .map(|k| {
+                    json!([
+                        k.open_time,
+                        k.open.to_string(),
+                        k.high.to_string(),
+                        k.low.to_string(),
+                        k.close.to_string(),
+                        k.volume.to_string(),
+                        k.close_time,
+                        k.quote_asset_volume.to_string(),
+                        k.number_of_trades,
+                        k.taker_buy_base_asset_volume.to_string(),
+                        k.taker_buy_quote_asset_volume.to_string(),
+                        "0"
+                    ])
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(Value::Array(rows))
+}
+
+fn spot_transaction_json(order: &MockOrder) -> Value {
+    let fills = if order.executed_qty > 0.0 {
+        json!([{
+            "price": order.avg_price.to_string(),
+            "qty": order.executed_qty.to_string(),
+            "commission": "0",
+            "commissionAsset": "BNB",
+        }])
+    } else {
+        json!([])
+    };
+    json!({
+        "symbol": order.symbol,
+        "orderId": order.order_id,
+        "clientOrderId": order.client_order_id,
+        "transactTime": order.time,
+        "price": order.price.to_string(),
+        "origQty": order.orig_qty.to_string(),
+        "executedQty": order.executed_qty.to_string(),
+        "cummulativeQuoteQty": (order.executed_qty * order.avg_price).to_string(),
+        "status": order.status,
+        "timeInForce": order.time_in_force,
+        "type": order.order_type,
+        "side": order.side,
+        "fills": fills,
+    })
+}
+
+fn spot_order_json(order: &MockOrder) -> Value {
+    json!({
+        "symbol": order.symbol,
+        "orderId": order.order_id,
+        "orderListId": -1,
+        "clientOrderId": order.client_order_id,
+        "price": order.price.to_string(),
+        "origQty": order.orig_qty.to_string(),
+        "executedQty": order.executed_qty.to_string(),
+        "cummulativeQuoteQty": (order.executed_qty * order.avg_price).to_string(),
+        "status": order.status,
+        "timeInForce": order.time_in_force,
+        "type": order.order_type,
+        "side": order.side,
+        "stopPrice": "0",
+        "icebergQty": "0",
+        "time": order.time,
+        "updateTime": order.update_time,
+        "isWorking": order.is_open(),
+        "origQuoteOrderQty": "0",
+    })
+}
+
+/// USDⓈ-M and COIN-M share the order schema, only COIN-M reports `cumBase`
+fn futures_transaction_json(order: &MockOrder, contract_size: Option<f64>) -> Value {
+    let mut value = json!({
+        "clientOrderId": order.client_order_id,
+        "cumQty": order.executed_qty.to_string(),
+        "cumQuote": (order.executed_qty * order.avg_price).to_string(),
+        "executedQty": order.executed_qty.to_string(),
+        "orderId": order.order_id,
+        "avgPrice": order.avg_price.to_string(),
+        "origQty": order.orig_qty.to_string(),
+        "price": order.price.to_string(),
+        "reduceOnly": false,
+        "side": order.side,
+        "positionSide": "BOTH",
+        "status": order.status,
+        "stopPrice": "0",
+        "closePosition": false,
+        "symbol": order.symbol,
+        "timeInForce": order.time_in_force,
+        "type": order.order_type,
+        "origType": order.order_type,
+        "updateTime": order.update_time,
+        "workingType": "CONTRACT_PRICE",
+        "priceProtect": false,
+    });
+    if order.market == Market::Delivery {
+        let cum_base = match (contract_size, order.avg_price > 0.0) {
+            (Some(size), true) => order.executed_qty * size / order.avg_price,
+            _ => 0.0,
+        };
+        value["cumBase"] = json!(cum_base.to_string());
+    }
+    value
+}
+
+fn execution_report_json(order: &MockOrder) -> Value {
+    json!({
+        "e": "executionReport",
+        "E": order.update_time,
+        "s": order.symbol,
+        "c": order.client_order_id,
+        "S": order.side,
+        "o": order.order_type,
+        "f": order.time_in_force,
+        "q": order.orig_qty.to_string(),
+        "p": order.price.to_string(),
+        "C": "",
+        "x": if order.executed_qty > 0.0 { "TRADE" } else { "NEW" },
+        "X": order.status,
+        "r": "NONE",
+        "i": order.order_id,
+        "l": order.executed_qty.to_string(),
+        "z": order.executed_qty.to_string(),
+        "L": order.avg_price.to_string(),
+        "n": "0",
+        "N": null,
+        "T": order.update_time,
+        "t": -1,
+        "Z": (order.executed_qty * order.avg_price).to_string(),
+    })
+}
+
+fn order_trade_update_json(order: &MockOrder) -> Value {
+    json!({
+        "e": "ORDER_TRADE_UPDATE",
+        "E": order.update_time,
+        "T": order.update_time,
+        "o": {
+            "s": order.symbol,
+            "c": order.client_order_id,
+            "S": order.side,
+            "o": order.order_type,
+            "f": order.time_in_force,
+            "q": order.orig_qty.to_string(),
+            "p": order.price.to_string(),
+            "ap": order.avg_price.to_string(),
+            "x": if order.executed_qty > 0.0 { "TRADE" } else { "NEW" },
+            "X": order.status,
+            "i": order.order_id,
+            "l": order.executed_qty.to_string(),
+            "z": order.executed_qty.to_string(),
+            "L": order.avg_price.to_string(),
+            "T": order.update_time,
+            "t": 0,
+            "ps": "BOTH",
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::binance::api::{
+        FuturesGetOrderRequest, FuturesOrderRequest, OrderRequest, OrderStatusRequest,
+    };
+    use crate::binance::errors::Error;
+    use crate::binance::rest_model::{KlineSummaries, UniversalTransferType};
+    use crate::binance::websockets::{
+        all_mini_ticker_stream, stream_metrics, ReconnectPolicy, WebSockets,
+    };
+    use crate::binance::ws_model::WebsocketEvent;
+    use crate::service::state_machine::{LegAction, StrategyMachine};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    fn limit(
+        symbol: &str,
+        side: OrderSide,
+        quantity: f64,
+        price: f64,
+        tif: TimeInForce,
+    ) -> FuturesOrderRequest {
+        FuturesOrderRequest {
+            symbol: symbol.to_string(),
+            side,
+            order_type: OrderType::Limit,
+            quantity: Some(quantity),
+            price: Some(price),
+            time_in_force: Some(tif),
+            new_client_order_id: None,
+            recv_window: None,
+        }
+    }
+
+    // Places the legs by hand, `service::binance_strategy::tests::test_run_strategy` drives
+    // the executor itself through a cycle against a test database
+    #[tokio::test]
+    async fn test_positive_cycle() {
+        let mock = MockExchange::start().await.unwrap();
+        mock.set_price(Market::Spot, "ETHUSDT", 3500.0);
+        mock.set_price(Market::Delivery, "ETHUSD_240628", 3700.0);
+        mock.set_contract_size("ETHUSD_240628", 10.0);
+        let api = mock.api();
+
+        let legs: Vec<LegAction> = StrategyMachine::positive()
+            .legs
+            .into_iter()
+            .map(|l| l.action)
+            .collect();
+        assert_eq!(
+            legs,
+            vec![
+                LegAction::Spot(OrderSide::Buy),
+                LegAction::Transfer(UniversalTransferType::MainCmfuture),
+                LegAction::Delivery(OrderSide::Sell),
+                LegAction::Delivery(OrderSide::Buy),
+                LegAction::Transfer(UniversalTransferType::CmfutureMain),
+                LegAction::Spot(OrderSide::Sell),
+            ]
+        );
+
+        // 开仓
+        let spot_buy = api
+            .place_order(OrderRequest {
+                symbol: "ETHUSDT".to_string(),
+                quantity: Some(1.0),
+                price: Some(3505.0),
+                order_type: OrderType::Limit,
+                side: OrderSide::Buy,
+                time_in_force: Some(TimeInForce::FOK),
+                ..OrderRequest::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(spot_buy.status, OrderStatus::Filled);
+        assert_eq!(spot_buy.executed_qty, 1.0);
+
+        api.universal_transfer("ETH".to_string(), 1.0, UniversalTransferType::MainCmfuture)
+            .await
+            .unwrap();
+
+        let contract_num = (1.0_f64 * 3700.0 / 10.0).ceil() - 1.0;
+        let delivery_sell = api
+            .delivery_place_order(limit(
+                "ETHUSD_240628",
+                OrderSide::Sell,
+                contract_num,
+                3695.0,
+                TimeInForce::FOK,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(delivery_sell.status, "FILLED");
+        assert_eq!(delivery_sell.executed_qty, contract_num);
+
+        // basis converges
+        mock.set_price(Market::Spot, "ETHUSDT", 3600.0);
+        mock.set_price(Market::Delivery, "ETHUSD_240628", 3600.0);
+
+        // 平仓
+        let delivery_buy = api
+            .delivery_place_order(limit(
+                "ETHUSD_240628",
+                OrderSide::Buy,
+                contract_num,
+                3605.0,
+                TimeInForce::FOK,
+            ))
+            .await
+            .unwrap();
+        let status = api
+            .delivery_order_status(FuturesGetOrderRequest {
+                symbol: "ETHUSD_240628".to_string(),
+                order_id: Some(delivery_buy.order_id.to_string()),
+                orig_client_order_id: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(status.status, "FILLED");
+        assert!((status.cum_base - contract_num * 10.0 / 3600.0).abs() < 1e-9);
+
+        api.universal_transfer(
+            "ETH".to_string(),
+            status.cum_base,
+            UniversalTransferType::CmfutureMain,
+        )
+        .await
+        .unwrap();
+
+        let spot_sell = api
+            .place_order(OrderRequest {
+                symbol: "ETHUSDT".to_string(),
+                quantity: Some(1.0),
+                price: Some(3595.0),
+                order_type: OrderType::Limit,
+                side: OrderSide::Sell,
+                time_in_force: Some(TimeInForce::FOK),
+                ..OrderRequest::default()
+            })
+            .await
+            .unwrap();
+        let order = api
+            .order_status(OrderStatusRequest {
+                symbol: "ETHUSDT".to_string(),
+                order_id: Some(spot_sell.order_id),
+                orig_client_order_id: None,
+                recv_window: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(order.status, OrderStatus::Filled);
+        assert_eq!(order.cummulative_quote_qty, 3600.0);
+
+        assert_eq!(mock.orders().len(), 4);
+        assert!(mock
+            .orders()
+            .iter()
+            .all(|o| o.status == OrderStatus::Filled));
+        let transfers = mock.transfers();
+        assert_eq!(transfers.len(), 2);
+        assert_eq!(transfers[0].transfer_type, "MAIN_CMFUTURE");
+        assert_eq!(transfers[1].transfer_type, "CMFUTURE_MAIN");
+        let history = api
+            .transfer_history(UniversalTransferType::MainCmfuture, None)
+            .await
+            .unwrap();
+        assert_eq!(history.total, 1);
+        assert_eq!(history.rows[0].tran_id, transfers[0].tran_id);
+        assert_eq!(history.rows[0].amount, 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_fills_and_rejects() {
+        let mock = MockExchange::start().await.unwrap();
+        mock.set_price(Market::Futures, "ETHUSDT", 3500.0);
+        let api = mock.api();
+
+        // not marketable FOK expires
+        let fok = api
+            .futures_place_order(limit(
+                "ETHUSDT",
+                OrderSide::Buy,
+                0.1,
+                3400.0,
+                TimeInForce::FOK,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(fok.status, "EXPIRED");
+        assert_eq!(fok.cum_base, 0.0);
+
+        // scripted partial fill rests, then fills when the price comes down
+        mock.script_order(Market::Futures, OrderScript::PartialFill(0.5));
+        let gtc = api
+            .futures_place_order(limit(
+                "ETHUSDT",
+                OrderSide::Buy,
+                0.2,
+                3450.0,
+                TimeInForce::GTC,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(gtc.status, "PARTIALLY_FILLED");
+        assert_eq!(gtc.executed_qty, 0.1);
+        mock.set_price(Market::Futures, "ETHUSDT", 3440.0);
+        let filled = api
+            .futures_order_status(FuturesGetOrderRequest {
+                symbol: "ETHUSDT".to_string(),
+                order_id: Some(gtc.order_id.to_string()),
+                orig_client_order_id: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(filled.status, "FILLED");
+        assert_eq!(filled.executed_qty, 0.2);
+
+        mock.script_order(
+            Market::Futures,
+            OrderScript::Reject {
+                code: -2019,
+                msg: "Margin is insufficient.".to_string(),
+            },
+        );
+        match api
+            .futures_place_order(limit(
+                "ETHUSDT",
+                OrderSide::Buy,
+                0.1,
+                3500.0,
+                TimeInForce::FOK,
+            ))
+            .await
+        {
+            Err(Error::BinanceError { response }) => assert_eq!(response.code, -2019),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        mock.script_transfer(OrderScript::Reject {
+            code: -5013,
+            msg: "Asset transfer failed: insufficient balance".to_string(),
+        });
+        assert!(api
+            .universal_transfer(
+                "USDT".to_string(),
+                10.0,
+                UniversalTransferType::MainUmfuture
+            )
+            .await
+            .is_err());
+
+        // wrong secret is refused
+        let bad = MyApi::new_with_config(
+            MOCK_API_KEY.to_string(),
+            "wrong".to_string(),
+            &mock.config(),
+        );
+        match bad
+            .futures_place_order(limit(
+                "ETHUSDT",
+                OrderSide::Buy,
+                0.1,
+                3500.0,
+                TimeInForce::FOK,
+            ))
+            .await
+        {
+            Err(Error::BinanceError { response }) => assert_eq!(response.code, -1022),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel_and_open_orders() {
+        let mock = MockExchange::start().await.unwrap();
+        mock.set_price(Market::Spot, "ETHUSDT", 3500.0);
+        mock.set_price(Market::Delivery, "ETHUSD_240628", 3700.0);
+        let api = mock.api();
+
+        let mut resting = vec![];
+        for price in [3400.0, 3300.0] {
+            let order = api
+                .place_order(OrderRequest {
+                    symbol: "ETHUSDT".to_string(),
+                    quantity: Some(1.0),
+                    price: Some(price),
+                    order_type: OrderType::Limit,
+                    side: OrderSide::Buy,
+                    time_in_force: Some(TimeInForce::GTC),
+                    ..OrderRequest::default()
+                })
+                .await
+                .unwrap();
+            assert_eq!(order.status, OrderStatus::New);
+            resting.push(order.order_id);
+        }
+        let open = api.open_orders("ETHUSDT").await.unwrap();
+        assert_eq!(open.iter().map(|o| o.order_id).collect::<Vec<_>>(), resting);
+
+        let canceled = api
+            .cancel_order(OrderStatusRequest {
+                symbol: "ETHUSDT".to_string(),
+                order_id: Some(resting[0]),
+                orig_client_order_id: None,
+                recv_window: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(canceled.status, OrderStatus::Canceled);
+        assert_eq!(canceled.executed_qty, 0.0);
+        // a canceled order can't be canceled again
+        match api
+            .cancel_order(OrderStatusRequest {
+                symbol: "ETHUSDT".to_string(),
+                order_id: Some(resting[0]),
+                orig_client_order_id: None,
+                recv_window: None,
+            })
+            .await
+        {
+            Err(Error::BinanceError { response }) => assert_eq!(response.code, -2011),
+            other => panic!("unexpected {:?}", other),
+        }
+        let all = api.cancel_all_open_orders("ETHUSDT").await.unwrap();
+        assert_eq!(all.len(), 1);
+        assert_eq!(all[0].order_id, resting[1]);
+        assert!(api.open_orders("ETHUSDT").await.unwrap().is_empty());
+
+        // orders can be looked up and canceled by client order id
+        let by_client = |client_order_id: &str| OrderStatusRequest {
+            symbol: "ETHUSDT".to_string(),
+            order_id: None,
+            orig_client_order_id: Some(client_order_id.to_string()),
+            recv_window: None,
+        };
+        let client_order = OrderRequest {
+            symbol: "ETHUSDT".to_string(),
+            quantity: Some(1.0),
+            price: Some(3200.0),
+            order_type: OrderType::Limit,
+            side: OrderSide::Buy,
+            time_in_force: Some(TimeInForce::GTC),
+            new_client_order_id: Some("arb_7_1".to_string()),
+            ..OrderRequest::default()
+        };
+        let placed = api.place_order(client_order.clone()).await.unwrap();
+        assert_eq!(placed.client_order_id, "arb_7_1");
+        let found = api.order_status(by_client("arb_7_1")).await.unwrap();
+        assert_eq!(found.order_id, placed.order_id);
+        match api.place_order(client_order).await {
+            Err(Error::BinanceError { response }) => assert_eq!(response.code, -2010),
+            other => panic!("unexpected {:?}", other),
+        }
+        let canceled = api.cancel_order(by_client("arb_7_1")).await.unwrap();
+        assert_eq!(canceled.order_id, placed.order_id);
+        match api.order_status(by_client("arb_7_2")).await {
+            Err(Error::BinanceError { response }) => assert_eq!(response.code, -2013),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // partially filled COIN-M order keeps its fill when canceled
+        mock.script_order(Market::Delivery, OrderScript::PartialFill(0.5));
+        let partial = api
+            .delivery_place_order(limit(
+                "ETHUSD_240628",
+                OrderSide::Sell,
+                10.0,
+                3710.0,
+                TimeInForce::GTC,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            api.delivery_open_orders("ETHUSD_240628")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        let canceled = api
+            .delivery_cancel_order(FuturesGetOrderRequest {
+                symbol: "ETHUSD_240628".to_string(),
+                order_id: Some(partial.order_id.to_string()),
+                orig_client_order_id: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(canceled.status, "CANCELED");
+        assert_eq!(canceled.executed_qty, 5.0);
+
+        api.delivery_place_order(limit(
+            "ETHUSD_240628",
+            OrderSide::Sell,
+            10.0,
+            3800.0,
+            TimeInForce::GTC,
+        ))
+        .await
+        .unwrap();
+        api.delivery_cancel_all_open_orders("ETHUSD_240628")
+            .await
+            .unwrap();
+        assert!(api
+            .delivery_open_orders("ETHUSD_240628")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_klines() {
+        let mock = MockExchange::start().await.unwrap();
+        let klines: Vec<KlineSummary> = (0..10)
+            .map(|i| KlineSummary {
+                open_time: i * 900_000,
+                open: 1.0,
+                high: 1.001,
+                low: 0.999,
+                close: 1.0 + i as f64 / 10_000.0,
+                volume: 100.0,
+                close_time: (i + 1) * 900_000 - 1,
+                quote_asset_volume: 100.0,
+                number_of_trades: 10,
+                taker_buy_base_asset_volume: 50.0,
+                taker_buy_quote_asset_volume: 50.0,
+            })
+            .collect();
+        mock.set_klines("FDUSDUSDT", klines.clone());
+
+        let KlineSummaries::AllKlineSummaries(got) = mock
+            .api()
+            .get_klines("FDUSDUSDT", "15m", 3, Some(4 * 900_000), None)
+            .await
+            .unwrap();
+        assert_eq!(got, klines[4..7].to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_account_and_symbols() {
+        let mock = MockExchange::start().await.unwrap();
+        mock.set_price(Market::Delivery, "ETHUSD_240628", 3700.0);
+        mock.set_contract_size("ETHUSD_240628", 10.0);
+        mock.list_symbol(Market::Spot, "ETHUSDT", "0.01", "0.0001");
+        mock.list_symbol(Market::Delivery, "ETHUSD_240628", "0.01", "1");
+        mock.set_balance(Market::Spot, "USDT", 5000.0);
+        mock.set_balance(Market::Delivery, "ETH", 1.5);
+        let api = mock.api();
+
+        let spot = api.exchange_info().await.unwrap();
+        assert_eq!(spot.symbols.len(), 1);
+        assert_eq!(spot.symbols[0].base_asset, "ETH");
+        let delivery = api.delivery_exchange_info().await.unwrap();
+        assert_eq!(delivery.symbols[0].pair, "ETHUSD");
+        assert_eq!(delivery.symbols[0].contract_type, "CURRENT_QUARTER");
+        assert_eq!(delivery.symbols[0].contract_size, 10);
+        assert!(api
+            .futures_exchange_info()
+            .await
+            .unwrap()
+            .symbols
+            .is_empty());
+
+        let account = api.account_information().await.unwrap();
+        assert_eq!(account.balances[0].asset, "USDT");
+        assert_eq!(account.balances[0].free, 5000.0);
+        let account = api.delivery_account().await.unwrap();
+        assert_eq!(account.assets[0].available_balance, 1.5);
+
+        api.delivery_place_order(limit(
+            "ETHUSD_240628",
+            OrderSide::Sell,
+            5.0,
+            3700.0,
+            TimeInForce::FOK,
+        ))
+        .await
+        .unwrap();
+        let positions = api.delivery_position_risk("ETHUSD").await.unwrap();
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions[0].position_amt, -5.0);
+        assert!(api
+            .delivery_position_risk("BTCUSD")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_mini_ticker_stream() {
+        let mock = MockExchange::start().await.unwrap();
+        mock.set_price_path(
+            Market::Delivery,
+            "BTCUSD_PERP",
+            vec![67000.0, 67100.0, 67200.0],
+        );
+        let config = mock.config();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let client = tokio::spawn(async move {
+            let keep_running = AtomicBool::new(true);
+            let mut web_socket: WebSockets<'_, Vec<WebsocketEvent>> = WebSockets::new_with_options(
+                |events: Vec<WebsocketEvent>| {
+                    for event in events {
+                        if let WebsocketEvent::DayMiniTicker(tick) = event {
+                            if tx.send(tick.current_close).is_err() {
+                                keep_running.store(false, Ordering::Relaxed);
+                            }
+                        }
+                    }
+                    Ok(())
+                },
+                config,
+            );
+            web_socket
+                .connect_delivery(all_mini_ticker_stream())
+                .await
+                .unwrap();
+            let _ = web_socket.event_loop(&keep_running).await;
+        });
+
+        // wait for the subscription before publishing
+        tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+        while mock.advance() {}
+
+        let mut closes = vec![];
+        for _ in 0..3 {
+            closes.push(rx.recv().await.unwrap());
+        }
+        assert_eq!(closes, vec!["67000", "67100", "67200"]);
+        client.abort();
+    }
+
+    /// Runs a supervised mini ticker stream of `market`, forwarding every close price
+    fn supervised_ticker(
+        config: Config,
+        market: &'static str,
+        policy: ReconnectPolicy,
+    ) -> (
+        tokio::task::JoinHandle<()>,
+        tokio::sync::mpsc::UnboundedReceiver<String>,
+    ) {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let client = tokio::spawn(async move {
+            let keep_running = AtomicBool::new(true);
+            let mut web_socket: WebSockets<'_, Vec<WebsocketEvent>> = WebSockets::new_with_options(
+                |events: Vec<WebsocketEvent>| {
+                    for event in events {
+                        if let WebsocketEvent::DayMiniTicker(tick) = event {
+                            if tx.send(tick.current_close).is_err() {
+                                keep_running.store(false, Ordering::Relaxed);
+                            }
+                        }
+                    }
+                    Ok(())
+                },
+                config,
+            );
+            let _ = web_socket
+                .run_forever(market, all_mini_ticker_stream(), &keep_running, policy)
+                .await;
+        });
+        (client, rx)
+    }
+
+    /// Publishes `price` until the client receives it
+    async fn publish_until_received(
+        mock: &MockExchange,
+        market: Market,
+        price: f64,
+        rx: &mut tokio::sync::mpsc::UnboundedReceiver<String>,
+    ) {
+        let expected = price.to_string();
+        for _ in 0..100 {
+            mock.set_price(market, "BTCUSDT", price);
+            let timeout = tokio::time::Duration::from_millis(50);
+            while let Ok(Some(close)) = tokio::time::timeout(timeout, rx.recv()).await {
+                if close == expected {
+                    return;
+                }
+            }
+        }
+        panic!("{expected} never received");
+    }
+
+    #[tokio::test]
+    async fn test_supervised_stream_reconnects() {
+        let mock = MockExchange::start().await.unwrap();
+        let policy = ReconnectPolicy {
+            initial_backoff: tokio::time::Duration::from_millis(50),
+            ..ReconnectPolicy::default()
+        };
+        let (client, mut rx) = supervised_ticker(mock.config(), "futures", policy);
+
+        let metrics = stream_metrics(&format!("futures:{}", all_mini_ticker_stream()));
+        publish_until_received(&mock, Market::Futures, 67000.0, &mut rx).await;
+        for (i, price) in [67100.0, 67200.0].into_iter().enumerate() {
+            mock.drop_websockets();
+            while metrics.connects.load(Ordering::Relaxed) < i as u64 + 2 {
+                tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+            }
+            publish_until_received(&mock, Market::Futures, price, &mut rx).await;
+        }
+
+        assert_eq!(metrics.reconnects.load(Ordering::Relaxed), 2);
+        client.abort();
+    }
+
+    #[tokio::test]
+    async fn test_supervised_stream_rotates() {
+        let mock = MockExchange::start().await.unwrap();
+        let policy = ReconnectPolicy {
+            max_connection_age: tokio::time::Duration::from_millis(200),
+            ping_interval: tokio::time::Duration::from_millis(50),
+            ..ReconnectPolicy::default()
+        };
+        let (client, mut rx) = supervised_ticker(mock.config(), "spot", policy);
+
+        publish_until_received(&mock, Market::Spot, 67000.0, &mut rx).await;
+        tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+        publish_until_received(&mock, Market::Spot, 67100.0, &mut rx).await;
+
+        let metrics = stream_metrics(&format!("spot:{}", all_mini_ticker_stream()));
+        assert!(metrics.rotations.load(Ordering::Relaxed) >= 1);
+        assert_eq!(metrics.reconnects.load(Ordering::Relaxed), 0);
+        client.abort();
+    }
+}
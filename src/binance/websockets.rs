@@ -1,8 +1,15 @@
-use std::sync::atomic::{AtomicBool, Ordering};
-
-use futures::StreamExt;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use dashmap::DashMap;
+use futures::{SinkExt, StreamExt};
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use serde::Serialize;
 use serde_json::from_str;
 use tokio::net::TcpStream;
+use tokio::time::Instant;
 use tokio_tungstenite::tungstenite::handshake::client::Response;
 use tokio_tungstenite::tungstenite::Message;
 use tokio_tungstenite::WebSocketStream;
@@ -65,6 +72,95 @@ pub fn diff_book_depth_stream(symbol: &str, update_speed: u16) -> String { forma
 
 fn combined_stream(streams: Vec<String>) -> String { streams.join("/") }
 
+/// How a supervised connection reconnects and checks liveness
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// First delay after a failure, doubled on every consecutive failure
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Interval of the client pings
+    pub ping_interval: Duration,
+    /// The connection is considered dead when nothing, not even a pong, arrives for this long
+    pub liveness_timeout: Duration,
+    /// Binance drops connections after 24 hours, reconnect before that
+    pub max_connection_age: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            ping_interval: Duration::from_secs(30),
+            liveness_timeout: Duration::from_secs(90),
+            max_connection_age: Duration::from_secs(23 * 3600),
+        }
+    }
+}
+
+/// Counters of a supervised connection
+#[derive(Debug, Default)]
+pub struct StreamMetrics {
+    pub connects: AtomicU64,
+    /// Reconnects after an error, proactive rotations are not counted
+    pub reconnects: AtomicU64,
+    pub rotations: AtomicU64,
+    pub messages: AtomicU64,
+    /// Local time of the last message in milliseconds
+    pub last_message_time: AtomicI64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamMetricsSnapshot {
+    pub stream: String,
+    pub connects: u64,
+    pub reconnects: u64,
+    pub rotations: u64,
+    pub messages: u64,
+    pub last_message_time: i64,
+}
+
+static STREAM_METRICS: Lazy<DashMap<String, Arc<StreamMetrics>>> = Lazy::new(DashMap::new);
+
+/// Metrics of the supervised connection named `stream`, created on first use
+pub fn stream_metrics(stream: &str) -> Arc<StreamMetrics> {
+    STREAM_METRICS.entry(stream.to_string()).or_default().clone()
+}
+
+/// Metrics of every supervised connection sorted by name
+pub fn all_stream_metrics() -> Vec<StreamMetricsSnapshot> {
+    let mut list: Vec<StreamMetricsSnapshot> = STREAM_METRICS
+        .iter()
+        .map(|m| StreamMetricsSnapshot {
+            stream: m.key().clone(),
+            connects: m.connects.load(Ordering::Relaxed),
+            reconnects: m.reconnects.load(Ordering::Relaxed),
+            rotations: m.rotations.load(Ordering::Relaxed),
+            messages: m.messages.load(Ordering::Relaxed),
+            last_message_time: m.last_message_time.load(Ordering::Relaxed),
+        })
+        .collect();
+    list.sort_by(|a, b| a.stream.cmp(&b.stream));
+    list
+}
+
+/// Why a supervised session ended
+enum SessionEnd {
+    Stopped,
+    Rotate,
+    /// The connection broke, reconnect
+    Lost(Error),
+    /// The handler failed, give up
+    Failed(Error),
+}
+
+/// What a supervised connection subscribes to
+#[derive(Clone, Copy)]
+enum StreamTarget<'s> {
+    Single(&'s str),
+    Combined(&'s [String]),
+}
+
 pub struct WebSockets<'a, WE> {
     pub socket: Option<(WebSocketStream<MaybeTlsStream<TcpStream>>, Response)>,
     handler: Box<dyn FnMut(WE) -> Result<()> + 'a + Send>,
@@ -108,6 +204,24 @@ impl<'a, WE: serde::de::DeserializeOwned> WebSockets<'a, WE> {
         self.handle_connect(url).await
     }
 
+    /// Connect to multiple websocket endpoints of `market`: spot, futures or delivery
+    /// N.B: WE has to be CombinedStreamEvent
+    pub async fn connect_multiple_market(&mut self, market: &str, endpoints: &[String]) -> Result<()> {
+        let ws_endpoint = match market {
+            "spot" => &self.conf.ws_endpoint,
+            "futures" => &self.conf.futures_ws_endpoint,
+            "delivery" => &self.conf.delivery_ws_endpoint,
+            _ => return Err(Error::Msg(format!("unknown market {market}"))),
+        };
+        let mut url = Url::parse(ws_endpoint)?;
+        url.path_segments_mut()
+            .map_err(|_| Error::UrlParserError(url::ParseError::RelativeUrlWithoutBase))?
+            .push(STREAM_ENDPOINT);
+        url.set_query(Some(&format!("streams={}", combined_stream(endpoints.to_vec()))));
+
+        self.handle_connect(url).await
+    }
+
     /// Connect to a websocket endpoint
     pub async fn connect(&mut self, endpoint: &str) -> Result<()> {
         let wss: String = format!("{}/{}/{}", self.conf.ws_endpoint, WS_ENDPOINT, endpoint);
@@ -132,6 +246,158 @@ impl<'a, WE: serde::de::DeserializeOwned> WebSockets<'a, WE> {
         self.handle_connect(url).await
     }
 
+    /// Connect to the endpoint of `market`: spot, futures or delivery
+    pub async fn connect_market(&mut self, market: &str, endpoint: &str) -> Result<()> {
+        match market {
+            "spot" => self.connect(endpoint).await,
+            "futures" => self.connect_futures(endpoint).await,
+            "delivery" => self.connect_delivery(endpoint).await,
+            _ => Err(Error::Msg(format!("unknown market {market}"))),
+        }
+    }
+
+    /// Keeps a connection to `endpoint` of `market` open until `running` is cleared or the
+    /// handler fails.
+    ///
+    /// Lost connections are reopened with exponential backoff, silent connections are detected
+    /// with pings and every connection is rotated before Binance's 24 hour disconnect. Counters
+    /// are published under `{market}:{endpoint}`, see [`all_stream_metrics`].
+    pub async fn run_forever(
+        &mut self,
+        market: &str,
+        endpoint: &str,
+        running: &AtomicBool,
+        policy: ReconnectPolicy,
+    ) -> Result<()> {
+        self.run_supervised(market, StreamTarget::Single(endpoint), running, policy).await
+    }
+
+    /// Same as [`WebSockets::run_forever`] for a combined stream of `endpoints`, counters are
+    /// published under `{market}:{endpoint1/endpoint2/...}`
+    /// N.B: WE has to be CombinedStreamEvent
+    pub async fn run_forever_multiple(
+        &mut self,
+        market: &str,
+        endpoints: &[String],
+        running: &AtomicBool,
+        policy: ReconnectPolicy,
+    ) -> Result<()> {
+        self.run_supervised(market, StreamTarget::Combined(endpoints), running, policy).await
+    }
+
+    async fn run_supervised(
+        &mut self,
+        market: &str,
+        target: StreamTarget<'_>,
+        running: &AtomicBool,
+        policy: ReconnectPolicy,
+    ) -> Result<()> {
+        let name = match target {
+            StreamTarget::Single(endpoint) => format!("{market}:{endpoint}"),
+            StreamTarget::Combined(endpoints) => format!("{market}:{}", combined_stream(endpoints.to_vec())),
+        };
+        let metrics = stream_metrics(&name);
+        let mut backoff = policy.initial_backoff;
+        while running.load(Ordering::Relaxed) {
+            let connected = match target {
+                StreamTarget::Single(endpoint) => self.connect_market(market, endpoint).await,
+                StreamTarget::Combined(endpoints) => self.connect_multiple_market(market, endpoints).await,
+            };
+            let end = match connected {
+                Ok(_) => {
+                    metrics.connects.fetch_add(1, Ordering::Relaxed);
+                    let messages = metrics.messages.load(Ordering::Relaxed);
+                    let end = self.supervised_loop(running, &policy, &metrics).await;
+                    // the connection worked, start over with the shortest delay
+                    if metrics.messages.load(Ordering::Relaxed) > messages {
+                        backoff = policy.initial_backoff;
+                    }
+                    end
+                }
+                Err(e) => SessionEnd::Lost(e),
+            };
+            if let Some((ref mut socket, _)) = self.socket {
+                let _ = socket.close(None).await;
+            }
+            self.socket = None;
+
+            match end {
+                SessionEnd::Stopped => break,
+                SessionEnd::Rotate => {
+                    metrics.rotations.fetch_add(1, Ordering::Relaxed);
+                    info!("{name} websocket rotated");
+                }
+                SessionEnd::Failed(e) => return Err(e),
+                SessionEnd::Lost(e) => {
+                    metrics.reconnects.fetch_add(1, Ordering::Relaxed);
+                    warn!("{name} websocket lost: {e}, reconnecting in {backoff:?}");
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(policy.max_backoff);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn supervised_loop(
+        &mut self,
+        running: &AtomicBool,
+        policy: &ReconnectPolicy,
+        metrics: &StreamMetrics,
+    ) -> SessionEnd {
+        let Some((ref mut socket, _)) = self.socket else {
+            return SessionEnd::Lost(Error::Msg("Not connected".to_string()));
+        };
+        let rotate_at = Instant::now() + policy.max_connection_age;
+        let mut last_seen = Instant::now();
+        let mut ping = tokio::time::interval_at(Instant::now() + policy.ping_interval, policy.ping_interval);
+
+        while running.load(Ordering::Relaxed) {
+            let message = tokio::select! {
+                message = socket.next() => message,
+                _ = ping.tick() => {
+                    if last_seen.elapsed() > policy.liveness_timeout {
+                        return SessionEnd::Lost(Error::Msg("No message within the liveness timeout".to_string()));
+                    }
+                    if let Err(e) = socket.send(Message::Ping(vec![])).await {
+                        return SessionEnd::Lost(e.into());
+                    }
+                    continue;
+                }
+                _ = tokio::time::sleep_until(rotate_at) => return SessionEnd::Rotate,
+            };
+
+            let message = match message {
+                Some(Ok(message)) => message,
+                Some(Err(e)) => return SessionEnd::Lost(e.into()),
+                None => return SessionEnd::Lost(Error::Msg("Stream ended".to_string())),
+            };
+            last_seen = Instant::now();
+            match message {
+                Message::Text(msg) => {
+                    metrics.messages.fetch_add(1, Ordering::Relaxed);
+                    metrics
+                        .last_message_time
+                        .store(chrono::Local::now().timestamp_millis(), Ordering::Relaxed);
+                    // a message we cannot parse does not make the connection unusable
+                    let event: WE = match from_str(msg.as_str()) {
+                        Ok(event) => event,
+                        Err(e) => {
+                            warn!("websocket message parse error: {e}");
+                            continue;
+                        }
+                    };
+                    if let Err(e) = (self.handler)(event) {
+                        return SessionEnd::Failed(e);
+                    }
+                }
+                Message::Close(e) => return SessionEnd::Lost(Error::Msg(format!("Disconnected {e:?}"))),
+                Message::Ping(_) | Message::Pong(_) | Message::Binary(_) | Message::Frame(_) => {}
+            }
+        }
+        SessionEnd::Stopped
+    }
+
     async fn handle_connect(&mut self, url: Url) -> Result<()> {
         match connect_async(url).await {
             Ok(answer) => {
@@ -157,8 +423,10 @@ impl<'a, WE: serde::de::DeserializeOwned> WebSockets<'a, WE> {
     pub async fn event_loop(&mut self, running: &AtomicBool) -> Result<()> {
         while running.load(Ordering::Relaxed) {
             if let Some((ref mut socket, _)) = self.socket {
-                // TODO: return error instead of panic?
-                let message = socket.next().await.unwrap()?;
+                let message = match socket.next().await {
+                    Some(message) => message?,
+                    None => return Err(Error::Msg("Stream ended".to_string())),
+                };
 
                 match message {
                     Message::Text(msg) => {
@@ -1,10 +1,31 @@
 use serde::{Serialize, Deserialize};
 
+use crate::binance::rest_model::PriceLevel;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "e")]
 pub enum WebsocketEvent {
     #[serde(alias = "24hrMiniTicker")]
     DayMiniTicker(Box<MiniDayTickerEvent>),
+    #[serde(alias = "bookTicker")]
+    BookTicker(Box<BookTickerEvent>),
+    #[serde(alias = "depthUpdate")]
+    DepthOrderBook(Box<DepthOrderBookEvent>),
+    #[serde(alias = "markPriceUpdate")]
+    MarkPriceUpdate(Box<MarkPriceEvent>),
+    #[serde(alias = "executionReport")]
+    OrderUpdate(Box<OrderUpdate>),
+    #[serde(alias = "outboundAccountPosition")]
+    AccountPositionUpdate(Box<AccountPositionUpdate>),
+    #[serde(alias = "ORDER_TRADE_UPDATE")]
+    OrderTradeUpdate(Box<OrderTradeEvent>),
+    #[serde(alias = "ACCOUNT_UPDATE")]
+    AccountUpdate(Box<AccountUpdateEvent>),
+    #[serde(alias = "listenKeyExpired")]
+    ListenKeyExpired(ListenKeyExpiredEvent),
+    /// Events this crate does not model, e.g. `balanceUpdate` or `TRADE_LITE`
+    #[serde(other)]
+    Unknown,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -28,4 +49,259 @@ pub struct MiniDayTickerEvent {
     pub volume: String,
     #[serde(rename = "q")]
     pub quote_volume: String,
-}
\ No newline at end of file
+}
+
+/// Best bid and ask, `<symbol>@bookTicker` or futures `!bookTicker`
+///
+/// Spot book tickers carry no `e`, `E` or `T` field and only come as the `data` of a
+/// [`CombinedStreamEvent`]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BookTickerEvent {
+    #[serde(rename = "u")]
+    pub update_id: u64,
+    #[serde(rename = "E")]
+    pub event_time: Option<u64>,
+    #[serde(rename = "T")]
+    pub transaction_time: Option<u64>,
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "b")]
+    pub best_bid: String,
+    #[serde(rename = "B")]
+    pub best_bid_qty: String,
+    #[serde(rename = "a")]
+    pub best_ask: String,
+    #[serde(rename = "A")]
+    pub best_ask_qty: String,
+}
+
+/// Diff. depth `<symbol>@depth@100ms`
+///
+/// Futures updates also carry `T` and `pu`, the final update id of the previous event
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DepthOrderBookEvent {
+    #[serde(rename = "E")]
+    pub event_time: u64,
+    #[serde(rename = "T")]
+    pub transaction_time: Option<u64>,
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "U")]
+    pub first_update_id: u64,
+    #[serde(rename = "u")]
+    pub final_update_id: u64,
+    #[serde(rename = "pu")]
+    pub previous_final_update_id: Option<u64>,
+    #[serde(rename = "b")]
+    pub bids: Vec<PriceLevel>,
+    #[serde(rename = "a")]
+    pub asks: Vec<PriceLevel>,
+}
+
+/// Futures `<symbol>@markPrice@1s`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MarkPriceEvent {
+    #[serde(rename = "E")]
+    pub event_time: u64,
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "p")]
+    pub mark_price: String,
+    #[serde(rename = "i")]
+    pub index_price: Option<String>,
+    #[serde(rename = "P")]
+    pub estimated_settle_price: String,
+    /// Predicted rate of the next funding, empty for delivery contracts
+    #[serde(rename = "r")]
+    pub funding_rate: String,
+    #[serde(rename = "T")]
+    pub next_funding_time: u64,
+}
+
+/// Message of a combined stream, `/stream?streams=...`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CombinedStreamEvent<T> {
+    pub stream: String,
+    pub data: T,
+}
+
+/// Spot `executionReport`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OrderUpdate {
+    #[serde(rename = "E")]
+    pub event_time: u64,
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "c")]
+    pub client_order_id: String,
+    #[serde(rename = "S")]
+    pub side: String,
+    #[serde(rename = "o")]
+    pub order_type: String,
+    #[serde(rename = "f")]
+    pub time_in_force: String,
+    #[serde(rename = "q")]
+    pub qty: String,
+    #[serde(rename = "p")]
+    pub price: String,
+    #[serde(rename = "C")]
+    pub orig_client_order_id: String,
+    #[serde(rename = "x")]
+    pub execution_type: String,
+    #[serde(rename = "X")]
+    pub order_status: String,
+    #[serde(rename = "r")]
+    pub order_reject_reason: String,
+    #[serde(rename = "i")]
+    pub order_id: u64,
+    #[serde(rename = "l")]
+    pub qty_last_executed: String,
+    #[serde(rename = "z")]
+    pub cumulative_filled_qty: String,
+    #[serde(rename = "L")]
+    pub last_executed_price: String,
+    #[serde(rename = "n")]
+    pub commission: String,
+    #[serde(rename = "N")]
+    pub commission_asset: Option<String>,
+    #[serde(rename = "T")]
+    pub trade_order_time: u64,
+    #[serde(rename = "t")]
+    pub trade_id: i64,
+    #[serde(rename = "Z")]
+    pub cumulative_quote_qty: String,
+}
+
+/// Spot `outboundAccountPosition`, sent whenever a balance changes
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AccountPositionUpdate {
+    #[serde(rename = "E")]
+    pub event_time: u64,
+    #[serde(rename = "u")]
+    pub last_update_time: u64,
+    #[serde(rename = "B")]
+    pub balances: Vec<EventBalance>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EventBalance {
+    #[serde(rename = "a")]
+    pub asset: String,
+    #[serde(rename = "f")]
+    pub free: String,
+    #[serde(rename = "l")]
+    pub locked: String,
+}
+
+/// USDⓈ-M and COIN-M `ORDER_TRADE_UPDATE`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OrderTradeEvent {
+    #[serde(rename = "E")]
+    pub event_time: u64,
+    #[serde(rename = "T")]
+    pub transaction_time: u64,
+    #[serde(rename = "o")]
+    pub order: OrderTradeUpdate,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OrderTradeUpdate {
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "c")]
+    pub client_order_id: String,
+    #[serde(rename = "S")]
+    pub side: String,
+    #[serde(rename = "o")]
+    pub order_type: String,
+    #[serde(rename = "f")]
+    pub time_in_force: String,
+    #[serde(rename = "q")]
+    pub original_qty: String,
+    #[serde(rename = "p")]
+    pub original_price: String,
+    #[serde(rename = "ap")]
+    pub average_price: String,
+    #[serde(rename = "x")]
+    pub execution_type: String,
+    #[serde(rename = "X")]
+    pub order_status: String,
+    #[serde(rename = "i")]
+    pub order_id: u64,
+    #[serde(rename = "l")]
+    pub order_last_filled_qty: String,
+    /// Quantity for USDⓈ-M, contracts for COIN-M
+    #[serde(rename = "z")]
+    pub order_filled_accumulated_qty: String,
+    #[serde(rename = "L")]
+    pub last_filled_price: String,
+    #[serde(rename = "N", default)]
+    pub commission_asset: Option<String>,
+    #[serde(rename = "n", default)]
+    pub commission: Option<String>,
+    #[serde(rename = "T")]
+    pub order_trade_time: u64,
+    #[serde(rename = "t")]
+    pub trade_id: i64,
+    #[serde(rename = "ps")]
+    pub position_side: String,
+    #[serde(rename = "rp", default)]
+    pub realized_profit: Option<String>,
+}
+
+/// USDⓈ-M and COIN-M `ACCOUNT_UPDATE`, only changed balances and positions are sent
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AccountUpdateEvent {
+    #[serde(rename = "E")]
+    pub event_time: u64,
+    #[serde(rename = "T")]
+    pub transaction_time: u64,
+    #[serde(rename = "a")]
+    pub data: AccountUpdateData,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AccountUpdateData {
+    #[serde(rename = "m")]
+    pub reason: String,
+    #[serde(rename = "B")]
+    pub balances: Vec<AccountUpdateBalance>,
+    #[serde(rename = "P")]
+    pub positions: Vec<AccountUpdatePosition>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AccountUpdateBalance {
+    #[serde(rename = "a")]
+    pub asset: String,
+    #[serde(rename = "wb")]
+    pub wallet_balance: String,
+    #[serde(rename = "cw")]
+    pub cross_wallet_balance: String,
+    /// Only sent by USDⓈ-M
+    #[serde(rename = "bc", default)]
+    pub balance_change: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AccountUpdatePosition {
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "pa")]
+    pub position_amount: String,
+    #[serde(rename = "ep")]
+    pub entry_price: String,
+    #[serde(rename = "up")]
+    pub unrealized_pnl: String,
+    #[serde(rename = "mt")]
+    pub margin_type: String,
+    #[serde(rename = "ps")]
+    pub position_side: String,
+}
+
+/// The spot stream sends `E` as a string, so only the key is kept
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ListenKeyExpiredEvent {
+    #[serde(rename = "listenKey", default)]
+    pub listen_key: Option<String>,
+}
@@ -1,3 +1,14 @@
+pub const PRICE_KEY: &str = "_binance_price_v1";
 
+pub const PAPER_STATE_KEY: &str = "paper_trading_state_v1";
 
-pub const PRICE_KEY: &str = "_binance_price_v1";
\ No newline at end of file
+pub const BALANCE_KEY: &str = "_binance_balance_v1";
+
+pub const POSITION_KEY: &str = "_binance_position_v1";
+
+pub const BOOK_TICKER_KEY: &str = "_binance_book_ticker_v1";
+
+pub const FUNDING_KEY: &str = "_binance_funding_v1";
+
+/// 存在时所有进程停止执行新的腿, 值为原因
+pub const HALT_KEY: &str = "arb_halt_trading_v1";
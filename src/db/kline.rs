@@ -0,0 +1,100 @@
+use crate::binance::rest_model::KlineSummary;
+use rocksdb::{Direction, IteratorMode, Options, WriteBatch, DB};
+use std::sync::Arc;
+
+/// RocksDB k线存储
+///
+/// 每个 market/symbol/interval 一个列族, key 为大端序的 open_time, value 为 bincode 编码的 k 线,
+/// 按 key 顺序遍历即按时间顺序
+#[derive(Debug, Clone)]
+pub struct KlineStore {
+    db: Arc<DB>,
+    /// 每个列族最多保留的k线数量
+    retention: usize,
+}
+
+pub fn cf_name(market: &str, symbol: &str, interval: &str) -> String {
+    format!("kline_{}_{}_{}", market, symbol, interval)
+}
+
+fn key(open_time: i64) -> [u8; 8] {
+    (open_time as u64).to_be_bytes()
+}
+
+impl KlineStore {
+    pub fn new(db: Arc<DB>, retention: usize) -> Self {
+        Self { db, retention }
+    }
+
+    fn cf(&self, name: &str) -> anyhow::Result<Arc<rocksdb::BoundColumnFamily<'_>>> {
+        if self.db.cf_handle(name).is_none() {
+            self.db.create_cf(name, &Options::default())?;
+        }
+        self.db
+            .cf_handle(name)
+            .ok_or_else(|| anyhow::anyhow!("column family {} not found", name))
+    }
+
+    /// 写入k线, 相同 open_time 覆盖, 超过保留数量时删除最早的
+    pub fn put(
+        &self,
+        market: &str,
+        symbol: &str,
+        interval: &str,
+        klines: &[KlineSummary],
+    ) -> anyhow::Result<()> {
+        if klines.is_empty() {
+            return Ok(());
+        }
+        let cf = self.cf(&cf_name(market, symbol, interval))?;
+        let mut batch = WriteBatch::default();
+        for kline in klines {
+            batch.put_cf(&cf, key(kline.open_time), bincode::serialize(kline)?);
+        }
+        self.db.write(batch)?;
+        self.trim(&cf)
+    }
+
+    fn trim(&self, cf: &Arc<rocksdb::BoundColumnFamily<'_>>) -> anyhow::Result<()> {
+        let keys = self
+            .db
+            .iterator_cf(cf, IteratorMode::Start)
+            .map(|item| item.map(|(k, _)| k))
+            .collect::<Result<Vec<_>, _>>()?;
+        if keys.len() <= self.retention {
+            return Ok(());
+        }
+        let mut batch = WriteBatch::default();
+        for k in &keys[..keys.len() - self.retention] {
+            batch.delete_cf(cf, k);
+        }
+        self.db.write(batch)?;
+        Ok(())
+    }
+
+    /// open_time 在 [start, end] 内的k线, 按时间升序
+    pub fn range(
+        &self,
+        market: &str,
+        symbol: &str,
+        interval: &str,
+        start: i64,
+        end: i64,
+    ) -> anyhow::Result<Vec<KlineSummary>> {
+        let cf = self.cf(&cf_name(market, symbol, interval))?;
+        let from = key(start);
+        let mut klines = Vec::new();
+        for item in self
+            .db
+            .iterator_cf(&cf, IteratorMode::From(&from, Direction::Forward))
+        {
+            let (_, value) = item?;
+            let kline: KlineSummary = bincode::deserialize(&value)?;
+            if kline.open_time > end {
+                break;
+            }
+            klines.push(kline);
+        }
+        Ok(klines)
+    }
+}
@@ -1,23 +1,36 @@
-use crate::conf;
+use crate::conf::Config;
 use anyhow::anyhow;
 use once_cell::sync::OnceCell;
-use rocksdb::{DBWithThreadMode, MultiThreaded, DB};
+use rocksdb::{DBWithThreadMode, MultiThreaded, Options, DB};
 use sqlx::mysql::MySqlPoolOptions;
 use sqlx::MySqlPool;
 use std::sync::Arc;
-use tokio::fs::remove_dir_all;
+
+pub mod kline;
+
+pub use kline::KlineStore;
 
 #[derive(Debug, Clone)]
 pub struct Db {
     db_pool: MySqlPool,
     redis: redis::Client,
     rocksdb: Arc<DBWithThreadMode<MultiThreaded>>,
+    klines: KlineStore,
 }
 
 pub static DBV1: OnceCell<Db> = OnceCell::new();
 
-pub async fn init_env() -> anyhow::Result<()> {
-    let db = Db::new().await?;
+pub async fn init_env(config: &Config) -> anyhow::Result<()> {
+    let db = Db::new(config).await?;
+    if let Err(e) = DBV1.set(db) {
+        panic!("{:?}", e);
+    }
+    Ok(())
+}
+
+/// 运维工具使用, RocksDB 以只读方式打开, 可以和运行中的 arbitrage 进程同时使用
+pub async fn init_env_read_only(config: &Config) -> anyhow::Result<()> {
+    let db = Db::open(config, true).await?;
     if let Err(e) = DBV1.set(db) {
         panic!("{:?}", e);
     }
@@ -32,19 +45,24 @@ pub fn get_db<'a>() -> anyhow::Result<&'a Db> {
 }
 
 impl Db {
-    pub async fn new() -> anyhow::Result<Self> {
+    pub async fn new(config: &Config) -> anyhow::Result<Self> {
+        Self::open(config, false).await
+    }
+
+    async fn open(config: &Config, read_only: bool) -> anyhow::Result<Self> {
         let db_pool = MySqlPoolOptions::new()
             .max_connections(5)
-            .connect(conf::C.mysql.url.as_str())
+            .connect(config.mysql.url.as_str())
             .await?;
-        let redis = redis::Client::open(conf::C.redis.url.as_str())?;
+        let redis = redis::Client::open(config.redis.url.as_str())?;
 
-        remove_dir_all(&conf::C.rocksdb.path).await?;
-        let rocksdb = Arc::new(DB::open_default(&conf::C.rocksdb.path).unwrap());
+        let rocksdb = Arc::new(open_rocksdb(&config.rocksdb.path, read_only)?);
+        let klines = KlineStore::new(rocksdb.clone(), config.rocksdb.kline_retention);
         Ok(Self {
             db_pool,
             redis,
             rocksdb,
+            klines,
         })
     }
 
@@ -52,6 +70,10 @@ impl Db {
         &self.rocksdb
     }
 
+    pub fn klines(&self) -> &KlineStore {
+        &self.klines
+    }
+
     pub fn database(&self) -> &MySqlPool {
         &self.db_pool
     }
@@ -63,3 +85,18 @@ impl Db {
             .map_err(|e| e.into())
     }
 }
+
+// 重启后保留数据, 已有的列族需要全部打开
+// 只读方式不占用进程锁, 数据库还不存在时按读写方式创建
+fn open_rocksdb(path: &str, read_only: bool) -> anyhow::Result<DB> {
+    let mut opts = Options::default();
+    opts.create_if_missing(true);
+    opts.create_missing_column_families(true);
+    let cfs = DB::list_cf(&opts, path).unwrap_or_else(|_| vec!["default".to_string()]);
+    if read_only {
+        if let Ok(db) = DB::open_cf_for_read_only(&opts, path, &cfs, false) {
+            return Ok(db);
+        }
+    }
+    Ok(DB::open_cf(&opts, path, cfs)?)
+}
@@ -1,4 +1,4 @@
-use crate::conf::C;
+use crate::conf::LogConfig;
 use chrono::Local;
 use env_logger::fmt::Color;
 use env_logger::Env;
@@ -7,20 +7,20 @@ use std::fs::File;
 use std::io::Write;
 use std::str::FromStr;
 
-pub fn init_log() {
+pub fn init_log(config: &LogConfig) {
     let env = Env::default().filter_or("MY_LOG_LEVEL", "debug");
 
     // 输出到控制台
-    let target = if C.log.pattern == "console" {
+    let target = if config.pattern == "console" {
         env_logger::Target::Stdout
     } else {
         // 输出到文件
-        let log_path = format!("{}/{}", C.log.dir, C.log.prefix);
+        let log_path = format!("{}/{}", config.dir, config.prefix);
         let t = Box::new(File::create(log_path).expect("Can't create file"));
         env_logger::Target::Pipe(t)
     };
 
-    let level = LevelFilter::from_str(C.log.level.as_str()).unwrap();
+    let level = LevelFilter::from_str(config.level.as_str()).unwrap();
 
     env_logger::Builder::from_env(env)
         .format(|buf, record| {
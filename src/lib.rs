@@ -1,12 +1,10 @@
 #![feature(slice_take)]
-// #[macro_use]
-extern crate lazy_static;
-
 #[macro_use]
 #[allow(unused_imports)]
 extern crate serde;
 extern crate serde_qs as qs;
 
+pub mod admin;
 pub mod binance;
 pub mod conf;
 pub mod db;
@@ -1,24 +1,35 @@
 #[macro_use]
 extern crate tokio;
 
-use arbitrage::conf;
-use arbitrage::service::PriceStream;
+use arbitrage::binance::ApiPool;
+use arbitrage::service::{BookTickerStream, PriceStream};
+use arbitrage::{admin, conf};
 use arbitrage::{db, helper, service};
 use futures::future::BoxFuture;
 use log::warn;
 use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::signal::unix::{signal, SignalKind};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // 初始化配置文件
-    lazy_static::initialize(&conf::C);
+    let config = Arc::new(conf::load_from_args()?);
     // 初始化Db
-    db::init_env().await?;
+    db::init_env(&config).await?;
     // 初始化日志
-    helper::log::init_log();
+    helper::log::init_log(&config.log);
+    // 每个用户的账户 api
+    let pool = Arc::new(ApiPool::new(config.clone()));
+    // 启动对账: 回写停机期间成交的订单和划转, 确认不一致的策略标记为需人工处理
+    service::reconcile_strategies(&pool).await?;
+
+    // 容器和 systemd 停止时发送 SIGTERM, 和 ctrl_c 一样优雅退出
+    let mut terminate = signal(SignalKind::terminate())?;
 
     let (close_tx, mut close_rx) = tokio::sync::mpsc::unbounded_channel::<bool>();
     let (price_tx, price_rx) = tokio::sync::mpsc::unbounded_channel::<PriceStream>();
+    let (book_tx, book_rx) = tokio::sync::mpsc::unbounded_channel::<BookTickerStream>();
 
     let wait_loop = tokio::spawn(async move {
         'hello: loop {
@@ -42,25 +53,47 @@ async fn main() -> anyhow::Result<()> {
         Box::pin(service::spot_all_ticker(price_tx.clone())),
         Box::pin(service::futures_all_ticker(price_tx.clone())),
         Box::pin(service::delivery_all_ticker(price_tx.clone())),
-        Box::pin(service::set_binance_diff_rate()),
+        Box::pin(service::set_binance_book_ticker(book_rx)), // 买一卖一, 计算可成交比率
+        Box::pin(service::spot_book_ticker(book_tx.clone())),
+        Box::pin(service::futures_book_ticker(book_tx.clone())),
+        Box::pin(service::delivery_book_ticker(book_tx.clone())),
+        Box::pin(service::order_book_stream(config.clone(), "spot")), // 本地订单簿, 按深度计算下单价格
+        Box::pin(service::order_book_stream(config.clone(), "futures")),
+        Box::pin(service::order_book_stream(config.clone(), "delivery")),
+        Box::pin(service::funding_rate_stream()), // 预测资金费率
+        Box::pin(service::inspect_funding_income(pool.clone())), // 资金费率策略累计资金费收入
+        Box::pin(service::set_binance_diff_rate(config.clone())),
         Box::pin(service::range_new_strategy()), //根据arb_strategy表创建arb_strategy_ex表
-        Box::pin(service::inspect_strategy(txs.clone())), // 轮训策略
+        Box::pin(service::user_data_stream(pool.clone(), "spot")), // 账户数据流, 订单成交实时回写
+        Box::pin(service::user_data_stream(pool.clone(), "futures")),
+        Box::pin(service::user_data_stream(pool.clone(), "delivery")),
+        Box::pin(admin::admin_server(config.clone())), // 管理接口
+        Box::pin(service::halt::watch_halt(config.clone())), // 停止交易开关
     ];
 
     for stream in streams {
         tokio::spawn(stream);
     }
+    // 轮训策略, 退出时停止分发
+    let dispatcher = tokio::spawn(service::inspect_strategy(txs));
 
     // 开始线程池
-    service::event_start(rxs).await;
+    let workers = service::event_start(pool.clone(), rxs).await;
 
     select! {
         _ = wait_loop => { warn!("Finished!") }
         _ = tokio::signal::ctrl_c() => {
             warn!("Closing websocket stream...");
             close_tx.send(true).unwrap();
-            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
         }
+        _ = terminate.recv() => {
+            warn!("SIGTERM, closing websocket stream...");
+            close_tx.send(true).unwrap();
+        }
+    }
+    // 停止分发, 等待进行中的下单/划转完成
+    if service::halt::shutdown(&config.shutdown, dispatcher, workers).await {
+        service::halt::close_open_orders(&pool).await;
     }
 
     Ok(())
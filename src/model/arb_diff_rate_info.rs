@@ -16,6 +16,10 @@ pub struct ArbDiffRateInfo {
     pub to_price: Decimal,
     pub diff_price: Decimal,
     pub diff_rate: Decimal,
+    /// 按买一卖一计算的可成交开仓比率
+    pub open_diff_rate: Decimal,
+    /// 按买一卖一计算的可成交平仓比率
+    pub close_diff_rate: Decimal,
     pub created: Option<i64>,
     pub updated: Option<i64>,
     pub bak: Option<String>,
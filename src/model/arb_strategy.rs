@@ -4,6 +4,8 @@ use serde::{Deserialize, Serialize};
 pub const DOING_STATUS_UN_RUN: i8 = 0;
 pub const DOING_STATUS_RUN: i8 = 1;
 pub const DOING_STATUS_DONE: i8 = 2;
+/// 启动对账无法确认状态, 停止交易等待人工处理
+pub const DOING_STATUS_MANUAL: i8 = 3;
 
 #[derive(Debug, Clone, Deserialize, Serialize, Default, sqlx::FromRow)]
 pub struct ArbStrategy {
@@ -33,6 +35,10 @@ pub struct ArbStrategy {
     pub futures_fee: Decimal,
     pub delivery_fee: Decimal,
     pub doing_status: i8,
+    /// 资金费率策略累计的资金费收入(计价币)
+    pub funding_income: Decimal,
+    /// 已累计到的资金费结算时间(毫秒)
+    pub funding_time: i64,
     pub created: Option<i64>,
     pub updated: Option<i64>,
     pub bak: Option<String>,
@@ -21,8 +21,12 @@ pub struct ArbStrategyExInfo {
     pub amount: Decimal,
     pub executed_amt: Decimal,
     pub order_id: String,
+    /// 下单前生成并落库, arb_{arb_strategy_ex_id}_{第几次下单}
+    pub client_order_id: String,
     pub is_ok: i8,
     pub created: Option<i64>,
     pub updated: Option<i64>,
     pub bak: Option<String>,
+    /// 1 模拟盘成交
+    pub simulated: i8,
 }
@@ -0,0 +1,197 @@
+//! 账户余额与持仓
+//!
+//! 下单和划转前检查可用资金, 资金不足时不发送请求, 避免执行到一半才收到币安报错;
+//! 每个用户使用自己账户的 api, 限频或密钥错误只暂停该用户
+
+use crate::binance::errors::Error;
+use crate::binance::paper::{delivery_margin_asset, futures_margin_asset};
+use crate::binance::rest_model::{FuturesAsset, OrderSide, PositionRisk, UniversalTransferType};
+use crate::binance::{ApiPool, MyApi};
+use crate::service::exchange_info;
+use anyhow::anyhow;
+use chrono::Local;
+use log::warn;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+
+/// user_id 对应账户的 api, 账户暂停期间返回错误
+pub fn user_api(pool: &ApiPool, user_id: i64) -> anyhow::Result<MyApi> {
+    if let Some(until) = pool.paused_until(user_id, Local::now().timestamp_millis()) {
+        return Err(anyhow!("user_id {} is paused until {}", user_id, until));
+    }
+    Ok(pool.get(user_id)?)
+}
+
+/// 限频或密钥被拒绝时暂停该用户的账户, 其他用户不受影响
+pub fn report_error(pool: &ApiPool, user_id: i64, e: &anyhow::Error) {
+    let Some(err) = e.downcast_ref::<Error>() else {
+        return;
+    };
+    if let Some(until) = pool.report(user_id, err, Local::now().timestamp_millis()) {
+        warn!("user_id {} paused until {}: {}", user_id, until, err);
+    }
+}
+
+/// 订单是否只减少已有持仓, 减仓不占用保证金
+pub fn reduces_position(position_amt: f64, side: &OrderSide, qty: f64) -> bool {
+    match side {
+        OrderSide::Buy => position_amt < 0.0 && qty <= -position_amt,
+        OrderSide::Sell => position_amt > 0.0 && qty <= position_amt,
+    }
+}
+
+/// 开仓所需保证金, 币本位 qty 为张数、保证金为币
+pub fn required_margin(
+    market: &str,
+    price: f64,
+    qty: f64,
+    contract_size: f64,
+    leverage: f64,
+) -> f64 {
+    let leverage = if leverage > 0.0 { leverage } else { 1.0 };
+    match market {
+        "delivery" => qty * contract_size / price / leverage,
+        _ => qty * price / leverage,
+    }
+}
+
+fn ensure(wallet: &str, asset: &str, available: f64, needed: f64) -> anyhow::Result<()> {
+    if available < needed {
+        return Err(anyhow!(
+            "insufficient {} in {} wallet, available: {}, needed: {}",
+            asset,
+            wallet,
+            available,
+            needed
+        ));
+    }
+    Ok(())
+}
+
+async fn spot_free(api: &MyApi, asset: &str) -> anyhow::Result<f64> {
+    let account = api.account_information().await?;
+    Ok(account
+        .balances
+        .iter()
+        .find(|b| b.asset == asset)
+        .map(|b| b.free)
+        .unwrap_or(0.0))
+}
+
+fn max_withdraw(assets: &[FuturesAsset], asset: &str) -> f64 {
+    assets
+        .iter()
+        .find(|a| a.asset == asset)
+        .map(|a| a.max_withdraw_amount)
+        .unwrap_or(0.0)
+}
+
+/// 现货买入检查计价币, 卖出检查 coin; 合约开仓检查可用保证金, 减仓不检查
+pub async fn check_order_funds(
+    api: &MyApi,
+    market: &str,
+    symbol: &str,
+    coin: &str,
+    side: &OrderSide,
+    price: Decimal,
+    qty: Decimal,
+) -> anyhow::Result<()> {
+    let price = price.to_f64().ok_or(anyhow!("invalid price: {}", price))?;
+    let qty = qty.to_f64().ok_or(anyhow!("invalid qty: {}", qty))?;
+    match market {
+        "spot" => {
+            let quote =
+                symbol
+                    .strip_prefix(coin)
+                    .ok_or(anyhow!("{} is not a {} symbol", symbol, coin))?;
+            let (asset, needed) = match side {
+                OrderSide::Buy => (quote, price * qty),
+                OrderSide::Sell => (coin, qty),
+            };
+            ensure(market, asset, spot_free(api, asset).await?, needed)
+        }
+        "futures" | "delivery" => {
+            let (positions, account, asset) = if market == "futures" {
+                (
+                    api.futures_position_risk(symbol).await?,
+                    api.futures_account().await?,
+                    futures_margin_asset(symbol).to_string(),
+                )
+            } else {
+                let pair = symbol.split('_').next().unwrap_or(symbol);
+                (
+                    api.delivery_position_risk(pair).await?,
+                    api.delivery_account().await?,
+                    delivery_margin_asset(symbol)?,
+                )
+            };
+            let position: Option<&PositionRisk> = positions.iter().find(|p| p.symbol == symbol);
+            let position_amt = position.map(|p| p.position_amt).unwrap_or(0.0);
+            if reduces_position(position_amt, side, qty) {
+                return Ok(());
+            }
+            let contract_size = exchange_info::symbol_filter(api, market, symbol)
+                .await?
+                .contract_size
+                .to_f64()
+                .unwrap_or(0.0);
+            let leverage = position.map(|p| p.leverage).unwrap_or(1.0);
+            let needed = required_margin(market, price, qty, contract_size, leverage);
+            let available = account
+                .assets
+                .iter()
+                .find(|a| a.asset == asset)
+                .map(|a| a.available_balance)
+                .unwrap_or(0.0);
+            ensure(market, asset.as_str(), available, needed)
+        }
+        _ => Err(anyhow!("unsupported market: {}", market)),
+    }
+}
+
+/// 划转检查转出钱包的可用/可转出数量
+pub async fn check_transfer_funds(
+    api: &MyApi,
+    asset: &str,
+    amount: Decimal,
+    transfer_type: &UniversalTransferType,
+) -> anyhow::Result<()> {
+    let amount = amount
+        .to_f64()
+        .ok_or(anyhow!("invalid amount: {}", amount))?;
+    let (wallet, available) = match transfer_type {
+        UniversalTransferType::MainUmfuture | UniversalTransferType::MainCmfuture => {
+            ("spot", spot_free(api, asset).await?)
+        }
+        UniversalTransferType::UmfutureMain => {
+            let account = api.futures_account().await?;
+            ("futures", max_withdraw(&account.assets, asset))
+        }
+        UniversalTransferType::CmfutureMain => {
+            let account = api.delivery_account().await?;
+            ("delivery", max_withdraw(&account.assets, asset))
+        }
+        _ => return Ok(()),
+    };
+    ensure(wallet, asset, available, amount)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_margin() {
+        assert!(reduces_position(-369.0, &OrderSide::Buy, 369.0));
+        assert!(!reduces_position(-369.0, &OrderSide::Buy, 370.0));
+        assert!(!reduces_position(-369.0, &OrderSide::Sell, 1.0));
+        assert!(reduces_position(0.5, &OrderSide::Sell, 0.2));
+        assert!(!reduces_position(0.0, &OrderSide::Sell, 0.2));
+
+        // 369 张 * 10 USD / 3700 / 20 倍
+        let margin = required_margin("delivery", 3700.0, 369.0, 10.0, 20.0);
+        assert!((margin - 369.0 * 10.0 / 3700.0 / 20.0).abs() < 1e-12);
+        assert_eq!(required_margin("futures", 3500.0, 2.0, 0.0, 10.0), 700.0);
+        assert_eq!(required_margin("futures", 3500.0, 2.0, 0.0, 0.0), 7000.0);
+    }
+}
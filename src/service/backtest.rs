@@ -0,0 +1,662 @@
+use crate::binance::rest_model::KlineSummary;
+use crate::model;
+use crate::service::diff_rate::calc_diff_rate;
+use crate::service::stable_coin_hedging::{boll_bands, boll_signal, StableSignal};
+use crate::service::state_machine::{LegAction, Signal, StrategyMachine};
+use anyhow::anyhow;
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::ops::{Add, Div, Mul, Sub};
+
+/// 回测行情点, time 为秒
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tick {
+    pub time: i64,
+    pub from_price: Decimal,
+    pub to_price: Decimal,
+    pub diff_rate: Decimal,
+}
+
+/// 单个策略的回测结果, 金额均为计价币(USDT/USD)
+#[derive(Debug, Clone, Default)]
+pub struct BacktestReport {
+    pub strategy_id: i64,
+    pub option_choose: String,
+    pub from_symbol: String,
+    pub to_symbol: String,
+    pub ticks: usize,
+    /// 完成的开平仓次数
+    pub round_trips: u32,
+    /// 已实现盈亏
+    pub pnl: Decimal,
+    /// 回测结束时未平仓位的浮动盈亏
+    pub unrealized_pnl: Decimal,
+    /// 权益(已实现 + 浮动)最大回撤
+    pub max_drawdown: Decimal,
+    /// 持仓时长(秒)
+    pub time_in_position: i64,
+    /// 回测区间时长(秒)
+    pub duration: i64,
+}
+
+/// 由 arb_diff_rate_his 还原两腿价格
+/// 正向 diff = to - from, rate = diff / from; 反向 diff = from - to, rate = diff / to.
+/// his 中 diff_rate 只保留3位小数, 还原的价格是近似值; rate 为 0 时沿用上一个点的价格
+pub fn ticks_from_his(
+    strategy: &model::ArbStrategy,
+    his_list: &[model::ArbDiffRateHis],
+) -> Vec<Tick> {
+    let mut ticks: Vec<Tick> = Vec::new();
+    for his in his_list {
+        let prices = match his.diff_price.checked_div(his.diff_rate) {
+            Some(base) if strategy.option_choose == "positive" => {
+                Some((base, base.add(his.diff_price)))
+            }
+            Some(base) => Some((base.add(his.diff_price), base)),
+            None => ticks.last().map(|t| (t.from_price, t.to_price)),
+        };
+        if let Some((from_price, to_price)) = prices {
+            ticks.push(Tick {
+                time: his.created.unwrap_or_default(),
+                from_price,
+                to_price,
+                diff_rate: his.diff_rate,
+            });
+        }
+    }
+    ticks
+}
+
+/// 按 open_time 对齐两腿 k 线, 用收盘价计算 diff_rate
+pub fn ticks_from_klines(
+    strategy: &model::ArbStrategy,
+    from_klines: &[KlineSummary],
+    to_klines: &[KlineSummary],
+) -> Vec<Tick> {
+    let to_map: HashMap<i64, &KlineSummary> = to_klines.iter().map(|k| (k.open_time, k)).collect();
+    from_klines
+        .iter()
+        .filter_map(|from| {
+            let to = to_map.get(&from.open_time)?;
+            let from_price = Decimal::from_f64(from.close)?;
+            let to_price = Decimal::from_f64(to.close)?;
+            let (_, diff_rate) =
+                calc_diff_rate(strategy.option_choose.as_str(), from_price, to_price);
+            Some(Tick {
+                time: from.close_time / 1000,
+                from_price,
+                to_price,
+                diff_rate,
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Book {
+    /// 现货 + 币本位
+    Positive,
+    /// U本位 远期 + 永续
+    ReverseUsdm,
+    /// 币本位 远期 + 永续
+    ReverseCoinm,
+}
+
+/// 开仓时的成交
+#[derive(Debug, Clone)]
+struct Position {
+    /// 现货数量 / U本位数量 / 币本位张数
+    from_qty: Decimal,
+    from_price: Decimal,
+    /// 币本位卖出张数 / U本位数量 / 币本位张数
+    to_qty: Decimal,
+    to_price: Decimal,
+}
+
+struct Backtest<'a> {
+    strategy: &'a model::ArbStrategy,
+    machine: StrategyMachine,
+    book: Book,
+}
+
+impl<'a> Backtest<'a> {
+    fn new(strategy: &'a model::ArbStrategy) -> anyhow::Result<Self> {
+        let machine = StrategyMachine::of(strategy)
+            .filter(|m| m.signal == Signal::DiffRate)
+            .ok_or(anyhow!("unsupported strategy: {}", strategy.id))?;
+        let book = match machine.legs[0].action {
+            LegAction::Spot(_) => Book::Positive,
+            LegAction::Futures(_) => Book::ReverseUsdm,
+            LegAction::Delivery(_) => Book::ReverseCoinm,
+            LegAction::Transfer(_) => return Err(anyhow!("unsupported strategy: {}", strategy.id)),
+        };
+        Ok(Backtest {
+            strategy,
+            machine,
+            book,
+        })
+    }
+
+    fn leg_price(&self, step: usize, tick: &Tick) -> Decimal {
+        self.machine.legs[step].price(self.strategy, tick.from_price, tick.to_price)
+    }
+
+    // 与实盘一致: 可开张数 = ceil(数量 * 最新价 / 合约面值) - 1
+    fn contracts(&self, amt: Decimal, last_price: Decimal) -> Decimal {
+        amt.mul(last_price)
+            .div(Decimal::from(self.strategy.contract_mul))
+            .ceil()
+            .sub(Decimal::ONE)
+    }
+
+    fn contract_value(&self, contracts: Decimal, price: Decimal) -> Decimal {
+        contracts
+            .mul(Decimal::from(self.strategy.contract_mul))
+            .checked_div(price)
+            .unwrap_or(Decimal::ZERO)
+    }
+
+    fn open(&self, tick: &Tick) -> Option<Position> {
+        let strategy = self.strategy;
+        let mut amt = strategy.option_amt.mul(Decimal::from(strategy.margin_mul));
+        amt.rescale(self.machine.legs[0].amt_truncate(strategy));
+        let position = match self.book {
+            Book::Positive => {
+                // 现货买入 -> 扣手续费划转 -> 币本位按张卖出
+                let mut coin = amt.mul(Decimal::ONE.sub(strategy.spot_fee));
+                coin.rescale(self.machine.legs[1].amt_truncate(strategy));
+                let contracts = self.contracts(coin, tick.to_price);
+                if contracts <= Decimal::ZERO {
+                    return None;
+                }
+                Position {
+                    from_qty: amt,
+                    from_price: self.leg_price(0, tick),
+                    to_qty: contracts,
+                    to_price: self.leg_price(2, tick),
+                }
+            }
+            Book::ReverseUsdm => Position {
+                from_qty: amt,
+                from_price: self.leg_price(0, tick),
+                to_qty: amt,
+                to_price: self.leg_price(1, tick),
+            },
+            Book::ReverseCoinm => {
+                let from_contracts = self.contracts(amt, tick.from_price);
+                let to_contracts = self.contracts(amt, tick.to_price);
+                if from_contracts <= Decimal::ZERO || to_contracts <= Decimal::ZERO {
+                    return None;
+                }
+                Position {
+                    from_qty: from_contracts,
+                    from_price: self.leg_price(0, tick),
+                    to_qty: to_contracts,
+                    to_price: self.leg_price(1, tick),
+                }
+            }
+        };
+        Some(position)
+    }
+
+    /// 按当前行情平仓的盈亏(计价币)
+    fn close_pnl(&self, position: &Position, tick: &Tick) -> Decimal {
+        let strategy = self.strategy;
+        match self.book {
+            Book::Positive => {
+                // 币本位买入平仓(空单以币计盈亏 = 平仓价值 - 开仓价值), 剩余币全部转回现货卖出
+                let buy_price = self.leg_price(3, tick);
+                let sell_price = self.leg_price(5, tick);
+                let mut coin = position.from_qty.mul(Decimal::ONE.sub(strategy.spot_fee));
+                coin.rescale(self.machine.legs[1].amt_truncate(strategy));
+                let open_value = self.contract_value(position.to_qty, position.to_price);
+                let close_value = self.contract_value(position.to_qty, buy_price);
+                let fee = open_value.add(close_value).mul(strategy.delivery_fee);
+                let coin_end = coin.add(close_value).sub(open_value).sub(fee);
+                let proceeds = coin_end
+                    .mul(sell_price)
+                    .mul(Decimal::ONE.sub(strategy.spot_fee));
+                proceeds.sub(position.from_qty.mul(position.from_price))
+            }
+            Book::ReverseUsdm => {
+                let to_buy = self.leg_price(2, tick);
+                let from_sell = self.leg_price(3, tick);
+                let pnl = position
+                    .from_qty
+                    .mul(from_sell.sub(position.from_price))
+                    .add(position.to_qty.mul(position.to_price.sub(to_buy)));
+                let turnover = position
+                    .from_qty
+                    .mul(position.from_price.add(from_sell))
+                    .add(position.to_qty.mul(position.to_price.add(to_buy)));
+                pnl.sub(turnover.mul(strategy.futures_fee))
+            }
+            Book::ReverseCoinm => {
+                let to_buy = self.leg_price(2, tick);
+                let from_sell = self.leg_price(3, tick);
+                let from_open = self.contract_value(position.from_qty, position.from_price);
+                let from_close = self.contract_value(position.from_qty, from_sell);
+                let to_open = self.contract_value(position.to_qty, position.to_price);
+                let to_close = self.contract_value(position.to_qty, to_buy);
+                // 多远期、空永续, 以币计的盈亏
+                let pnl_coin = from_open.sub(from_close).add(to_close.sub(to_open));
+                let fee = from_open
+                    .add(from_close)
+                    .add(to_open)
+                    .add(to_close)
+                    .mul(strategy.delivery_fee);
+                pnl_coin.sub(fee).mul(tick.to_price)
+            }
+        }
+    }
+
+    fn run(&self, ticks: &[Tick]) -> BacktestReport {
+        let strategy = self.strategy;
+        let open_guard = self.machine.legs[0].guard;
+        let close_guard = self.machine.legs[self.machine.legs.len() - 1].guard;
+
+        let mut report = BacktestReport {
+            strategy_id: strategy.id,
+            option_choose: strategy.option_choose.clone(),
+            from_symbol: strategy.from_symbol.clone(),
+            to_symbol: strategy.to_symbol.clone(),
+            ticks: ticks.len(),
+            ..BacktestReport::default()
+        };
+        let mut position: Option<Position> = None;
+        let mut peak = Decimal::ZERO;
+        let mut last_time: Option<i64> = None;
+
+        for tick in ticks {
+            if let (Some(_), Some(last)) = (&position, last_time) {
+                report.time_in_position += tick.time - last;
+            }
+            last_time = Some(tick.time);
+
+            match &position {
+                None if open_guard.pass(tick.diff_rate, strategy) => position = self.open(tick),
+                Some(p) if close_guard.pass(tick.diff_rate, strategy) => {
+                    report.pnl = report.pnl.add(self.close_pnl(p, tick));
+                    report.round_trips += 1;
+                    position = None;
+                }
+                _ => {}
+            }
+
+            report.unrealized_pnl = match &position {
+                Some(p) => self.close_pnl(p, tick),
+                None => Decimal::ZERO,
+            };
+            let equity = report.pnl.add(report.unrealized_pnl);
+            peak = peak.max(equity);
+            report.max_drawdown = report.max_drawdown.max(peak.sub(equity));
+        }
+
+        if let (Some(first), Some(last)) = (ticks.first(), ticks.last()) {
+            report.duration = last.time - first.time;
+        }
+        report
+    }
+}
+
+/// 用与实盘相同的阀值、手续费及合约换算回放行情
+pub fn backtest(strategy: &model::ArbStrategy, ticks: &[Tick]) -> anyhow::Result<BacktestReport> {
+    Ok(Backtest::new(strategy)?.run(ticks))
+}
+
+/// boll 回测的成交假设
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BollFill {
+    /// FOK 限价单总是以委托价成交
+    Limit,
+    /// 下一根k线触及委托价(买: low <= price, 卖: high >= price)才成交, 否则视为 FOK 失效
+    NextBar,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BollTrade {
+    /// 信号k线的 open_time(毫秒)
+    pub time: i64,
+    pub option_type: &'static str,
+    pub price: Decimal,
+    pub amount: Decimal,
+}
+
+/// 单组 boll 参数的回测结果
+#[derive(Debug, Clone)]
+pub struct BollReport {
+    pub period: usize,
+    pub multiplier: f64,
+    pub trades: Vec<BollTrade>,
+    pub round_trips: u32,
+    /// NextBar 下未成交的信号数
+    pub missed: u32,
+    /// 已平仓盈亏(计价币)
+    pub pnl: Decimal,
+    /// pnl / 最大买入金额
+    pub return_rate: Decimal,
+    /// 回测结束时仍持有
+    pub holding: bool,
+}
+
+/// 逐根k线回放 boll 策略, 与实盘使用相同的 boll_bands / boll_signal
+/// fee 为买卖双边按成交额收取的手续费率
+pub fn boll_backtest(
+    stable: &model::ArbStableCoin,
+    klines: &[KlineSummary],
+    period: usize,
+    multiplier: f64,
+    fill: BollFill,
+    fee: Decimal,
+) -> anyhow::Result<BollReport> {
+    let mut report = BollReport {
+        period,
+        multiplier,
+        trades: Vec::new(),
+        round_trips: 0,
+        missed: 0,
+        pnl: Decimal::ZERO,
+        return_rate: Decimal::ZERO,
+        holding: false,
+    };
+    if period == 0 {
+        return Err(anyhow!("boll period must be positive"));
+    }
+
+    let mut last_info: Option<(&'static str, Decimal, Decimal)> = None;
+    let mut cost = Decimal::ZERO;
+    let mut max_cost = Decimal::ZERO;
+    for i in period.saturating_sub(1)..klines.len() {
+        let bands = boll_bands(
+            &klines[i + 1 - period..=i],
+            period,
+            multiplier,
+            stable.price_truncate,
+        )?;
+        let (option_type, price, amount) = match boll_signal(stable, &bands, last_info) {
+            StableSignal::Buy { price, amount } => ("buy", price, amount),
+            StableSignal::Sell { price, amount } => ("sell", price, amount),
+            StableSignal::Hold => continue,
+        };
+
+        let filled = match fill {
+            BollFill::Limit => true,
+            BollFill::NextBar => match (klines.get(i + 1), price.to_f64()) {
+                (Some(next), Some(p)) if option_type == "buy" => next.low <= p,
+                (Some(next), Some(p)) => next.high >= p,
+                _ => false,
+            },
+        };
+        if !filled {
+            report.missed += 1;
+            continue;
+        }
+
+        let notional = price.mul(amount);
+        if option_type == "buy" {
+            cost = notional.mul(Decimal::ONE.add(fee));
+            max_cost = max_cost.max(cost);
+        } else {
+            report.pnl = report
+                .pnl
+                .add(notional.mul(Decimal::ONE.sub(fee)).sub(cost));
+            report.round_trips += 1;
+        }
+        report.trades.push(BollTrade {
+            time: klines[i].open_time,
+            option_type,
+            price,
+            amount,
+        });
+        last_info = Some((option_type, price, amount));
+    }
+
+    report.holding = matches!(last_info, Some(("buy", _, _)));
+    report.return_rate = report.pnl.checked_div(max_cost).unwrap_or(Decimal::ZERO);
+    Ok(report)
+}
+
+/// period x multiplier 参数网格
+pub fn boll_sweep(
+    stable: &model::ArbStableCoin,
+    klines: &[KlineSummary],
+    periods: &[usize],
+    multipliers: &[f64],
+    fill: BollFill,
+    fee: Decimal,
+) -> anyhow::Result<Vec<BollReport>> {
+    let mut reports = Vec::new();
+    for period in periods {
+        for multiplier in multipliers {
+            reports.push(boll_backtest(
+                stable,
+                klines,
+                *period,
+                *multiplier,
+                fill,
+                fee,
+            )?);
+        }
+    }
+    Ok(reports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn d(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    fn strategy(option_choose: &str, from_market: &str, to_market: &str) -> model::ArbStrategy {
+        model::ArbStrategy {
+            id: 1,
+            option_choose: option_choose.to_string(),
+            coin: "ETH".to_string(),
+            from_market: from_market.to_string(),
+            to_market: to_market.to_string(),
+            from_price_truncate: 2,
+            from_amt_truncate: 4,
+            to_price_truncate: 2,
+            to_amt_truncate: 4,
+            option_amt: d("1"),
+            margin_mul: 1,
+            contract_mul: 10,
+            fok_diff: d("0"),
+            spot_fee: d("0.001"),
+            futures_fee: d("0.0005"),
+            delivery_fee: d("0.0005"),
+            ..model::ArbStrategy::default()
+        }
+    }
+
+    fn tick(time: i64, from: &str, to: &str, option_choose: &str) -> Tick {
+        let (from_price, to_price) = (d(from), d(to));
+        let (_, diff_rate) = calc_diff_rate(option_choose, from_price, to_price);
+        Tick {
+            time,
+            from_price,
+            to_price,
+            diff_rate,
+        }
+    }
+
+    #[test]
+    fn test_positive() {
+        let mut s = strategy("positive", "spot", "delivery");
+        s.option_open = d("0.05");
+        s.option_close = d("0.01");
+        let ticks = vec![
+            tick(0, "3500", "3600", "positive"),
+            tick(60, "3500", "3700", "positive"),
+            tick(120, "3400", "3700", "positive"),
+            tick(180, "3600", "3620", "positive"),
+            tick(240, "3600", "3900", "positive"),
+        ];
+        let report = backtest(&s, &ticks).unwrap();
+        assert_eq!(report.round_trips, 1);
+        assert_eq!(report.time_in_position, 120);
+        assert_eq!(report.duration, 240);
+        // 开仓基差 200, 平仓基差 20
+        assert!(
+            report.pnl > d("100") && report.pnl < d("200"),
+            "{:?}",
+            report.pnl
+        );
+        assert!(report.max_drawdown > Decimal::ZERO);
+        assert!(report.unrealized_pnl < Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_reverse_usdm() {
+        let mut s = strategy("reverse", "futures", "futures");
+        s.option_open = d("-0.01");
+        s.option_close = d("0");
+        let ticks = vec![
+            tick(0, "3500", "3600", "reverse"),
+            tick(10, "3590", "3600", "reverse"),
+            tick(20, "3550", "3600", "reverse"),
+            tick(30, "3610", "3600", "reverse"),
+        ];
+        let report = backtest(&s, &ticks).unwrap();
+        assert_eq!(report.round_trips, 1);
+        // 远期 +110, 永续 0, 手续费 (3500 + 3610 + 3600 * 2) * 0.0005
+        assert_eq!(report.pnl, d("110").sub(d("14310").mul(d("0.0005"))));
+        assert_eq!(report.time_in_position, 30);
+    }
+
+    #[test]
+    fn test_reverse_coinm() {
+        let mut s = strategy("reverse", "delivery", "delivery");
+        s.option_open = d("-0.01");
+        s.option_close = d("0");
+        let ticks = vec![
+            tick(0, "3500", "3600", "reverse"),
+            tick(10, "3600", "3600", "reverse"),
+        ];
+        let report = backtest(&s, &ticks).unwrap();
+        assert_eq!(report.round_trips, 1);
+        assert!(report.pnl > Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_ticks_from_his() {
+        let s = strategy("positive", "spot", "delivery");
+        let his = |created, diff_price: &str, diff_rate: &str| model::ArbDiffRateHis {
+            diff_price: d(diff_price),
+            diff_rate: d(diff_rate),
+            created: Some(created),
+            ..model::ArbDiffRateHis::default()
+        };
+        let ticks = ticks_from_his(
+            &s,
+            &[his(1, "0", "0"), his(2, "100", "0.05"), his(3, "0", "0")],
+        );
+        assert_eq!(ticks.len(), 2);
+        assert_eq!(ticks[0].from_price, d("2000"));
+        assert_eq!(ticks[0].to_price, d("2100"));
+        assert_eq!(ticks[1].from_price, d("2000"));
+        assert_eq!(ticks[1].diff_rate, d("0"));
+    }
+
+    #[test]
+    fn test_unsupported() {
+        assert!(backtest(&strategy("reverse", "spot", "futures"), &[]).is_err());
+    }
+
+    fn kline(open_time: i64, close: f64) -> KlineSummary {
+        KlineSummary {
+            open_time,
+            open: close,
+            high: close + 0.0001,
+            low: close - 0.0001,
+            close,
+            volume: 0.0,
+            close_time: open_time + 899_999,
+            quote_asset_volume: 0.0,
+            number_of_trades: 0,
+            taker_buy_base_asset_volume: 0.0,
+            taker_buy_quote_asset_volume: 0.0,
+        }
+    }
+
+    fn stable() -> model::ArbStableCoin {
+        model::ArbStableCoin {
+            symbol: "FDUSDUSDT".to_string(),
+            price_truncate: 4,
+            amt_truncate: 0,
+            strategy: "11".to_string(),
+            option_amt: d("1000"),
+            fok_diff: d("0.0001"),
+            ..model::ArbStableCoin::default()
+        }
+    }
+
+    fn closes() -> Vec<KlineSummary> {
+        [
+            0.9995, 0.9990, 0.9995, 0.9990, 0.9995, 0.9980, 0.9990, 1.0005, 1.0000, 0.9990,
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, c)| kline(i as i64 * 900_000, c))
+        .collect()
+    }
+
+    #[test]
+    fn test_boll_backtest() {
+        let report =
+            boll_backtest(&stable(), &closes(), 5, 1.0, BollFill::Limit, Decimal::ZERO).unwrap();
+        assert_eq!(report.round_trips, 1);
+        assert_eq!(report.trades[0].option_type, "buy");
+        assert_eq!(report.trades[0].price, d("0.9981"));
+        assert_eq!(report.trades[1].option_type, "sell");
+        assert_eq!(report.trades[1].price, d("1.0004"));
+        assert_eq!(report.pnl, d("2.3"));
+        assert!(!report.holding);
+
+        // 下一根k线未触及买价, 买单失效
+        let report = boll_backtest(
+            &stable(),
+            &closes(),
+            5,
+            1.0,
+            BollFill::NextBar,
+            Decimal::ZERO,
+        )
+        .unwrap();
+        assert!(report.trades.is_empty());
+        assert_eq!(report.missed, 1);
+        assert!(!report.holding);
+    }
+
+    #[test]
+    fn test_boll_price_guard() {
+        // price >= 1 不买入
+        let klines: Vec<KlineSummary> = [1.001, 1.001, 1.001, 1.001, 1.0]
+            .into_iter()
+            .enumerate()
+            .map(|(i, c)| kline(i as i64, c))
+            .collect();
+        let report =
+            boll_backtest(&stable(), &klines, 4, 1.0, BollFill::Limit, Decimal::ZERO).unwrap();
+        assert!(report.trades.is_empty());
+    }
+
+    #[test]
+    fn test_boll_sweep() {
+        let reports = boll_sweep(
+            &stable(),
+            &closes(),
+            &[3, 5],
+            &[1.0, 2.0, 3.0],
+            BollFill::Limit,
+            d("0.0001"),
+        )
+        .unwrap();
+        assert_eq!(reports.len(), 6);
+        assert_eq!((reports[1].period, reports[1].multiplier), (3, 2.0));
+    }
+}
@@ -1,13 +1,73 @@
+use crate::conf::{Config, PriceGuardConfig};
 use crate::{model, service, sql};
 use chrono::Local;
 use log::{debug, error};
 use rust_decimal::Decimal;
 use std::collections::HashMap;
-use std::ops::{Div, Sub};
+use std::ops::Sub;
 use std::str::FromStr;
+use std::sync::Arc;
+
+/// 计算差价、比率(保留4位)
+/// 正向 positive、资金费率 funding: (to - from) / from, 反向 reverse: (from - to) / to
+pub fn calc_diff_rate(
+    option_choose: &str,
+    from_price: Decimal,
+    to_price: Decimal,
+) -> (Decimal, Decimal) {
+    let (diff, base) = if matches!(option_choose, "positive" | "funding") {
+        (to_price.sub(from_price), from_price)
+    } else {
+        (from_price.sub(to_price), to_price)
+    };
+    let mut rate = diff.checked_div(base).unwrap_or(Decimal::ZERO);
+    rate.rescale(4);
+    (diff, rate)
+}
+
+/// 按买一卖一计算可成交的 (开仓比率, 平仓比率)(保留4位)
+/// 正向开仓买入 from 卖出 to: (to_bid - from_ask) / from_ask, 平仓: (to_ask - from_bid) / from_bid
+/// 反向开仓买入 from 卖出 to: (from_ask - to_bid) / to_bid, 平仓: (from_bid - to_ask) / to_ask
+pub fn calc_executable_rate(
+    option_choose: &str,
+    from_bid: Decimal,
+    from_ask: Decimal,
+    to_bid: Decimal,
+    to_ask: Decimal,
+) -> (Decimal, Decimal) {
+    let (_, open_rate) = calc_diff_rate(option_choose, from_ask, to_bid);
+    let (_, close_rate) = calc_diff_rate(option_choose, from_bid, to_ask);
+    (open_rate, close_rate)
+}
+
+/// 读取两腿买一卖一计算可成交比率, 任一腿缺失或过期时为 0
+async fn executable_rate(
+    guard: &PriceGuardConfig,
+    diff_rate: &model::ArbDiffRate,
+) -> anyhow::Result<(Decimal, Decimal)> {
+    let from = service::price::get_fresh_book_ticker(
+        guard,
+        diff_rate.from_market.clone(),
+        diff_rate.from_symbol.clone(),
+    )
+    .await?;
+    let to = service::price::get_fresh_book_ticker(
+        guard,
+        diff_rate.to_market.clone(),
+        diff_rate.to_symbol.clone(),
+    )
+    .await?;
+    Ok(calc_executable_rate(
+        diff_rate.option_choose.as_str(),
+        Decimal::from_str(from.ticker.best_bid.as_str())?,
+        Decimal::from_str(from.ticker.best_ask.as_str())?,
+        Decimal::from_str(to.ticker.best_bid.as_str())?,
+        Decimal::from_str(to.ticker.best_ask.as_str())?,
+    ))
+}
 
 #[allow(unused_assignments)]
-pub async fn set_binance_diff_rate() {
+pub async fn set_binance_diff_rate(config: Arc<Config>) {
     let mut diff_rate_his_map: HashMap<i64, Decimal> = HashMap::new();
     loop {
         tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
@@ -45,23 +105,16 @@ pub async fn set_binance_diff_rate() {
                     }
 
                     // 计算差价、比率
-                    let mut diff = Decimal::ZERO;
-                    let mut rate = Decimal::ZERO;
-                    let mut info_rate = Decimal::ZERO;
-
-                    if diff_rate.option_choose == "positive".to_string() {
-                        diff = to_price.sub(from_price);
-                        rate = diff.div(from_price);
-                        rate.rescale(4 as u32);
-                        info_rate = rate;
-                        info_rate.rescale(3 as u32);
-                    } else {
-                        diff = from_price.sub(to_price);
-                        rate = diff.div(to_price);
-                        rate.rescale(4 as u32);
-                        info_rate = rate;
-                        info_rate.rescale(3 as u32);
-                    }
+                    let (diff, rate) =
+                        calc_diff_rate(diff_rate.option_choose.as_str(), from_price, to_price);
+                    let mut info_rate = rate;
+                    info_rate.rescale(3);
+                    let (open_rate, close_rate) = executable_rate(&config.price_guard, &diff_rate)
+                        .await
+                        .unwrap_or_else(|e| {
+                            debug!("{} executable rate err: {:?}", diff_rate.id, e);
+                            (Decimal::ZERO, Decimal::ZERO)
+                        });
 
                     debug!(
                         "option_choose: {:?}, from_symbol: {:?}, to_symbol: {:?}, from: {:?}, to: {:?}, diff: {:?}, rate: {:?}, info_rate: {:?}",
@@ -115,6 +168,8 @@ pub async fn set_binance_diff_rate() {
                             to_price,
                             diff,
                             rate,
+                            open_rate,
+                            close_rate,
                         )
                         .await;
                     } else {
@@ -132,6 +187,8 @@ pub async fn set_binance_diff_rate() {
                             to_price,
                             diff_price: diff,
                             diff_rate: rate,
+                            open_diff_rate: open_rate,
+                            close_diff_rate: close_rate,
                             created: Some(Local::now().timestamp()),
                             updated: Some(Local::now().timestamp()),
                             bak: None,
@@ -150,3 +207,30 @@ pub async fn set_binance_diff_rate() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_calc_executable_rate() {
+        // 正向: 买 from 卖一 100.1, 卖 to 买一 101.2; 平仓买 to 卖一 101.3, 卖 from 买一 100
+        let (open, close) =
+            calc_executable_rate("positive", d("100"), d("100.1"), d("101.2"), d("101.3"));
+        assert_eq!(open, d("0.0110"));
+        assert_eq!(close, d("0.0130"));
+        // 盘口价差使开仓比率低于、平仓比率高于最新价比率
+        let (_, last) = calc_diff_rate("positive", d("100.05"), d("101.25"));
+        assert!(open < last && last < close);
+
+        // 反向: 买 from 卖一 99.1, 卖 to 买一 100; 平仓卖 from 买一 99, 买 to 卖一 100.2
+        let (open, close) =
+            calc_executable_rate("reverse", d("99"), d("99.1"), d("100"), d("100.2"));
+        assert_eq!(open, d("-0.0090"));
+        assert_eq!(close, d("-0.0120"));
+    }
+}
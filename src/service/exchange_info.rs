@@ -0,0 +1,208 @@
+//! 交易所交易对信息, 进程内缓存10分钟
+//!
+//! 下单前按 PRICE_FILTER、LOT_SIZE 的 tickSize/stepSize 取整价格和数量,
+//! 按 MIN_NOTIONAL 拒绝金额过小的订单, 币本位按 contractSize 换算张数
+
+use crate::binance::rest_model::{Filters, FuturesSymbol};
+use crate::binance::MyApi;
+use anyhow::anyhow;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const CACHE_TTL: Duration = Duration::from_secs(600);
+
+/// market -> (拉取时间, 数据)
+type Cache<T> = DashMap<String, (Instant, Arc<T>)>;
+
+static FUTURES_SYMBOLS: Lazy<Cache<Vec<FuturesSymbol>>> = Lazy::new(DashMap::new);
+
+static SYMBOL_FILTERS: Lazy<Cache<HashMap<String, SymbolFilter>>> = Lazy::new(DashMap::new);
+
+/// 交易对的下单精度和限制, 缺失的过滤器为 0 表示不限制
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SymbolFilter {
+    pub tick_size: Decimal,
+    pub step_size: Decimal,
+    pub min_qty: Decimal,
+    pub min_notional: Decimal,
+    /// 币本位合约面值(USD), 其它市场为 0
+    pub contract_size: Decimal,
+}
+
+impl SymbolFilter {
+    pub fn from_filters(filters: &[Filters], contract_size: i64) -> anyhow::Result<Self> {
+        let mut filter = SymbolFilter {
+            contract_size: Decimal::from(contract_size),
+            ..SymbolFilter::default()
+        };
+        for f in filters {
+            match f {
+                Filters::PriceFilter { tick_size, .. } => {
+                    filter.tick_size = Decimal::from_str(tick_size)?.normalize()
+                }
+                Filters::LotSize {
+                    min_qty, step_size, ..
+                } => {
+                    filter.min_qty = Decimal::from_str(min_qty)?.normalize();
+                    filter.step_size = Decimal::from_str(step_size)?.normalize();
+                }
+                Filters::MinNotional { min_notional } | Filters::Notional { min_notional } => {
+                    filter.min_notional = Decimal::from_str(min_notional)?.normalize()
+                }
+                Filters::Others => {}
+            }
+        }
+        Ok(filter)
+    }
+
+    /// 价格取整到 tickSize 的整数倍
+    pub fn round_price(&self, price: Decimal, rounding: RoundingStrategy) -> Decimal {
+        round_to(price, self.tick_size, rounding)
+    }
+
+    /// 数量向下取整到 stepSize 的整数倍
+    pub fn round_qty(&self, qty: Decimal) -> Decimal {
+        round_to(qty, self.step_size, RoundingStrategy::ToZero)
+    }
+
+    /// 数量低于 minQty 或名义价值低于最小值时拒绝下单, 币本位数量为张数
+    pub fn check_order(&self, price: Decimal, qty: Decimal) -> anyhow::Result<()> {
+        if qty <= Decimal::ZERO || qty < self.min_qty {
+            return Err(anyhow!("qty {} below min qty {}", qty, self.min_qty));
+        }
+        let notional = if self.contract_size > Decimal::ZERO {
+            qty * self.contract_size
+        } else {
+            qty * price
+        };
+        if notional < self.min_notional {
+            return Err(anyhow!(
+                "notional {} below min notional {}",
+                notional,
+                self.min_notional
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn round_to(value: Decimal, step: Decimal, rounding: RoundingStrategy) -> Decimal {
+    if step <= Decimal::ZERO {
+        return value;
+    }
+    ((value / step).round_dp_with_strategy(0, rounding) * step).normalize()
+}
+
+/// futures 或 delivery 的全部合约
+pub async fn futures_symbols(api: &MyApi, market: &str) -> anyhow::Result<Arc<Vec<FuturesSymbol>>> {
+    if let Some(cached) = FUTURES_SYMBOLS.get(market) {
+        if cached.0.elapsed() < CACHE_TTL {
+            return Ok(cached.1.clone());
+        }
+    }
+
+    let info = match market {
+        "futures" => api.futures_exchange_info().await?,
+        "delivery" => api.delivery_exchange_info().await?,
+        _ => return Err(anyhow!("unsupported market: {}", market)),
+    };
+    let symbols = Arc::new(info.symbols);
+    FUTURES_SYMBOLS.insert(market.to_string(), (Instant::now(), symbols.clone()));
+    Ok(symbols)
+}
+
+/// 市场内一个交易对的下单精度和限制
+pub async fn symbol_filter(
+    api: &MyApi,
+    market: &str,
+    symbol: &str,
+) -> anyhow::Result<SymbolFilter> {
+    let filters = match SYMBOL_FILTERS.get(market) {
+        Some(cached) if cached.0.elapsed() < CACHE_TTL => cached.1.clone(),
+        _ => {
+            let filters = Arc::new(fetch_symbol_filters(api, market).await?);
+            SYMBOL_FILTERS.insert(market.to_string(), (Instant::now(), filters.clone()));
+            filters
+        }
+    };
+    filters
+        .get(symbol)
+        .cloned()
+        .ok_or(anyhow!("{} {} not found in exchange info", market, symbol))
+}
+
+async fn fetch_symbol_filters(
+    api: &MyApi,
+    market: &str,
+) -> anyhow::Result<HashMap<String, SymbolFilter>> {
+    let mut filters = HashMap::new();
+    if market == "spot" {
+        for symbol in api.exchange_info().await?.symbols {
+            let filter = SymbolFilter::from_filters(&symbol.filters, 0)?;
+            filters.insert(symbol.symbol, filter);
+        }
+    } else {
+        for symbol in futures_symbols(api, market).await?.iter() {
+            // U本位 contractSize 为空
+            let contract_size = if market == "delivery" {
+                symbol.contract_size
+            } else {
+                0
+            };
+            let filter = SymbolFilter::from_filters(&symbol.filters, contract_size)?;
+            filters.insert(symbol.symbol.clone(), filter);
+        }
+    }
+    Ok(filters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_symbol_filter() {
+        let filters: Vec<Filters> = serde_json::from_str(
+            r#"[
+                {"filterType":"PRICE_FILTER","minPrice":"0.01000000","maxPrice":"1000000.00000000","tickSize":"0.01000000"},
+                {"filterType":"LOT_SIZE","minQty":"0.00001000","maxQty":"9000.00000000","stepSize":"0.00001000"},
+                {"filterType":"ICEBERG_PARTS","limit":10},
+                {"filterType":"NOTIONAL","minNotional":"5.00000000","applyMinToMarket":true,"maxNotional":"9000000.00000000","applyMaxToMarket":false,"avgPriceMins":5}
+            ]"#,
+        )
+        .unwrap();
+        let filter = SymbolFilter::from_filters(&filters, 0).unwrap();
+        assert_eq!(filter.tick_size, d("0.01"));
+        assert_eq!(filter.min_notional, d("5"));
+
+        assert_eq!(
+            filter.round_price(d("43210.123"), RoundingStrategy::AwayFromZero),
+            d("43210.13")
+        );
+        assert_eq!(
+            filter.round_price(d("43210.129"), RoundingStrategy::ToZero),
+            d("43210.12")
+        );
+        assert_eq!(filter.round_qty(d("0.123456789")), d("0.12345"));
+        assert!(filter.check_order(d("40000"), d("0.0002")).is_ok());
+        assert!(filter.check_order(d("40000"), d("0.0001")).is_err());
+
+        // 币本位按张数 * 面值计算名义价值
+        let futures: Vec<Filters> =
+            serde_json::from_str(r#"[{"filterType":"MIN_NOTIONAL","notional":"100"},{"filterType":"LOT_SIZE","minQty":"1","maxQty":"1000000","stepSize":"1"}]"#)
+                .unwrap();
+        let delivery = SymbolFilter::from_filters(&futures, 100).unwrap();
+        assert_eq!(delivery.round_qty(d("3.7")), d("3"));
+        assert!(delivery.check_order(d("40000"), d("1")).is_ok());
+        assert!(delivery.check_order(d("40000"), d("0")).is_err());
+    }
+}
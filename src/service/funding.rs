@@ -0,0 +1,224 @@
+//! 资金费率
+//!
+//! 订阅U本位永续的 markPrice 获取预测资金费率, 缓存到 redis,
+//! 定时按已结算的资金费率累计资金费率策略的资金费收入
+
+use crate::binance::rest_model::FundingRate;
+use crate::binance::websockets::*;
+use crate::binance::ws_model::{CombinedStreamEvent, MarkPriceEvent, WebsocketEvent};
+use crate::binance::{ApiPool, MyApi};
+use crate::conf::{redis_key, PriceGuardConfig};
+use crate::service::account;
+use crate::service::state_machine::StrategyMachine;
+use crate::{db, model, service, sql};
+use anyhow::anyhow;
+use chrono::Local;
+use log::{error, info, warn};
+use redis::AsyncCommands;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::select;
+
+const MARKET: &str = "futures";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct CachedMarkPrice {
+    #[serde(flatten)]
+    event: MarkPriceEvent,
+    local_time: i64,
+}
+
+/// 订阅运行中差价记录U本位交易对的 markPrice, 交易对变化时重新订阅
+pub async fn funding_rate_stream() {
+    let mut redis = db::get_db().unwrap().redis().await.unwrap();
+    loop {
+        let symbols = match service::diff_rate_symbols(MARKET).await {
+            Ok(symbols) => symbols,
+            Err(e) => {
+                error!("{} mark price symbols err: {:?}", MARKET, e);
+                tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
+                continue;
+            }
+        };
+        if symbols.is_empty() {
+            tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
+            continue;
+        }
+
+        let (mark_tx, mut mark_rx) = tokio::sync::mpsc::unbounded_channel();
+        let keep_running = AtomicBool::new(true);
+        let endpoints: Vec<String> = symbols.iter().map(|s| mark_price_stream(s, 1)).collect();
+        let mut web_socket: WebSockets<'_, CombinedStreamEvent<WebsocketEvent>> =
+            WebSockets::new(|event: CombinedStreamEvent<WebsocketEvent>| {
+                if let WebsocketEvent::MarkPriceUpdate(mark) = event.data {
+                    let cached = CachedMarkPrice {
+                        event: *mark,
+                        local_time: Local::now().timestamp_millis(),
+                    };
+                    if mark_tx.send(cached).is_err() {
+                        keep_running.store(false, Ordering::Relaxed);
+                    }
+                }
+                Ok(())
+            });
+
+        let cache = async {
+            let key = format!("{}{}", MARKET, redis_key::FUNDING_KEY);
+            while let Some(cached) = mark_rx.recv().await {
+                let symbol = cached.event.symbol.clone();
+                let json = serde_json::to_string(&cached).unwrap();
+                if let Err(e) = redis.hset::<_, _, _, ()>(&key, symbol, json).await {
+                    error!("set {} mark price err: {:?}", MARKET, e);
+                }
+            }
+        };
+
+        select! {
+            res = web_socket.run_forever_multiple(MARKET, &endpoints, &keep_running, ReconnectPolicy::default()) => {
+                if let Err(e) = res {
+                    error!("{} mark price websocket error: {e}", MARKET);
+                    tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+                }
+            }
+            _ = cache => {}
+            _ = service::symbols_changed(MARKET, &symbols) => {
+                info!("{} mark price symbols changed, resubscribing", MARKET)
+            }
+        }
+    }
+}
+
+/// 预测资金费率, 优先使用 markPrice 推送, 缺失或过期时查询 premiumIndex
+pub async fn predicted_funding_rate(
+    guard: &PriceGuardConfig,
+    api: &MyApi,
+    symbol: &str,
+) -> anyhow::Result<Decimal> {
+    let mut redis = db::get_db()?.redis().await?;
+    let key = format!("{}{}", MARKET, redis_key::FUNDING_KEY);
+    let cached: Option<String> = redis.hget(key, symbol).await?;
+    if let Some(cached) = cached {
+        let cached = serde_json::from_str::<CachedMarkPrice>(cached.as_str())?;
+        let age = Local::now().timestamp_millis() - cached.local_time;
+        if age <= guard.max_age_ms(MARKET) && !cached.event.funding_rate.is_empty() {
+            return Ok(Decimal::from_str(cached.event.funding_rate.as_str())?);
+        }
+    }
+
+    let index = api.futures_premium_index(symbol).await?;
+    Ok(Decimal::from_str(index.last_funding_rate.as_str())?)
+}
+
+/// 空头仓位 qty 在这些结算中收到的资金费, 费率为正时空头收取
+pub fn funding_income(qty: Decimal, fundings: &[FundingRate]) -> anyhow::Result<Decimal> {
+    let mut income = Decimal::ZERO;
+    for funding in fundings {
+        let rate = Decimal::from_str(funding.funding_rate.as_str())?;
+        let mark_price = Decimal::from_str(funding.mark_price.as_str()).map_err(|_| {
+            anyhow!(
+                "{} funding at {} has no mark price",
+                funding.symbol,
+                funding.funding_time
+            )
+        })?;
+        income += rate * qty * mark_price;
+    }
+    Ok(income)
+}
+
+/// 累计一个资金费率策略持有永续空头期间的资金费收入
+async fn accrue_funding_income(api: &MyApi, strategy: &model::ArbStrategy) -> anyhow::Result<()> {
+    let machine = StrategyMachine::funding();
+    let ex_list = sql::get_arb_strategy_ex_list_by_strategy_id(strategy.id).await?;
+    if ex_list.len() != machine.legs.len() {
+        return Ok(());
+    }
+    // 永续空头: 第 1 条腿卖出成交后到第 2 条腿买入成交
+    let (short, cover) = (&ex_list[1], &ex_list[2]);
+    if short.option_status != model::arb_strategy_ex::OPTION_STATUS_DONE {
+        return Ok(());
+    }
+    let start = match strategy.funding_time {
+        0 => short.updated.unwrap_or_default() * 1000,
+        t => t + 1,
+    };
+    let end = if cover.option_status == model::arb_strategy_ex::OPTION_STATUS_DONE {
+        cover.updated.unwrap_or_default() * 1000
+    } else {
+        Local::now().timestamp_millis()
+    };
+    if start >= end {
+        return Ok(());
+    }
+
+    let fundings = api
+        .futures_funding_rate(strategy.to_symbol.as_str(), start as u64, end as u64, 1000)
+        .await?;
+    let Some(last) = fundings.last() else {
+        return Ok(());
+    };
+    let income = funding_income(short.option_executed_amt, &fundings)?;
+    sql::add_strategy_funding_income(strategy.id, income, last.funding_time as i64).await?;
+    info!(
+        "strategy_id: {}, {} fundings, income: {}",
+        strategy.id,
+        fundings.len(),
+        income
+    );
+    Ok(())
+}
+
+/// 每5分钟累计运行中资金费率策略的资金费收入
+pub async fn inspect_funding_income(pool: Arc<ApiPool>) {
+    loop {
+        match sql::get_arb_strategy_list_by_doing_status(model::arb_strategy::DOING_STATUS_RUN)
+            .await
+        {
+            Ok(strategy_list) => {
+                for strategy in strategy_list {
+                    if strategy.option_choose != "funding" {
+                        continue;
+                    }
+                    let result = match account::user_api(&pool, strategy.user_id) {
+                        Ok(api) => accrue_funding_income(&api, &strategy).await,
+                        Err(e) => Err(e),
+                    };
+                    if let Err(e) = result {
+                        account::report_error(&pool, strategy.user_id, &e);
+                        warn!("strategy_id: {}, funding income err: {:?}", strategy.id, e);
+                    }
+                }
+            }
+            Err(e) => {
+                error!("{:?}", e);
+            }
+        }
+        tokio::time::sleep(tokio::time::Duration::from_secs(300)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn funding(rate: &str, mark_price: &str) -> FundingRate {
+        FundingRate {
+            symbol: "BTCUSDT".to_string(),
+            funding_rate: rate.to_string(),
+            funding_time: 1700000000000,
+            mark_price: mark_price.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_funding_income() {
+        let d = |s: &str| Decimal::from_str(s).unwrap();
+        let fundings = vec![funding("0.0001", "40000"), funding("-0.00005", "42000")];
+        // 0.5 * (0.0001 * 40000 - 0.00005 * 42000) = 0.5 * (4 - 2.1)
+        assert_eq!(funding_income(d("0.5"), &fundings).unwrap(), d("0.95"));
+        assert!(funding_income(d("0.5"), &[funding("0.0001", "")]).is_err());
+    }
+}
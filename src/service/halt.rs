@@ -0,0 +1,213 @@
+//! 停止交易开关与优雅退出
+//!
+//! 停止交易: redis 中存在 HALT_KEY(管理接口写入, 所有进程共享), 或 shutdown.halt_file 存在时,
+//! 不再执行新的腿, 已提交的订单继续跟进到终态, 行情和账户数据流照常运行。
+//!
+//! 退出: 停止分发策略, 等待进行中的下单/划转完成并落库, 可选撤销未成交的挂单
+
+use crate::binance::ApiPool;
+use crate::conf::{redis_key, Config, ShutdownConfig};
+use crate::service::{account, binance_strategy};
+use crate::{db, model, sql};
+use chrono::Local;
+use log::{error, info, warn};
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+static REDIS_HALT: AtomicBool = AtomicBool::new(false);
+static FILE_HALT: AtomicBool = AtomicBool::new(false);
+
+/// 开关检查间隔
+const WATCH_INTERVAL_MS: u64 = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct HaltStatus {
+    pub halted: bool,
+    /// redis 开关的原因, 未设置时为 None
+    pub reason: Option<String>,
+    pub file: bool,
+    pub shutting_down: bool,
+}
+
+/// 是否停止执行新的腿
+pub fn is_halted() -> bool {
+    SHUTDOWN.load(Ordering::Relaxed)
+        || REDIS_HALT.load(Ordering::Relaxed)
+        || FILE_HALT.load(Ordering::Relaxed)
+}
+
+/// 是否正在退出, 退出时不再分发策略
+pub fn is_shutting_down() -> bool {
+    SHUTDOWN.load(Ordering::Relaxed)
+}
+
+/// 停止交易时只跟进已提交的订单
+pub fn may_execute(ex_list: &[model::ArbStrategyEx]) -> bool {
+    !is_halted() || binance_strategy::order_in_flight(ex_list)
+}
+
+/// 设置停止交易开关, 所有进程在下一次检查时生效
+pub async fn set_halt(reason: &str) -> anyhow::Result<()> {
+    let mut redis = db::get_db()?.redis().await?;
+    let value = format!("{} at {}", reason, Local::now().format("%Y-%m-%d %H:%M:%S"));
+    let _: () = redis.set(redis_key::HALT_KEY, value).await?;
+    REDIS_HALT.store(true, Ordering::Relaxed);
+    warn!("trading halted: {}", reason);
+    Ok(())
+}
+
+/// 清除 redis 停止交易开关, halt_file 需手动删除
+pub async fn clear_halt() -> anyhow::Result<()> {
+    let mut redis = db::get_db()?.redis().await?;
+    let _: () = redis.del(redis_key::HALT_KEY).await?;
+    REDIS_HALT.store(false, Ordering::Relaxed);
+    warn!("trading resumed");
+    Ok(())
+}
+
+pub async fn halt_status(halt_file: &str) -> anyhow::Result<HaltStatus> {
+    let mut redis = db::get_db()?.redis().await?;
+    let reason: Option<String> = redis.get(redis_key::HALT_KEY).await?;
+    let file = halt_file_exists(halt_file);
+    Ok(HaltStatus {
+        halted: reason.is_some() || file || is_shutting_down(),
+        reason,
+        file,
+        shutting_down: is_shutting_down(),
+    })
+}
+
+fn halt_file_exists(file: &str) -> bool {
+    !file.is_empty() && Path::new(file).exists()
+}
+
+/// 定时读取 redis 和文件开关
+pub async fn watch_halt(config: Arc<Config>) {
+    let halt_file = config.shutdown.halt_file.as_str();
+    loop {
+        let file = halt_file_exists(halt_file);
+        if FILE_HALT.swap(file, Ordering::Relaxed) != file {
+            warn!(
+                "halt file {} {}",
+                halt_file,
+                if file {
+                    "found, trading halted"
+                } else {
+                    "removed"
+                }
+            );
+        }
+        match halt_status(halt_file).await {
+            Ok(status) => {
+                let halted = status.reason.is_some();
+                if REDIS_HALT.swap(halted, Ordering::Relaxed) != halted {
+                    match status.reason {
+                        Some(reason) => warn!("trading halted: {}", reason),
+                        None => warn!("trading resumed"),
+                    }
+                }
+            }
+            Err(e) => error!("read halt switch err: {:?}", e),
+        }
+        tokio::time::sleep(tokio::time::Duration::from_millis(WATCH_INTERVAL_MS)).await;
+    }
+}
+
+/// 优雅退出: 停止分发, 等待 workers 处理完进行中的腿, 超时返回 false
+///
+/// dispatcher 为分发策略的任务, 退出后通道关闭, workers 处理完队列中的策略后结束
+pub async fn shutdown(
+    config: &ShutdownConfig,
+    dispatcher: JoinHandle<()>,
+    workers: Vec<JoinHandle<()>>,
+) -> bool {
+    SHUTDOWN.store(true, Ordering::Relaxed);
+    warn!(
+        "shutting down, waiting up to {}s for in-flight legs",
+        config.timeout_secs
+    );
+    let wait = async {
+        let _ = dispatcher.await;
+        for worker in workers {
+            let _ = worker.await;
+        }
+    };
+    let timeout = tokio::time::Duration::from_secs(config.timeout_secs);
+    let finished = tokio::time::timeout(timeout, wait).await.is_ok();
+    if !finished {
+        warn!("in-flight legs did not finish in time, they are reconciled on next start");
+    }
+
+    if let Ok(db) = db::get_db() {
+        if let Err(e) = db.rocksdb().flush() {
+            error!("flush rocksdb err: {:?}", e);
+        }
+    }
+    info!("shutdown complete");
+    finished
+}
+
+/// 退出前检查仍有挂单的套利策略, shutdown.cancel_open_orders 时撤单, 部分成交的按成交数量完成当前腿
+///
+/// 需在 workers 全部结束后调用, 避免和进行中的腿同时处理同一订单
+pub async fn close_open_orders(pool: &ApiPool) {
+    if let Err(e) = open_orders(pool, pool.config().shutdown.cancel_open_orders).await {
+        error!("check open orders err: {:?}", e);
+    }
+}
+
+async fn open_orders(pool: &ApiPool, cancel: bool) -> anyhow::Result<()> {
+    let strategy_list =
+        sql::get_arb_strategy_list_by_doing_status(model::arb_strategy::DOING_STATUS_RUN).await?;
+    for strategy in strategy_list {
+        let ex_list = sql::get_arb_strategy_ex_list_by_strategy_id(strategy.id).await?;
+        if !binance_strategy::order_in_flight(&ex_list) {
+            continue;
+        }
+        if !cancel {
+            warn!(
+                "strategy_id: {} has a resting order, left open",
+                strategy.id
+            );
+            continue;
+        }
+        let result = match account::user_api(pool, strategy.user_id) {
+            Ok(api) => binance_strategy::retry_leg(&api, &strategy).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(ex) => warn!(
+                "strategy_id: {}, {} order canceled, executed: {}",
+                strategy.id, ex.option_type, ex.option_executed_amt
+            ),
+            Err(e) => error!("strategy_id: {}, cancel err: {:?}", strategy.id, e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_may_execute() {
+        let idle = vec![model::ArbStrategyEx::default()];
+        let in_flight = vec![model::ArbStrategyEx {
+            current_order_id: "42".to_string(),
+            ..model::ArbStrategyEx::default()
+        }];
+        assert!(may_execute(&idle));
+        FILE_HALT.store(true, Ordering::Relaxed);
+        assert!(!may_execute(&idle));
+        // 已提交的订单继续跟进
+        assert!(may_execute(&in_flight));
+        FILE_HALT.store(false, Ordering::Relaxed);
+        assert!(may_execute(&idle));
+    }
+}
@@ -0,0 +1,172 @@
+use crate::binance::rest_model::{KlineSummaries, KlineSummary};
+use crate::binance::MyApi;
+use crate::db;
+use anyhow::anyhow;
+use chrono::Local;
+
+/// k线周期的毫秒数, 周线/月线不按固定间隔对齐, 不支持
+pub fn interval_ms(interval: &str) -> Option<i64> {
+    let (num, unit) = interval.split_at(interval.len().checked_sub(1)?);
+    let num: i64 = num.parse().ok()?;
+    let unit_ms = match unit {
+        "m" => 60_000,
+        "h" => 3_600_000,
+        "d" => 86_400_000,
+        _ => return None,
+    };
+    Some(num * unit_ms)
+}
+
+/// 已有 open_time(升序) 在 [start, end] 内缺失的区间, 返回 (首个缺失 open_time, 最后一个缺失 open_time)
+pub fn find_gaps(open_times: &[i64], interval_ms: i64, start: i64, end: i64) -> Vec<(i64, i64)> {
+    let mut gaps = Vec::new();
+    let mut expected = start;
+    for &open_time in open_times.iter().filter(|t| **t >= start && **t <= end) {
+        if open_time > expected {
+            gaps.push((expected, open_time - interval_ms));
+        }
+        expected = expected.max(open_time + interval_ms);
+    }
+    if expected <= end {
+        gaps.push((expected, end));
+    }
+    gaps
+}
+
+/// 分页拉取 [start_time, end_time] 的 k 线, 时间为毫秒
+pub async fn fetch_klines(
+    api: &MyApi,
+    market: &str,
+    symbol: &str,
+    interval: &str,
+    start_time: u64,
+    end_time: u64,
+) -> anyhow::Result<Vec<KlineSummary>> {
+    let mut klines: Vec<KlineSummary> = Vec::new();
+    let mut start = start_time;
+    while start < end_time {
+        let KlineSummaries::AllKlineSummaries(page) = match market {
+            "spot" => {
+                api.get_klines(symbol, interval, 1000, start, end_time)
+                    .await?
+            }
+            "futures" => {
+                api.futures_get_klines(symbol, interval, 1000, start, end_time)
+                    .await?
+            }
+            "delivery" => {
+                api.delivery_get_klines(symbol, interval, 1000, start, end_time)
+                    .await?
+            }
+            _ => return Err(anyhow!("unsupported market: {}", market)),
+        };
+        match page.last() {
+            Some(last) => start = last.close_time as u64 + 1,
+            None => break,
+        }
+        klines.extend(page);
+    }
+    Ok(klines)
+}
+
+/// open_time 在 [start, end] 内的k线, 优先读取 RocksDB, 缺失的区间从币安补齐并落库
+///
+/// 未收盘的k线不落库, 每次重新拉取
+pub async fn klines_between(
+    api: &MyApi,
+    market: &str,
+    symbol: &str,
+    interval: &str,
+    start: i64,
+    end: i64,
+) -> anyhow::Result<Vec<KlineSummary>> {
+    let interval_ms =
+        interval_ms(interval).ok_or_else(|| anyhow!("unsupported interval: {}", interval))?;
+    let start = start - start.rem_euclid(interval_ms);
+    let store = db::get_db()?.klines();
+
+    let mut klines = store.range(market, symbol, interval, start, end)?;
+    let open_times: Vec<i64> = klines.iter().map(|k| k.open_time).collect();
+    let gaps = find_gaps(&open_times, interval_ms, start, end);
+    if gaps.is_empty() {
+        return Ok(klines);
+    }
+
+    let mut fetched = Vec::new();
+    for (from, to) in gaps {
+        fetched.extend(
+            fetch_klines(
+                api,
+                market,
+                symbol,
+                interval,
+                from as u64,
+                (to + interval_ms - 1) as u64,
+            )
+            .await?,
+        );
+    }
+    let now = Local::now().timestamp_millis();
+    let closed: Vec<KlineSummary> = fetched
+        .iter()
+        .filter(|k| k.close_time < now)
+        .cloned()
+        .collect();
+    store.put(market, symbol, interval, &closed)?;
+
+    klines.extend(
+        fetched
+            .into_iter()
+            .filter(|k| k.open_time >= start && k.open_time <= end),
+    );
+    klines.sort_by_key(|k| k.open_time);
+    klines.dedup_by_key(|k| k.open_time);
+    Ok(klines)
+}
+
+/// 最近 limit 根k线, 最后一根为当前未收盘的k线
+pub async fn recent_klines(
+    api: &MyApi,
+    market: &str,
+    symbol: &str,
+    interval: &str,
+    limit: usize,
+) -> anyhow::Result<Vec<KlineSummary>> {
+    let interval_ms =
+        interval_ms(interval).ok_or_else(|| anyhow!("unsupported interval: {}", interval))?;
+    let now = Local::now().timestamp_millis();
+    let end = now - now.rem_euclid(interval_ms);
+    let start = end - (limit.saturating_sub(1) as i64) * interval_ms;
+    klines_between(api, market, symbol, interval, start, end).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interval_ms() {
+        assert_eq!(interval_ms("1m"), Some(60_000));
+        assert_eq!(interval_ms("15m"), Some(900_000));
+        assert_eq!(interval_ms("4h"), Some(14_400_000));
+        assert_eq!(interval_ms("1d"), Some(86_400_000));
+        assert_eq!(interval_ms("1w"), None);
+        assert_eq!(interval_ms("1M"), None);
+        assert_eq!(interval_ms(""), None);
+    }
+
+    #[test]
+    fn test_find_gaps() {
+        // 完整
+        assert!(find_gaps(&[0, 10, 20], 10, 0, 20).is_empty());
+        // 全部缺失
+        assert_eq!(find_gaps(&[], 10, 0, 20), vec![(0, 20)]);
+        // 头部, 中间, 尾部缺失
+        assert_eq!(
+            find_gaps(&[20, 30, 60], 10, 0, 80),
+            vec![(0, 10), (40, 50), (70, 80)]
+        );
+        // 区间外的数据忽略
+        assert_eq!(find_gaps(&[-10, 0, 30], 10, 0, 20), vec![(10, 20)]);
+    }
+}
@@ -1,23 +1,44 @@
+pub mod account;
+pub mod backtest;
 pub mod binance_strategy;
 mod common;
 pub mod diff_rate;
+pub mod exchange_info;
+pub mod funding;
+pub mod halt;
+pub mod kline;
+pub mod order_book;
 pub mod price;
+pub mod reconcile;
+pub mod rollover;
 pub mod stable_coin_hedging;
+pub mod state_machine;
+pub mod user_stream;
 
 pub use binance_strategy::event_start;
 pub use binance_strategy::inspect_strategy;
 pub use binance_strategy::range_new_strategy;
 pub use diff_rate::set_binance_diff_rate;
+pub use funding::funding_rate_stream;
+pub use funding::inspect_funding_income;
+pub use order_book::order_book_stream;
+pub use price::get_binance_book_ticker;
 pub use price::get_binance_price;
+pub use price::set_binance_book_ticker;
 pub use price::set_binance_price;
+pub use reconcile::reconcile_strategies;
 pub use stable_coin_hedging::event_stable_coin_start;
 pub use stable_coin_hedging::inspect_stable_coin;
+pub use user_stream::user_data_stream;
 
 use crate::binance::websockets::*;
 use crate::binance::ws_model::*;
+use crate::{model, sql};
 use log::{error, info};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
 use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::select;
 use tokio::sync::mpsc::UnboundedSender;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -27,9 +48,27 @@ pub struct PriceStream {
     pub local_time: i64,
 }
 
-#[allow(dead_code)]
-#[allow(irrefutable_let_patterns)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BookTickerStream {
+    pub ticker: BookTickerEvent,
+    pub market: String,
+    pub local_time: i64,
+}
+
 pub async fn spot_all_ticker(price_tx: UnboundedSender<PriceStream>) {
+    all_ticker("spot", price_tx).await
+}
+
+pub async fn futures_all_ticker(price_tx: UnboundedSender<PriceStream>) {
+    all_ticker("futures", price_tx).await
+}
+
+pub async fn delivery_all_ticker(price_tx: UnboundedSender<PriceStream>) {
+    all_ticker("delivery", price_tx).await
+}
+
+/// 订阅全市场 mini ticker, 断线自动重连, 直到 price_tx 关闭
+async fn all_ticker(market: &'static str, price_tx: UnboundedSender<PriceStream>) {
     let keep_running = AtomicBool::new(true);
     let all_ticker = all_mini_ticker_stream();
 
@@ -43,106 +82,140 @@ pub async fn spot_all_ticker(price_tx: UnboundedSender<PriceStream>) {
             }
 
             let price_stream = PriceStream {
-                tickers: tickers,
-                market: "spot".to_string(),
+                tickers,
+                market: market.to_string(),
                 local_time: chrono::Local::now().timestamp_millis(),
             };
-            match price_tx.send(price_stream) {
-                Ok(_) => {}
-                Err(_e) => {
-                    keep_running.store(false, Ordering::Relaxed);
-                }
+            if price_tx.send(price_stream).is_err() {
+                keep_running.store(false, Ordering::Relaxed);
             }
-
-            // Break the event loop
-            // keep_running.store(false, Ordering::Relaxed);
             Ok(())
         });
 
-    web_socket.connect(all_ticker).await.unwrap(); // check error
-    if let Err(e) = web_socket.event_loop(&keep_running).await {
-        error!("Error: {e}");
+    if let Err(e) = web_socket
+        .run_forever(
+            market,
+            all_ticker,
+            &keep_running,
+            ReconnectPolicy::default(),
+        )
+        .await
+    {
+        error!("{} websocket error: {e}", market);
     }
-    web_socket.disconnect().await.unwrap();
-    info!("spot websocket disconnected");
+    info!("{} websocket stopped", market);
 }
 
-#[allow(dead_code)]
-#[allow(irrefutable_let_patterns)]
-pub async fn futures_all_ticker(price_tx: UnboundedSender<PriceStream>) {
-    let keep_running = AtomicBool::new(true);
-    let all_ticker = all_mini_ticker_stream();
+pub async fn futures_book_ticker(book_tx: UnboundedSender<BookTickerStream>) {
+    all_book_ticker("futures", book_tx).await
+}
 
-    let mut web_socket: WebSockets<'_, Vec<WebsocketEvent>> =
-        WebSockets::new(|events: Vec<WebsocketEvent>| {
-            let mut tickers = Vec::new();
-            for tick_events in events {
-                if let WebsocketEvent::DayMiniTicker(tick_event) = tick_events {
-                    tickers.push(*tick_event)
-                }
-            }
+pub async fn delivery_book_ticker(book_tx: UnboundedSender<BookTickerStream>) {
+    all_book_ticker("delivery", book_tx).await
+}
 
-            let price_stream = PriceStream {
-                tickers: tickers,
-                market: "futures".to_string(),
-                local_time: chrono::Local::now().timestamp_millis(),
-            };
-            match price_tx.send(price_stream) {
-                Ok(_) => {}
-                Err(_e) => {
+/// 订阅合约全市场 bookTicker, 断线自动重连, 直到 book_tx 关闭
+async fn all_book_ticker(market: &'static str, book_tx: UnboundedSender<BookTickerStream>) {
+    let keep_running = AtomicBool::new(true);
+
+    let mut web_socket: WebSockets<'_, WebsocketEvent> =
+        WebSockets::new(|event: WebsocketEvent| {
+            if let WebsocketEvent::BookTicker(ticker) = event {
+                let book_stream = BookTickerStream {
+                    ticker: *ticker,
+                    market: market.to_string(),
+                    local_time: chrono::Local::now().timestamp_millis(),
+                };
+                if book_tx.send(book_stream).is_err() {
                     keep_running.store(false, Ordering::Relaxed);
                 }
             }
-
-            // Break the event loop
-            // keep_running.store(false, Ordering::Relaxed);
             Ok(())
         });
 
-    web_socket.connect_futures(all_ticker).await.unwrap(); // check error
-    if let Err(e) = web_socket.event_loop(&keep_running).await {
-        error!("Error: {e}");
+    if let Err(e) = web_socket
+        .run_forever(
+            market,
+            all_book_ticker_stream(),
+            &keep_running,
+            ReconnectPolicy::default(),
+        )
+        .await
+    {
+        error!("{} book ticker websocket error: {e}", market);
     }
-    web_socket.disconnect().await.unwrap();
-    info!("futures websocket disconnected");
+    info!("{} book ticker websocket stopped", market);
 }
 
-#[allow(dead_code)]
-#[allow(irrefutable_let_patterns)]
-pub async fn delivery_all_ticker(price_tx: UnboundedSender<PriceStream>) {
-    let keep_running = AtomicBool::new(true);
-    let all_ticker = all_mini_ticker_stream();
+/// 运行中差价记录在 market 上的交易对(小写)
+pub(crate) async fn diff_rate_symbols(market: &str) -> anyhow::Result<BTreeSet<String>> {
+    let mut symbols = BTreeSet::new();
+    for diff_rate in
+        sql::get_arb_diff_rate_list_by_diff_status(model::arb_diff_rate::DIFF_STATUS_RUN).await?
+    {
+        if diff_rate.from_market == market {
+            symbols.insert(diff_rate.from_symbol.to_lowercase());
+        }
+        if diff_rate.to_market == market {
+            symbols.insert(diff_rate.to_symbol.to_lowercase());
+        }
+    }
+    Ok(symbols)
+}
 
-    let mut web_socket: WebSockets<'_, Vec<WebsocketEvent>> =
-        WebSockets::new(|events: Vec<WebsocketEvent>| {
-            let mut tickers = Vec::new();
-            for tick_events in events {
-                if let WebsocketEvent::DayMiniTicker(tick_event) = tick_events {
-                    tickers.push(*tick_event)
-                }
-            }
+/// 每分钟检查一次, market 上的交易对与 symbols 不同时返回
+pub(crate) async fn symbols_changed(market: &str, symbols: &BTreeSet<String>) {
+    loop {
+        tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
+        match diff_rate_symbols(market).await {
+            Ok(latest) if &latest != symbols => break,
+            Ok(_) => {}
+            Err(e) => error!("{} symbols err: {:?}", market, e),
+        }
+    }
+}
 
-            let price_stream = PriceStream {
-                tickers: tickers,
-                market: "delivery".to_string(),
-                local_time: chrono::Local::now().timestamp_millis(),
-            };
-            match price_tx.send(price_stream) {
-                Ok(_) => {}
-                Err(_e) => {
+/// 现货没有全市场 bookTicker, 按运行中差价记录的交易对组合订阅, 交易对变化时重新订阅
+pub async fn spot_book_ticker(book_tx: UnboundedSender<BookTickerStream>) {
+    let market = "spot";
+    while !book_tx.is_closed() {
+        let symbols = match diff_rate_symbols(market).await {
+            Ok(symbols) => symbols,
+            Err(e) => {
+                error!("{} book ticker symbols err: {:?}", market, e);
+                tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
+                continue;
+            }
+        };
+        if symbols.is_empty() {
+            tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
+            continue;
+        }
+
+        let keep_running = AtomicBool::new(true);
+        let endpoints: Vec<String> = symbols.iter().map(|s| book_ticker_stream(s)).collect();
+        let mut web_socket: WebSockets<'_, CombinedStreamEvent<BookTickerEvent>> =
+            WebSockets::new(|event: CombinedStreamEvent<BookTickerEvent>| {
+                let book_stream = BookTickerStream {
+                    ticker: event.data,
+                    market: market.to_string(),
+                    local_time: chrono::Local::now().timestamp_millis(),
+                };
+                if book_tx.send(book_stream).is_err() {
                     keep_running.store(false, Ordering::Relaxed);
                 }
+                Ok(())
+            });
+
+        select! {
+            res = web_socket.run_forever_multiple(market, &endpoints, &keep_running, ReconnectPolicy::default()) => {
+                if let Err(e) = res {
+                    error!("{} book ticker websocket error: {e}", market);
+                    tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+                }
             }
-
-            // Break the event loop
-            // keep_running.store(false, Ordering::Relaxed);
-            Ok(())
-        });
-
-    web_socket.connect_delivery(all_ticker).await.unwrap(); // check error
-    if let Err(e) = web_socket.event_loop(&keep_running).await {
-        error!("Error: {e}");
+            _ = symbols_changed(market, &symbols) => info!("{} book ticker symbols changed, resubscribing", market),
+        }
     }
-    web_socket.disconnect().await.unwrap();
-    info!("delivery websocket disconnected");
+    info!("{} book ticker websocket stopped", market);
 }
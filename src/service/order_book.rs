@@ -0,0 +1,430 @@
+//! 本地订单簿
+//!
+//! 订阅运行中差价记录交易对的 diff depth, 先缓存增量再拉取 REST 快照,
+//! 按 lastUpdateId 校验连续性, 断档时丢弃订单簿重新同步
+
+use crate::binance::rest_model::{OrderBook, OrderSide, PriceLevel};
+use crate::binance::websockets::*;
+use crate::binance::ws_model::{CombinedStreamEvent, DepthOrderBookEvent, WebsocketEvent};
+use crate::binance::MyApi;
+use crate::conf::{Config, PriceGuardConfig};
+use crate::service;
+use anyhow::anyhow;
+use chrono::Local;
+use dashmap::DashMap;
+use log::{error, info, warn};
+use once_cell::sync::Lazy;
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use tokio::select;
+
+/// 快照到达前最多缓存的增量条数
+const MAX_BUFFERED_EVENTS: usize = 1000;
+
+/// 快照档位数
+const SNAPSHOT_LIMIT: u16 = 1000;
+
+static ORDER_BOOKS: Lazy<DashMap<String, LocalOrderBook>> = Lazy::new(DashMap::new);
+
+fn book_key(market: &str, symbol: &str) -> String {
+    format!("{}:{}", market, symbol.to_uppercase())
+}
+
+/// 吃掉 qty 数量时的成交均价和需要的限价(最差一档价格)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VwapFill {
+    pub qty: Decimal,
+    pub avg_price: Decimal,
+    pub limit_price: Decimal,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LocalOrderBook {
+    bids: BTreeMap<Decimal, Decimal>,
+    asks: BTreeMap<Decimal, Decimal>,
+    last_update_id: u64,
+    /// 已加载快照
+    synced: bool,
+    /// 快照后的第一条增量已衔接
+    bridged: bool,
+    /// 快照到达前的增量
+    buffer: Vec<DepthOrderBookEvent>,
+    /// 最后一次更新的本地时间(毫秒)
+    pub local_time: i64,
+}
+
+impl LocalOrderBook {
+    pub fn is_synced(&self) -> bool {
+        self.synced
+    }
+
+    /// 已缓存增量, 等待快照
+    pub fn needs_snapshot(&self) -> bool {
+        !self.synced && !self.buffer.is_empty()
+    }
+
+    pub fn reset(&mut self) {
+        *self = LocalOrderBook {
+            local_time: self.local_time,
+            ..LocalOrderBook::default()
+        };
+    }
+
+    /// 加载快照并回放缓存的增量
+    pub fn apply_snapshot(&mut self, snapshot: &OrderBook) -> anyhow::Result<()> {
+        self.bids.clear();
+        self.asks.clear();
+        update_levels(&mut self.bids, &snapshot.bids)?;
+        update_levels(&mut self.asks, &snapshot.asks)?;
+        self.last_update_id = snapshot.last_update_id;
+        self.synced = true;
+        self.bridged = false;
+        for event in std::mem::take(&mut self.buffer) {
+            self.apply_event(event)?;
+        }
+        Ok(())
+    }
+
+    /// 应用一条增量, 与上一条不连续时报错, 调用方需 reset 后重新同步
+    ///
+    /// 现货: 丢弃 u <= lastUpdateId, 第一条满足 U <= lastUpdateId + 1 <= u, 之后 U == 上一条 u + 1
+    /// 合约: 丢弃 u < lastUpdateId, 第一条满足 U <= lastUpdateId <= u, 之后 pu == 上一条 u
+    pub fn apply_event(&mut self, event: DepthOrderBookEvent) -> anyhow::Result<()> {
+        if !self.synced {
+            if self.buffer.len() >= MAX_BUFFERED_EVENTS {
+                self.buffer.remove(0);
+            }
+            self.buffer.push(event);
+            return Ok(());
+        }
+
+        let futures = event.previous_final_update_id.is_some();
+        if !self.bridged {
+            let next = if futures {
+                self.last_update_id
+            } else {
+                self.last_update_id + 1
+            };
+            if event.final_update_id < next {
+                return Ok(());
+            }
+            if event.first_update_id > next {
+                return Err(anyhow!(
+                    "{} snapshot {} is older than update {}",
+                    event.symbol,
+                    self.last_update_id,
+                    event.first_update_id
+                ));
+            }
+        } else {
+            let continuous = match event.previous_final_update_id {
+                Some(pu) => pu == self.last_update_id,
+                None => event.first_update_id == self.last_update_id + 1,
+            };
+            if !continuous {
+                return Err(anyhow!(
+                    "{} update gap, last: {}, next: {}",
+                    event.symbol,
+                    self.last_update_id,
+                    event.first_update_id
+                ));
+            }
+        }
+
+        update_levels(&mut self.bids, &event.bids)?;
+        update_levels(&mut self.asks, &event.asks)?;
+        self.last_update_id = event.final_update_id;
+        self.bridged = true;
+        Ok(())
+    }
+
+    /// 买入吃卖盘、卖出吃买盘, 深度不足时报错
+    pub fn vwap(&self, side: &OrderSide, qty: Decimal) -> anyhow::Result<VwapFill> {
+        if qty <= Decimal::ZERO {
+            return Err(anyhow!("vwap qty must be positive: {}", qty));
+        }
+        let levels: Box<dyn Iterator<Item = (&Decimal, &Decimal)>> = match side {
+            OrderSide::Buy => Box::new(self.asks.iter()),
+            OrderSide::Sell => Box::new(self.bids.iter().rev()),
+        };
+
+        let mut remaining = qty;
+        let mut cost = Decimal::ZERO;
+        for (price, level_qty) in levels {
+            let take = remaining.min(*level_qty);
+            cost += take * price;
+            remaining -= take;
+            if remaining.is_zero() {
+                return Ok(VwapFill {
+                    qty,
+                    avg_price: cost / qty,
+                    limit_price: *price,
+                });
+            }
+        }
+        Err(anyhow!(
+            "order book too thin, {} of {} unfilled",
+            remaining,
+            qty
+        ))
+    }
+}
+
+/// 数量为 0 的档位删除
+fn update_levels(
+    book: &mut BTreeMap<Decimal, Decimal>,
+    levels: &[PriceLevel],
+) -> anyhow::Result<()> {
+    for level in levels {
+        let price = Decimal::from_str(level.price.as_str())?;
+        let qty = Decimal::from_str(level.qty.as_str())?;
+        if qty.is_zero() {
+            book.remove(&price);
+        } else {
+            book.insert(price, qty);
+        }
+    }
+    Ok(())
+}
+
+/// 按本地订单簿计算 qty 的成交均价, 订单簿未同步或超过市场配置的最大年龄时报错
+pub fn vwap_fill(
+    guard: &PriceGuardConfig,
+    market: &str,
+    symbol: &str,
+    side: &OrderSide,
+    qty: Decimal,
+) -> anyhow::Result<VwapFill> {
+    let key = book_key(market, symbol);
+    let book = ORDER_BOOKS
+        .get(&key)
+        .ok_or(anyhow!("{} order book not subscribed", key))?;
+    if !book.is_synced() {
+        return Err(anyhow!("{} order book not synced", key));
+    }
+    let age = Local::now().timestamp_millis() - book.local_time;
+    let max_age = guard.max_age_ms(market);
+    if age > max_age {
+        return Err(anyhow!(
+            "{} order book is stale, age: {}ms, max: {}ms",
+            key,
+            age,
+            max_age
+        ));
+    }
+    book.vwap(side, qty)
+}
+
+/// 测试直接加载快照, 不订阅 diff depth
+#[cfg(test)]
+pub(crate) fn load_snapshot(
+    market: &str,
+    symbol: &str,
+    snapshot: &OrderBook,
+) -> anyhow::Result<()> {
+    let mut book = ORDER_BOOKS.entry(book_key(market, symbol)).or_default();
+    book.reset();
+    book.apply_snapshot(snapshot)?;
+    book.local_time = Local::now().timestamp_millis();
+    Ok(())
+}
+
+async fn depth_snapshot(api: &MyApi, market: &str, symbol: &str) -> anyhow::Result<OrderBook> {
+    let snapshot = match market {
+        "spot" => api.get_depth(symbol, SNAPSHOT_LIMIT).await?,
+        "futures" => api.futures_get_depth(symbol, SNAPSHOT_LIMIT).await?,
+        "delivery" => api.delivery_get_depth(symbol, SNAPSHOT_LIMIT).await?,
+        _ => return Err(anyhow!("unsupported market: {}", market)),
+    };
+    Ok(snapshot)
+}
+
+async fn handle_depth(api: &MyApi, market: &str, event: DepthOrderBookEvent) {
+    let symbol = event.symbol.clone();
+    let key = book_key(market, &symbol);
+    // 不能持有 DashMap 的锁等待快照
+    let needs_snapshot = {
+        let mut book = ORDER_BOOKS.entry(key.clone()).or_default();
+        book.local_time = Local::now().timestamp_millis();
+        if let Err(e) = book.apply_event(event) {
+            warn!("{} resync order book: {}", key, e);
+            book.reset();
+        }
+        book.needs_snapshot()
+    };
+    if !needs_snapshot {
+        return;
+    }
+
+    match depth_snapshot(api, market, &symbol).await {
+        Ok(snapshot) => {
+            if let Some(mut book) = ORDER_BOOKS.get_mut(&key) {
+                if let Err(e) = book.apply_snapshot(&snapshot) {
+                    warn!("{} resync order book: {}", key, e);
+                    book.reset();
+                } else {
+                    info!("{} order book synced at {}", key, snapshot.last_update_id);
+                }
+            }
+        }
+        Err(e) => {
+            error!("{} depth snapshot err: {:?}", key, e);
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        }
+    }
+}
+
+/// 维护 market 上运行中差价记录交易对的本地订单簿, 交易对变化时重新订阅
+pub async fn order_book_stream(config: Arc<Config>, market: &'static str) {
+    let api = MyApi::from_config(&config);
+    loop {
+        let symbols = match service::diff_rate_symbols(market).await {
+            Ok(symbols) => symbols,
+            Err(e) => {
+                error!("{} order book symbols err: {:?}", market, e);
+                tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
+                continue;
+            }
+        };
+        // 断线期间的增量已丢失, 重新订阅时从快照开始
+        ORDER_BOOKS.retain(|key, _| !key.starts_with(&format!("{}:", market)));
+        if symbols.is_empty() {
+            tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
+            continue;
+        }
+
+        let (depth_tx, mut depth_rx) = tokio::sync::mpsc::unbounded_channel();
+        let keep_running = AtomicBool::new(true);
+        let endpoints: Vec<String> = symbols
+            .iter()
+            .map(|s| diff_book_depth_stream(s, 100))
+            .collect();
+        let mut web_socket: WebSockets<'_, CombinedStreamEvent<WebsocketEvent>> =
+            WebSockets::new(|event: CombinedStreamEvent<WebsocketEvent>| {
+                if let WebsocketEvent::DepthOrderBook(depth) = event.data {
+                    let _ = depth_tx.send(*depth);
+                }
+                Ok(())
+            });
+
+        let apply = async {
+            while let Some(event) = depth_rx.recv().await {
+                handle_depth(&api, market, event).await;
+            }
+        };
+
+        select! {
+            res = web_socket.run_forever_multiple(market, &endpoints, &keep_running, ReconnectPolicy::default()) => {
+                if let Err(e) = res {
+                    error!("{} order book websocket error: {e}", market);
+                    tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+                }
+            }
+            _ = apply => {}
+            _ = service::symbols_changed(market, &symbols) => {
+                info!("{} order book symbols changed, resubscribing", market)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    fn levels(levels: &[(&str, &str)]) -> Vec<PriceLevel> {
+        levels
+            .iter()
+            .map(|(price, qty)| PriceLevel {
+                price: price.to_string(),
+                qty: qty.to_string(),
+            })
+            .collect()
+    }
+
+    fn depth(first: u64, last: u64, pu: Option<u64>, asks: &[(&str, &str)]) -> DepthOrderBookEvent {
+        DepthOrderBookEvent {
+            event_time: 0,
+            transaction_time: None,
+            symbol: "BTCUSDT".to_string(),
+            first_update_id: first,
+            final_update_id: last,
+            previous_final_update_id: pu,
+            bids: vec![],
+            asks: levels(asks),
+        }
+    }
+
+    fn snapshot(last_update_id: u64) -> OrderBook {
+        OrderBook {
+            last_update_id,
+            bids: levels(&[("99", "1"), ("98", "2")]),
+            asks: levels(&[("100", "1"), ("101", "2"), ("102", "5")]),
+        }
+    }
+
+    #[test]
+    fn test_spot_sequencing() {
+        let mut book = LocalOrderBook::default();
+        // 快照前的增量先缓存, 回放时丢弃 u <= lastUpdateId
+        book.apply_event(depth(95, 100, None, &[("100", "9")]))
+            .unwrap();
+        book.apply_event(depth(101, 105, None, &[("100", "0")]))
+            .unwrap();
+        assert!(book.needs_snapshot());
+        book.apply_snapshot(&snapshot(102)).unwrap();
+        assert!(book.is_synced());
+        assert!(!book.asks.contains_key(&d("100")));
+
+        book.apply_event(depth(106, 107, None, &[("103", "1")]))
+            .unwrap();
+        assert_eq!(book.last_update_id, 107);
+        // 断档
+        assert!(book.apply_event(depth(109, 110, None, &[])).is_err());
+
+        // 快照早于第一条增量
+        let mut book = LocalOrderBook::default();
+        book.apply_event(depth(120, 125, None, &[])).unwrap();
+        assert!(book.apply_snapshot(&snapshot(110)).is_err());
+    }
+
+    #[test]
+    fn test_futures_sequencing() {
+        let mut book = LocalOrderBook::default();
+        book.apply_event(depth(90, 99, Some(89), &[])).unwrap();
+        book.apply_event(depth(100, 110, Some(99), &[("101", "3")]))
+            .unwrap();
+        book.apply_snapshot(&snapshot(105)).unwrap();
+        assert_eq!(book.asks.get(&d("101")), Some(&d("3")));
+
+        book.apply_event(depth(111, 115, Some(110), &[])).unwrap();
+        assert!(book.apply_event(depth(120, 125, Some(118), &[])).is_err());
+    }
+
+    #[test]
+    fn test_vwap() {
+        let mut book = LocalOrderBook::default();
+        book.apply_event(depth(1, 1, None, &[])).unwrap();
+        book.apply_snapshot(&snapshot(1)).unwrap();
+
+        // 1 @ 100 + 2 @ 101 + 1 @ 102
+        let fill = book.vwap(&OrderSide::Buy, d("4")).unwrap();
+        assert_eq!(fill.avg_price, d("101"));
+        assert_eq!(fill.limit_price, d("102"));
+
+        // 1 @ 99 + 1 @ 98
+        let fill = book.vwap(&OrderSide::Sell, d("2")).unwrap();
+        assert_eq!(fill.avg_price, d("98.5"));
+        assert_eq!(fill.limit_price, d("98"));
+
+        assert!(book.vwap(&OrderSide::Sell, d("3.5")).is_err());
+        assert!(book.vwap(&OrderSide::Buy, Decimal::ZERO).is_err());
+    }
+}
@@ -1,9 +1,14 @@
-use crate::binance::ws_model::MiniDayTickerEvent;
-use crate::conf::redis_key;
-use crate::db;
-use crate::service::PriceStream;
+use crate::binance::rest_model::KlineSummary;
+use crate::binance::ws_model::{BookTickerEvent, MiniDayTickerEvent};
+use crate::conf::{redis_key, PriceGuardConfig};
+use crate::service::{BookTickerStream, PriceStream};
+use crate::{db, model};
+use anyhow::anyhow;
+use chrono::Local;
+use log::error;
 use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use tokio::select;
 use tokio::sync::mpsc::UnboundedReceiver;
 
@@ -11,6 +16,17 @@ use tokio::sync::mpsc::UnboundedReceiver;
 pub struct PriceInfo {
     pub ticker: MiniDayTickerEvent,
     pub market: String,
+    /// 本地接收时间(毫秒), ticker.event_time 为交易所事件时间
+    pub local_time: i64,
+}
+
+/// redis 中缓存的最新价, 旧格式没有 local_time 时视为过期
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct CachedTicker {
+    #[serde(flatten)]
+    ticker: MiniDayTickerEvent,
+    #[serde(default)]
+    local_time: i64,
 }
 
 pub async fn set_binance_price(mut price_rx: UnboundedReceiver<PriceStream>) {
@@ -24,8 +40,10 @@ pub async fn set_binance_price(mut price_rx: UnboundedReceiver<PriceStream>) {
 
                     let mut items = vec![];
                     for ticker in stream.tickers {
-                        let ticker_json = serde_json::to_string(&ticker).unwrap();
-                        items.push((ticker.symbol, ticker_json))
+                        let symbol = ticker.symbol.clone();
+                        let cached = CachedTicker { ticker, local_time: stream.local_time };
+                        let ticker_json = serde_json::to_string(&cached).unwrap();
+                        items.push((symbol, ticker_json))
                     }
                     let _: () = redis.hset_multiple(key, &items).await.unwrap();
                     // info!("--------------, set binance {:?} price, current time: {:?}", stream.market, Local::now().timestamp_millis());
@@ -40,8 +58,265 @@ pub async fn get_binance_price(market: String, symbol: String) -> anyhow::Result
 
     let key = format!("{}{}", market, redis_key::PRICE_KEY);
     let x: String = redis.hget(key, symbol).await?;
-    let ticker = serde_json::from_str::<MiniDayTickerEvent>(x.as_str()).unwrap();
-    let info = PriceInfo { ticker, market };
+    let cached = serde_json::from_str::<CachedTicker>(x.as_str())?;
+    let info = PriceInfo {
+        ticker: cached.ticker,
+        market,
+        local_time: cached.local_time,
+    };
 
     Ok(info)
 }
+
+/// redis 中缓存的 market 全部最新价, 按 symbol 排序
+pub async fn get_binance_price_list(market: String) -> anyhow::Result<Vec<PriceInfo>> {
+    let mut redis = db::get_db()?.redis().await?;
+
+    let key = format!("{}{}", market, redis_key::PRICE_KEY);
+    let items: HashMap<String, String> = redis.hgetall(key).await?;
+    let mut list = Vec::with_capacity(items.len());
+    for x in items.values() {
+        let cached = serde_json::from_str::<CachedTicker>(x.as_str())?;
+        list.push(PriceInfo {
+            ticker: cached.ticker,
+            market: market.clone(),
+            local_time: cached.local_time,
+        });
+    }
+    list.sort_by(|a, b| a.ticker.symbol.cmp(&b.ticker.symbol));
+    Ok(list)
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BookTickerInfo {
+    pub ticker: BookTickerEvent,
+    pub market: String,
+    /// 本地接收时间(毫秒)
+    pub local_time: i64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct CachedBookTicker {
+    #[serde(flatten)]
+    ticker: BookTickerEvent,
+    local_time: i64,
+}
+
+/// 缓存买一卖一, bookTicker 推送频率很高, 同一交易对只保留最新一条, 每 200ms 批量写入 redis
+pub async fn set_binance_book_ticker(mut book_rx: UnboundedReceiver<BookTickerStream>) {
+    let mut redis = db::get_db().unwrap().redis().await.unwrap();
+    let mut pending: HashMap<String, HashMap<String, CachedBookTicker>> = HashMap::new();
+    let mut flush = tokio::time::interval(tokio::time::Duration::from_millis(200));
+    loop {
+        select! {
+            event = book_rx.recv() => {
+                let Some(stream) = event else {
+                    break;
+                };
+                let cached = CachedBookTicker { ticker: stream.ticker, local_time: stream.local_time };
+                pending
+                    .entry(stream.market)
+                    .or_default()
+                    .insert(cached.ticker.symbol.clone(), cached);
+            },
+            _ = flush.tick() => {
+                for (market, tickers) in pending.drain() {
+                    let key = format!("{}{}", market, redis_key::BOOK_TICKER_KEY);
+                    let mut items = vec![];
+                    for (symbol, cached) in tickers {
+                        items.push((symbol, serde_json::to_string(&cached).unwrap()));
+                    }
+                    if let Err(e) = redis.hset_multiple::<_, _, _, ()>(key, &items).await {
+                        error!("set {} book ticker err: {:?}", market, e);
+                    }
+                }
+            },
+        }
+    }
+}
+
+pub async fn get_binance_book_ticker(
+    market: String,
+    symbol: String,
+) -> anyhow::Result<BookTickerInfo> {
+    let mut redis = db::get_db()?.redis().await?;
+
+    let key = format!("{}{}", market, redis_key::BOOK_TICKER_KEY);
+    let x: String = redis.hget(key, symbol).await?;
+    let cached = serde_json::from_str::<CachedBookTicker>(x.as_str())?;
+    Ok(BookTickerInfo {
+        ticker: cached.ticker,
+        market,
+        local_time: cached.local_time,
+    })
+}
+
+/// 读取买一卖一, 超过市场配置的最大年龄时报错
+pub async fn get_fresh_book_ticker(
+    guard: &PriceGuardConfig,
+    market: String,
+    symbol: String,
+) -> anyhow::Result<BookTickerInfo> {
+    let info = get_binance_book_ticker(market, symbol).await?;
+    let age = Local::now().timestamp_millis() - info.local_time;
+    let max_age = guard.max_age_ms(&info.market);
+    if age > max_age {
+        return Err(anyhow!(
+            "{} {} book ticker is stale, age: {}ms, max: {}ms",
+            info.market,
+            info.ticker.symbol,
+            age,
+            max_age
+        ));
+    }
+    Ok(info)
+}
+
+/// 读取策略两腿的最新价, 任一腿过期或两腿时间相差过大时报错
+pub async fn get_fresh_leg_prices(
+    guard: &PriceGuardConfig,
+    strategy: &model::ArbStrategy,
+) -> anyhow::Result<(PriceInfo, PriceInfo)> {
+    let from =
+        get_binance_price(strategy.from_market.clone(), strategy.from_symbol.clone()).await?;
+    let to = get_binance_price(strategy.to_market.clone(), strategy.to_symbol.clone()).await?;
+    check_leg_prices(guard, &from, &to, Local::now().timestamp_millis())?;
+    Ok((from, to))
+}
+
+/// 价格超过市场配置的最大年龄时报错, now 为本地毫秒时间
+pub fn check_price_age(guard: &PriceGuardConfig, info: &PriceInfo, now: i64) -> anyhow::Result<()> {
+    let age = now - info.local_time;
+    let max_age = guard.max_age_ms(&info.market);
+    if age > max_age {
+        return Err(anyhow!(
+            "{} {} price is stale, age: {}ms, max: {}ms",
+            info.market,
+            info.ticker.symbol,
+            age,
+            max_age
+        ));
+    }
+    Ok(())
+}
+
+/// 两腿价格都未过期, 且事件时间间隔不超过 max_leg_skew_ms
+pub fn check_leg_prices(
+    guard: &PriceGuardConfig,
+    from: &PriceInfo,
+    to: &PriceInfo,
+    now: i64,
+) -> anyhow::Result<()> {
+    check_price_age(guard, from, now)?;
+    check_price_age(guard, to, now)?;
+    let skew = (from.ticker.event_time as i64 - to.ticker.event_time as i64).abs();
+    if skew > guard.max_leg_skew_ms {
+        return Err(anyhow!(
+            "{} and {} prices are {}ms apart, max: {}ms",
+            from.ticker.symbol,
+            to.ticker.symbol,
+            skew,
+            guard.max_leg_skew_ms
+        ));
+    }
+    Ok(())
+}
+
+/// 最后一根k线的收盘时间早于 now - 最大年龄时报错, 未收盘的k线收盘时间在未来
+pub fn check_kline_age(
+    guard: &PriceGuardConfig,
+    market: &str,
+    kline: &KlineSummary,
+    now: i64,
+) -> anyhow::Result<()> {
+    let age = now - kline.close_time;
+    let max_age = guard.max_age_ms(market);
+    if age > max_age {
+        return Err(anyhow!(
+            "{} kline is stale, closed {}ms ago, max: {}ms",
+            market,
+            age,
+            max_age
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn price(market: &str, event_time: u64, local_time: i64) -> PriceInfo {
+        PriceInfo {
+            ticker: MiniDayTickerEvent {
+                event_time,
+                symbol: "BTCUSDT".to_string(),
+                pair: None,
+                current_close: "67000".to_string(),
+                open: "67000".to_string(),
+                high: "67000".to_string(),
+                low: "67000".to_string(),
+                volume: "0".to_string(),
+                quote_volume: "0".to_string(),
+            },
+            market: market.to_string(),
+            local_time,
+        }
+    }
+
+    fn guard() -> PriceGuardConfig {
+        PriceGuardConfig {
+            default_max_age_ms: 5000,
+            max_age_ms: HashMap::from([("delivery".to_string(), 10_000)]),
+            max_leg_skew_ms: 2000,
+        }
+    }
+
+    #[test]
+    fn test_check_leg_prices() {
+        let now = 1_700_000_100_000;
+        let spot = price("spot", 1_700_000_099_000, now - 1000);
+        let delivery = price("delivery", 1_700_000_098_000, now - 8000);
+        assert!(check_leg_prices(&guard(), &spot, &delivery, now).is_ok());
+
+        // spot 默认 5s 过期, delivery 单独配置 10s
+        let old_spot = price("spot", 1_700_000_094_000, now - 6000);
+        assert!(check_leg_prices(&guard(), &old_spot, &delivery, now).is_err());
+        let old_delivery = price("delivery", 1_700_000_098_000, now - 11_000);
+        assert!(check_leg_prices(&guard(), &spot, &old_delivery, now).is_err());
+
+        // 两腿都新鲜但事件时间相差超过 2s
+        let skewed = price("delivery", 1_700_000_096_500, now - 500);
+        assert!(check_leg_prices(&guard(), &spot, &skewed, now).is_err());
+
+        // 旧格式缓存没有接收时间
+        let cached: CachedTicker = serde_json::from_str(
+            r#"{"E":1,"s":"BTCUSDT","ps":null,"c":"1","o":"1","h":"1","l":"1","v":"0","q":"0"}"#,
+        )
+        .unwrap();
+        assert_eq!(cached.local_time, 0);
+    }
+
+    #[test]
+    fn test_check_kline_age() {
+        let now = 1_700_000_100_000;
+        let kline = |close_time: i64| KlineSummary {
+            open_time: close_time - 899_999,
+            open: 1.0,
+            high: 1.0,
+            low: 1.0,
+            close: 1.0,
+            volume: 0.0,
+            close_time,
+            quote_asset_volume: 0.0,
+            number_of_trades: 0,
+            taker_buy_base_asset_volume: 0.0,
+            taker_buy_quote_asset_volume: 0.0,
+        };
+        // 未收盘
+        assert!(check_kline_age(&guard(), "spot", &kline(now + 60_000), now).is_ok());
+        assert!(check_kline_age(&guard(), "spot", &kline(now - 4000), now).is_ok());
+        assert!(check_kline_age(&guard(), "spot", &kline(now - 900_000), now).is_err());
+    }
+}
@@ -0,0 +1,530 @@
+//! 启动对账
+//!
+//! 进程停止期间订单可能已成交、划转可能已完成但未落库。启动时对每个执行中的策略,
+//! 按交易所订单、划转历史回写 arb_strategy_ex / arb_strategy_ex_info, 再核对合约持仓;
+//! 确认不一致的策略标记为需人工处理, 不再继续交易。交易所超时、限频或 5xx 时重试,
+//! 仍失败的策略只记录日志, 由执行器继续跟进
+
+use crate::binance::api::{FuturesGetOrderRequest, OrderStatusRequest};
+use crate::binance::errors::Error;
+use crate::binance::rest_model::{OrderSide, OrderStatus, UniversalTransferType};
+use crate::binance::{ApiPool, MyApi};
+use crate::service::binance_strategy::{
+    leg_amount, order_closed, order_filled, recover_order, transfer_done, transfer_ex_done,
+};
+use crate::service::state_machine::{LegAction, StrategyMachine, StrategyState};
+use crate::service::{account, rollover};
+use crate::{model, sql};
+use anyhow::anyhow;
+use log::{error, info, warn};
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// 交易所暂时不可用时对账的尝试次数
+const RECONCILE_ATTEMPTS: u64 = 3;
+
+/// 对账所有执行中的策略, 数据库不可用时返回错误; 单个用户的失败不影响其它用户
+pub async fn reconcile_strategies(pool: &ApiPool) -> anyhow::Result<()> {
+    let strategy_list =
+        sql::get_arb_strategy_list_by_doing_status(model::arb_strategy::DOING_STATUS_RUN).await?;
+
+    // 按用户对账, 持仓只和同一账户的策略比较
+    let mut reconciled: BTreeMap<i64, Vec<model::ArbStrategy>> = BTreeMap::new();
+    for strategy in strategy_list {
+        let result = match account::user_api(pool, strategy.user_id) {
+            Ok(api) => reconcile_with_retry(&api, &strategy).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => reconciled
+                .entry(strategy.user_id)
+                .or_default()
+                .push(strategy),
+            // 无法确认也无法否定, 不参与持仓核对
+            Err(e) if is_transient(&e) => error!(
+                "strategy_id: {}, reconcile skipped, binance unavailable: {}",
+                strategy.id, e
+            ),
+            Err(e) => flag_manual(strategy.id, &e.to_string()).await?,
+        }
+    }
+    let mut count = 0;
+    for (user_id, strategy_list) in reconciled {
+        let result = match account::user_api(pool, user_id) {
+            Ok(api) => check_positions(&api, &strategy_list).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            error!("user_id: {}, check positions err: {:?}", user_id, e);
+            continue;
+        }
+        count += strategy_list.len();
+    }
+    info!("reconciled {} strategies", count);
+    Ok(())
+}
+
+/// 超时、限频、5xx 等交易所暂时性错误
+pub fn is_transient(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<Error>().is_some_and(Error::is_transient)
+}
+
+async fn reconcile_with_retry(api: &MyApi, strategy: &model::ArbStrategy) -> anyhow::Result<()> {
+    let mut attempt = 1;
+    loop {
+        match reconcile_strategy(api, strategy).await {
+            Err(e) if is_transient(&e) && attempt < RECONCILE_ATTEMPTS => {
+                warn!(
+                    "strategy_id: {}, reconcile attempt {} failed, retrying: {}",
+                    strategy.id, attempt, e
+                );
+                tokio::time::sleep(std::time::Duration::from_secs(attempt)).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+async fn flag_manual(strategy_id: i64, reason: &str) -> anyhow::Result<()> {
+    error!(
+        "strategy_id: {}, needs manual attention: {}",
+        strategy_id, reason
+    );
+    sql::update_strategy_manual(strategy_id, reason).await?;
+    Ok(())
+}
+
+/// 回写停机期间完成的订单和划转
+async fn reconcile_strategy(api: &MyApi, strategy: &model::ArbStrategy) -> anyhow::Result<()> {
+    let machine = StrategyMachine::of(strategy).ok_or(anyhow!(
+        "unsupported strategy {} {} -> {}",
+        strategy.option_choose,
+        strategy.from_market,
+        strategy.to_market
+    ))?;
+    let ex_list = sql::get_arb_strategy_ex_list_by_strategy_id(strategy.id).await?;
+    // 还未生成执行记录
+    if ex_list.is_empty() {
+        return Ok(());
+    }
+    let (rolls, legs): (Vec<_>, Vec<_>) = ex_list.into_iter().partition(rollover::is_roll);
+
+    // 只有当前腿可能在停机期间执行过, 之后的腿还未开始
+    if let StrategyState::Leg(step) = machine.state(&legs)? {
+        let ex = &legs[step];
+        match &machine.legs[step].action {
+            LegAction::Transfer(transfer_type) => {
+                // 划转发生在上一条腿完成之后
+                let since = match step {
+                    0 => ex.created,
+                    _ => legs[step - 1].updated,
+                };
+                let since = since.unwrap_or_default() as u64 * 1000;
+                let diff_rate_info =
+                    sql::get_arb_diff_rate_info_by_diff_rate_id(strategy.diff_rate_id).await?;
+                let amount = leg_amount(
+                    api,
+                    strategy,
+                    &machine,
+                    &legs,
+                    &rolls,
+                    step,
+                    &diff_rate_info,
+                    None,
+                )
+                .await?;
+                reconcile_transfer(api, strategy, ex, transfer_type, since, amount).await?
+            }
+            _ => reconcile_order(api, ex).await?,
+        }
+    }
+    for ex in rolls
+        .iter()
+        .filter(|ex| ex.option_status == model::arb_strategy_ex::OPTION_STATUS_UN_DONE)
+    {
+        reconcile_order(api, ex).await?;
+    }
+    Ok(())
+}
+
+/// 已下单的腿按订单状态回写; 未落库订单ID的按客户端订单ID找回, 之后由执行器继续
+async fn reconcile_order(api: &MyApi, ex: &model::ArbStrategyEx) -> anyhow::Result<()> {
+    if ex.current_order_id.is_empty() {
+        recover_order(api, ex.market.as_str(), ex.symbol.as_str(), ex).await?;
+        return Ok(());
+    }
+    let ex_info =
+        sql::find_arb_strategy_ex_info_by_order_id(ex.market.as_str(), ex.current_order_id.clone())
+            .await?
+            .ok_or(anyhow!(
+                "arb_strategy_ex {} order {} has no arb_strategy_ex_info",
+                ex.id,
+                ex.current_order_id
+            ))?;
+
+    let (status, executed_qty) = order_status(api, ex).await?;
+    match status.as_str() {
+        "FILLED" => order_filled(ex.id, ex_info.id, executed_qty.to_string()).await?,
+        "NEW" | "PARTIALLY_FILLED" => return Ok(()),
+        _ => order_closed(ex.id, ex_info.id, executed_qty.to_string()).await?,
+    }
+    warn!(
+        "strategy_id: {}, {} order {} {} while offline, executed: {}",
+        ex.arb_strategy_id, ex.option_type, ex.current_order_id, status, executed_qty
+    );
+    Ok(())
+}
+
+/// 订单状态(FILLED 等)和成交数量, 交易所上不存在的订单无法对账
+async fn order_status(api: &MyApi, ex: &model::ArbStrategyEx) -> anyhow::Result<(String, f64)> {
+    let request = FuturesGetOrderRequest {
+        symbol: ex.symbol.clone(),
+        order_id: Some(ex.current_order_id.clone()),
+        orig_client_order_id: None,
+    };
+    let status = match ex.market.as_str() {
+        "spot" => api
+            .order_status(OrderStatusRequest {
+                symbol: ex.symbol.clone(),
+                order_id: Some(ex.current_order_id.parse::<u64>()?),
+                orig_client_order_id: None,
+                recv_window: None,
+            })
+            .await
+            .map(|o| (spot_status(&o.status).to_string(), o.executed_qty)),
+        "futures" => api
+            .futures_order_status(request)
+            .await
+            .map(|o| (o.status, o.executed_qty)),
+        "delivery" => api
+            .delivery_order_status(request)
+            .await
+            .map(|o| (o.status, o.executed_qty)),
+        market => return Err(anyhow!("unsupported market: {}", market)),
+    };
+    status.map_err(|e| match e {
+        Error::BinanceError { ref response } if !e.is_transient() => anyhow!(
+            "{} order {} of arb_strategy_ex {}: {}",
+            ex.market,
+            ex.current_order_id,
+            ex.id,
+            response
+        ),
+        e => e.into(),
+    })
+}
+
+fn spot_status(status: &OrderStatus) -> &'static str {
+    match status {
+        OrderStatus::New | OrderStatus::PendingCancel => "NEW",
+        OrderStatus::PartiallyFilled => "PARTIALLY_FILLED",
+        OrderStatus::Filled => "FILLED",
+        OrderStatus::Canceled => "CANCELED",
+        OrderStatus::Rejected => "REJECTED",
+        OrderStatus::Expired => "EXPIRED",
+        OrderStatus::ExpiredInMatch => "EXPIRED_IN_MATCH",
+    }
+}
+
+/// 划转历史中的数量等于本腿应划转的数量
+fn transfer_matches(amount: f64, expected: Decimal) -> bool {
+    Decimal::from_f64(amount).is_some_and(|amount| amount == expected)
+}
+
+/// 划转请求已成功但未落库时按划转历史补记, 只认数量与本腿一致的划转,
+/// 有多条无法区分时需人工处理
+async fn reconcile_transfer(
+    api: &MyApi,
+    strategy: &model::ArbStrategy,
+    ex: &model::ArbStrategyEx,
+    transfer_type: &UniversalTransferType,
+    since: u64,
+    amount: Decimal,
+) -> anyhow::Result<()> {
+    // 详情已插入但执行记录未更新
+    let ex_info_list = sql::get_arb_strategy_ex_info_list_by_ex_id(ex.id).await?;
+    if let Some(ex_info) = ex_info_list
+        .iter()
+        .find(|i| i.is_ok == model::arb_strategy_ex_info::IS_OK_DONE)
+    {
+        let tran_id = ex_info.order_id.parse::<u64>()?;
+        return transfer_ex_done(ex.id, ex_info.executed_amt, tran_id).await;
+    }
+
+    let history = api
+        .transfer_history(transfer_type.clone(), Some(since))
+        .await?;
+    let mut unrecorded = vec![];
+    for row in history
+        .rows
+        .into_iter()
+        .filter(|r| r.asset == strategy.coin && r.status == "CONFIRMED")
+    {
+        // 数量不同的是其它划转(如手动划转)
+        if !transfer_matches(row.amount, amount) {
+            continue;
+        }
+        if sql::find_arb_strategy_ex_info_by_order_id("transfer", row.tran_id.to_string())
+            .await?
+            .is_none()
+        {
+            unrecorded.push(row);
+        }
+    }
+    match unrecorded.as_slice() {
+        [] => Ok(()),
+        [row] => {
+            warn!(
+                "strategy_id: {}, {} transfer {} done while offline, amount: {}",
+                strategy.id, ex.option_type, row.tran_id, amount
+            );
+            transfer_done(
+                api,
+                strategy,
+                ex,
+                ex.option_type.clone(),
+                amount,
+                row.tran_id,
+            )
+            .await
+        }
+        rows => Err(anyhow!(
+            "{} unrecorded {:?} transfers of {} {} since {}",
+            rows.len(),
+            transfer_type,
+            amount,
+            strategy.coin,
+            since
+        )),
+    }
+}
+
+/// 已完成的合约腿按 (market, symbol) 汇总的净持仓, 买为正、卖为负, 币本位为张数
+pub fn expected_positions(
+    machine: &StrategyMachine,
+    ex_list: &[model::ArbStrategyEx],
+) -> BTreeMap<(String, String), Decimal> {
+    let mut positions = BTreeMap::new();
+    let (rolls, legs): (Vec<_>, Vec<_>) = ex_list.iter().partition(|ex| rollover::is_roll(ex));
+    let legs = legs
+        .into_iter()
+        .zip(&machine.legs)
+        .filter_map(|(ex, leg)| match &leg.action {
+            LegAction::Futures(side) | LegAction::Delivery(side) => Some((ex, side.clone())),
+            _ => None,
+        });
+    let rolls = rolls.into_iter().map(|ex| {
+        let side = match ex.option_type.as_str() {
+            rollover::ROLL_BUY => OrderSide::Buy,
+            _ => OrderSide::Sell,
+        };
+        (ex, side)
+    });
+    for (ex, side) in legs.chain(rolls) {
+        if ex.option_status != model::arb_strategy_ex::OPTION_STATUS_DONE {
+            continue;
+        }
+        let amount = match side {
+            OrderSide::Buy => ex.option_executed_amt,
+            OrderSide::Sell => -ex.option_executed_amt,
+        };
+        *positions
+            .entry((ex.market.clone(), ex.symbol.clone()))
+            .or_insert(Decimal::ZERO) += amount;
+    }
+    positions
+}
+
+/// 实际持仓方向相同且数量不少于应有持仓, 账户中允许有策略之外的持仓
+pub fn position_covers(expected: f64, actual: f64) -> bool {
+    const EPSILON: f64 = 1e-8;
+    if expected.abs() < EPSILON {
+        return true;
+    }
+    actual * expected.signum() >= expected.abs() - EPSILON
+}
+
+/// 核对合约持仓, 持仓少于策略应有持仓时对应策略需人工处理; 有挂单的交易对跳过
+async fn check_positions(api: &MyApi, strategy_list: &[model::ArbStrategy]) -> anyhow::Result<()> {
+    let mut expected: BTreeMap<(String, String), (Decimal, Vec<i64>)> = BTreeMap::new();
+    let mut pending = BTreeSet::new();
+    for strategy in strategy_list {
+        let Some(machine) = StrategyMachine::of(strategy) else {
+            continue;
+        };
+        let ex_list = sql::get_arb_strategy_ex_list_by_strategy_id(strategy.id).await?;
+        for ex in ex_list.iter().filter(|ex| {
+            ex.option_status == model::arb_strategy_ex::OPTION_STATUS_UN_DONE
+                && !ex.current_order_id.is_empty()
+        }) {
+            pending.insert((ex.market.clone(), ex.symbol.clone()));
+        }
+        for (key, amount) in expected_positions(&machine, &ex_list) {
+            let entry = expected.entry(key).or_insert((Decimal::ZERO, vec![]));
+            entry.0 += amount;
+            entry.1.push(strategy.id);
+        }
+    }
+
+    for ((market, symbol), (amount, strategy_ids)) in expected {
+        if pending.contains(&(market.clone(), symbol.clone())) {
+            info!(
+                "{} {} has open orders, position not checked",
+                market, symbol
+            );
+            continue;
+        }
+        let positions = match market.as_str() {
+            "futures" => api.futures_position_risk(symbol.as_str()).await?,
+            "delivery" => {
+                let pair = symbol.split('_').next().unwrap_or(symbol.as_str());
+                api.delivery_position_risk(pair).await?
+            }
+            _ => continue,
+        };
+        let actual: f64 = positions
+            .iter()
+            .filter(|p| p.symbol == symbol)
+            .map(|p| p.position_amt)
+            .sum();
+        let amount = amount.to_f64().unwrap_or_default();
+        if !position_covers(amount, actual) {
+            let reason = format!(
+                "{} {} position {} does not cover expected {}",
+                market, symbol, actual, amount
+            );
+            for strategy_id in strategy_ids {
+                flag_manual(strategy_id, &reason).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn d(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_expected_positions() {
+        let strategy = model::ArbStrategy {
+            option_choose: "positive".to_string(),
+            from_market: "spot".to_string(),
+            to_market: "delivery".to_string(),
+            ..model::ArbStrategy::default()
+        };
+        let machine = StrategyMachine::of(&strategy).unwrap();
+        let ex = |option_type: &str, market: &str, symbol: &str, done: bool, amt: &str| {
+            model::ArbStrategyEx {
+                market: market.to_string(),
+                symbol: symbol.to_string(),
+                option_type: option_type.to_string(),
+                option_status: if done {
+                    model::arb_strategy_ex::OPTION_STATUS_DONE
+                } else {
+                    model::arb_strategy_ex::OPTION_STATUS_UN_DONE
+                },
+                option_executed_amt: d(amt),
+                ..model::ArbStrategyEx::default()
+            }
+        };
+        let mut ex_list: Vec<model::ArbStrategyEx> = machine
+            .legs
+            .iter()
+            .enumerate()
+            .map(|(i, leg)| {
+                let market = leg.market(&strategy);
+                let symbol = match market.as_str() {
+                    "delivery" => "ETHUSD_240628",
+                    _ => "ETHUSDT",
+                };
+                ex(leg.option_type, market.as_str(), symbol, i < 3, "369")
+            })
+            .collect();
+        let positions = expected_positions(&machine, &ex_list);
+        assert_eq!(
+            positions,
+            BTreeMap::from([(
+                ("delivery".to_string(), "ETHUSD_240628".to_string()),
+                d("-369")
+            )])
+        );
+
+        // 展期后空单在次季
+        ex_list.push(ex(
+            rollover::ROLL_BUY,
+            "delivery",
+            "ETHUSD_240628",
+            true,
+            "369",
+        ));
+        ex_list.push(ex(
+            rollover::ROLL_SELL,
+            "delivery",
+            "ETHUSD_240927",
+            true,
+            "370",
+        ));
+        let positions = expected_positions(&machine, &ex_list);
+        assert_eq!(
+            positions[&("delivery".to_string(), "ETHUSD_240628".to_string())],
+            Decimal::ZERO
+        );
+        assert_eq!(
+            positions[&("delivery".to_string(), "ETHUSD_240927".to_string())],
+            d("-370")
+        );
+    }
+
+    #[test]
+    fn test_position_covers() {
+        assert!(position_covers(-369.0, -369.0));
+        assert!(position_covers(-369.0, -400.0));
+        assert!(!position_covers(-369.0, -368.0));
+        assert!(!position_covers(-369.0, 369.0));
+        assert!(position_covers(0.5, 0.5));
+        assert!(!position_covers(0.5, 0.0));
+        assert!(position_covers(0.0, -1.0));
+    }
+
+    #[test]
+    fn test_transfer_matches() {
+        assert!(transfer_matches(0.01, Decimal::new(1, 2)));
+        assert!(transfer_matches(0.0123, Decimal::new(123, 4)));
+        assert!(transfer_matches(12.0, Decimal::new(1200, 2)));
+        // 其它数量的划转不认领
+        assert!(!transfer_matches(0.02, Decimal::new(1, 2)));
+        assert!(!transfer_matches(0.0123, Decimal::new(12, 3)));
+        assert!(!transfer_matches(0.0, Decimal::new(1, 2)));
+    }
+
+    #[test]
+    fn test_is_transient() {
+        use crate::binance::errors::BinanceContentError;
+        let binance = |code: i32| -> anyhow::Error {
+            Error::BinanceError {
+                response: BinanceContentError::new(code, String::new()),
+            }
+            .into()
+        };
+        // 网络或交易所暂时不可用, 不标记人工处理
+        assert!(is_transient(&Error::ServiceUnavailable.into()));
+        assert!(is_transient(&Error::InternalServerError.into()));
+        assert!(is_transient(
+            &Error::TooManyRequests { retry_after: None }.into()
+        ));
+        assert!(is_transient(&binance(-1007)));
+        assert!(is_transient(&binance(-1003)));
+        // 订单不存在、划转无法区分等为真实的不一致
+        assert!(!is_transient(&binance(-2013)));
+        assert!(!is_transient(&Error::Unauthorized.into()));
+        assert!(!is_transient(&anyhow!("2 unrecorded transfers")));
+    }
+}
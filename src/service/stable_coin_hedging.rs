@@ -1,42 +1,67 @@
 use crate::binance::api::OrderRequest;
-use crate::binance::rest_model::{KlineSummaries, KlineSummary, OrderSide, OrderType, TimeInForce};
-use crate::binance::MyApi;
-use crate::{db, model, sql};
+use crate::binance::rest_model::{KlineSummary, OrderSide, OrderType, TimeInForce, Transaction};
+use crate::binance::{ApiPool, MyApi};
+use crate::conf::PriceGuardConfig;
+use crate::service::{account, exchange_info, halt, kline, price};
+use crate::{model, sql};
 use anyhow::anyhow;
 use chrono::Local;
-use log::{error, info};
+use log::{error, info, warn};
 use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
-use rust_decimal::Decimal;
+use rust_decimal::{Decimal, RoundingStrategy};
 use std::collections::HashMap;
-use std::ops::{Add, Sub};
+use std::ops::{Add, Mul, Sub};
+use std::sync::Arc;
 use ta::indicators::BollingerBands;
 use ta::Next;
 use tokio::select;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
 
-pub async fn event_stable_coin_start(rxs: HashMap<i64, UnboundedReceiver<model::ArbStableCoin>>) {
+/// 启动 workers, 分发通道关闭且队列处理完后退出
+pub async fn event_stable_coin_start(
+    pool: Arc<ApiPool>,
+    rxs: HashMap<i64, UnboundedReceiver<model::ArbStableCoin>>,
+) -> Vec<JoinHandle<()>> {
+    let mut workers = Vec::new();
     for (_, mut rx) in rxs {
-        let api = MyApi::new();
-        tokio::spawn(async move {
+        let pool = pool.clone();
+        workers.push(tokio::spawn(async move {
+            let guard = &pool.config().price_guard;
             loop {
                 select! {
                     Some(stable_coin) = rx.recv() => {
+                        // 停止交易时不再下单, 队列中的策略直接丢弃
+                        if halt::is_halted() {
+                            continue;
+                        }
+                        let user_id = stable_coin.user_id;
+                        let api = match account::user_api(&pool, user_id) {
+                            Ok(api) => api,
+                            Err(e) => {
+                                warn!("stable_coin_id: {}, {}", stable_coin.id, e);
+                                continue;
+                            }
+                        };
                         match stable_coin.strategy.as_str() {
                             // boll 15m
                             "11" => {
-                                if let Err(e) = boll(api.clone(), stable_coin).await {
+                                if let Err(e) = boll(guard, api, stable_coin).await {
+                                    account::report_error(&pool, user_id, &e);
                                     error!("boll err: {:?}", e);
                                 }
                             },
                             // 百分比
                             "21" => {
-                                if let Err(e) = percentage(api.clone(), stable_coin).await {
+                                if let Err(e) = percentage(guard, api, stable_coin).await {
+                                    account::report_error(&pool, user_id, &e);
                                     error!("percentage err: {:?}", e);
                                 }
                             },
                             // 固定阈值
                             "31" => {
-                                if let Err(e) = fixed_threshold(api.clone(), stable_coin).await {
+                                if let Err(e) = fixed_threshold(guard, api, stable_coin).await {
+                                    account::report_error(&pool, user_id, &e);
                                     error!("fixed threshold err: {:?}", e);
                                 }
                             },
@@ -45,231 +70,403 @@ pub async fn event_stable_coin_start(rxs: HashMap<i64, UnboundedReceiver<model::
                             }
                         }
                     }
+                    else => break,
                 }
             }
-        });
+        }));
     }
+    workers
 }
 
-async fn boll(api: MyApi, stable: model::ArbStableCoin) -> anyhow::Result<()> {
-    // 初始化K线数据
-    let kline_key = stable.symbol.clone() + "_15m";
-    let kline_interval = "15m";
-
-    let mut klines: Vec<KlineSummary> = Vec::new();
-    if let Some(en) = db::get_db()?.rocksdb().get(kline_key.clone())? {
-        klines = bincode::deserialize(&en[..])?;
-    }
-
-    if klines.len() == 0 {
-        match api
-            .get_klines(stable.symbol.clone(), kline_interval, 1000, None, None)
-            .await
-        {
-            Ok(KlineSummaries::AllKlineSummaries(new_klines)) => {
-                let encode = bincode::serialize(&new_klines)?;
-                let _ = db::get_db()?.rocksdb().put(kline_key.clone(), encode)?;
-            }
-            Err(e) => {
-                return Err(anyhow!(
-                    "get {:?} klines error, : {:?}",
-                    kline_key.clone(),
-                    e.to_string()
-                ));
-            }
-        }
-    } else {
-        // 增量K线
-        if let Some(last) = klines.last() {
-            match api
-                .get_klines(
-                    stable.symbol.clone(),
-                    kline_interval,
-                    5,
-                    Some(last.open_time as u64),
-                    None,
-                )
-                .await
-            {
-                Ok(KlineSummaries::AllKlineSummaries(new_klines)) => {
-                    if new_klines.len() == 1 {
-                        let mut k1: Vec<KlineSummary> = klines.clone();
-                        k1.remove(klines.len() - 1);
-                        k1.push(new_klines[0].clone());
-                        let encode = bincode::serialize(&k1)?;
-                        db::get_db()?.rocksdb().put(kline_key.clone(), encode)?;
-                    } else {
-                        let mut k2: Vec<KlineSummary> = klines.clone();
-                        for (i, kline) in new_klines.iter().enumerate() {
-                            if i == 0 {
-                                k2.remove(klines.len() - 1);
-                                k2.push(kline.clone());
-                            } else {
-                                k2.remove(0);
-                                k2.push(kline.clone());
-                            }
-                        }
-                        let encode = bincode::serialize(&k2)?;
-                        db::get_db()?.rocksdb().put(kline_key.clone(), encode)?;
-                    }
-                }
-                Err(e) => {
-                    return Err(anyhow!(
-                        "get last {:?} klines error, : {:?}",
-                        kline_key.clone(),
-                        e.to_string()
-                    ));
-                }
-            }
-        }
-    }
+/// boll 上下轨及最新价, 均按 price_truncate 保留小数
+#[derive(Debug, Clone, PartialEq)]
+pub struct BollBands {
+    pub upper: Decimal,
+    pub lower: Decimal,
+    pub last_price: Decimal,
+}
 
-    // 计算Boll
-    let mut bb = BollingerBands::new(20, 2.0_f64)?;
-    // let mut average = 0.0;
+/// 用 klines 收盘价计算 boll, 最后一根k线的收盘价为最新价
+pub fn boll_bands(
+    klines: &[KlineSummary],
+    period: usize,
+    multiplier: f64,
+    price_truncate: i8,
+) -> anyhow::Result<BollBands> {
+    let mut bb = BollingerBands::new(period, multiplier)?;
     let mut upper = 0.0;
     let mut lower = 0.0;
-    for k in klines.clone() {
+    for k in klines {
         let out = bb.next(k.close);
-        // average = out.average;
         upper = out.upper;
         lower = out.lower;
     }
 
     let mut upp = Decimal::from_f64(upper).ok_or(anyhow!("decimal from f64 upp"))?;
-    upp.rescale(stable.price_truncate as u32);
-    /*let mut avg = Decimal::from_f64(average).ok_or(anyhow!(""))?;
-    avg.rescale(stable.price_truncate as u32);*/
+    upp.rescale(price_truncate as u32);
     let mut low = Decimal::from_f64(lower).ok_or(anyhow!("decimal from f64 low"))?;
-    low.rescale(stable.price_truncate as u32);
+    low.rescale(price_truncate as u32);
 
     let price = klines.last().ok_or(anyhow!("last price"))?.close;
     let mut last_price = Decimal::from_f64(price).ok_or(anyhow!("decimal from f64 price"))?;
-    last_price.rescale(stable.price_truncate as u32);
-
-    // 策略，price < 1 && price <= low buy -> price >= upp sell
-    // 获取stable_coin_info 表最后1条数据状态
-    let info_list = sql::get_arb_stable_coin_info_list_by_stable_coin_id(stable.id, 1).await?;
-    // 表为空或者上一条记录为sell
-    if info_list.is_empty() || info_list[0].option_type == "sell" {
-        if last_price.lt(&Decimal::from(1)) && last_price.le(&low) {
-            let mut price = last_price.add(stable.fok_diff);
-            price.rescale(stable.price_truncate as u32);
-            let mut amount = stable.option_amt;
-            amount.rescale(stable.amt_truncate as u32);
-            let tran = api
-                .place_order(OrderRequest {
-                    symbol: stable.symbol.clone(),
-                    quantity: Some(amount.to_f64().ok_or(anyhow!(""))?),
-                    price: Some(price.to_f64().ok_or(anyhow!(""))?),
-                    order_type: OrderType::Limit,
-                    side: OrderSide::Buy,
-                    time_in_force: Some(TimeInForce::FOK),
-                    ..OrderRequest::default()
-                })
-                .await?;
-            let last_id = sql::insert_arb_stable_coin_info(model::ArbStableCoinInfo {
-                id: 0,
-                stable_coin_id: stable.id,
-                user_id: stable.user_id,
-                platform: stable.platform,
-                coin: stable.coin,
-                market: stable.market,
-                symbol: stable.symbol,
-                option_type: "buy".to_string(),
-                price,
-                amount,
-                order_id: tran.order_id.to_string(),
-                is_ok: model::arb_stable_coin_info::IS_OK_COMPLETED,
-                created: Some(Local::now().timestamp()),
-                updated: None,
-                bak: None,
-            })
-            .await?;
+    last_price.rescale(price_truncate as u32);
 
-            info!(
-                "--------------- boll, up: {:?}, dn: {:?}, current price: {:?}",
-                upp, low, price
-            );
-            info!(
-                "buy, amount: {:?}, orderId: {:?}, info table lastInsertId: {:?}",
-                stable.option_amt, tran.order_id, last_id
-            );
+    Ok(BollBands {
+        upper: upp,
+        lower: low,
+        last_price,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StableSignal {
+    Buy { price: Decimal, amount: Decimal },
+    Sell { price: Decimal, amount: Decimal },
+    Hold,
+}
+
+/// stable_coin_info 表最后1条记录的 (option_type, price, amount)
+pub type LastInfo<'a> = Option<(&'a str, Decimal, Decimal)>;
+
+/// 以 price + fok_diff 买入 option_amt
+fn buy_signal(stable: &model::ArbStableCoin, last_price: Decimal) -> StableSignal {
+    let mut price = last_price.add(stable.fok_diff);
+    price.rescale(stable.price_truncate as u32);
+    let mut amount = stable.option_amt;
+    amount.rescale(stable.amt_truncate as u32);
+    StableSignal::Buy { price, amount }
+}
+
+/// 以 price - fok_diff 卖出上一次买入的数量
+fn sell_signal(
+    stable: &model::ArbStableCoin,
+    last_price: Decimal,
+    bought: Decimal,
+) -> StableSignal {
+    let mut price = last_price.sub(stable.fok_diff);
+    price.rescale(stable.price_truncate as u32);
+    let mut amount = bought;
+    amount.rescale(stable.amt_truncate as u32);
+    StableSignal::Sell { price, amount }
+}
+
+/// 策略，price < 1 && price <= low buy -> price >= upp sell
+pub fn boll_signal(
+    stable: &model::ArbStableCoin,
+    bands: &BollBands,
+    last_info: LastInfo,
+) -> StableSignal {
+    match last_info {
+        // 表为空或者上一条记录为sell
+        None | Some(("sell", _, _)) => {
+            if bands.last_price.lt(&Decimal::from(1)) && bands.last_price.le(&bands.lower) {
+                return buy_signal(stable, bands.last_price);
+            }
+            StableSignal::Hold
+        }
+        Some(("buy", _, bought)) => {
+            if bands.last_price.ge(&bands.upper) {
+                return sell_signal(stable, bands.last_price, bought);
+            }
+            StableSignal::Hold
         }
-    } else if info_list[0].option_type == "buy" {
-        if last_price.ge(&upp) {
-            let mut price = last_price.sub(stable.fok_diff);
-            price.rescale(stable.price_truncate as u32);
-            let mut amount = info_list[0].amount;
-            amount.rescale(stable.amt_truncate as u32);
-            let tran = api
-                .place_order(OrderRequest {
-                    symbol: stable.symbol.clone(),
-                    quantity: Some(amount.to_f64().ok_or(anyhow!(""))?),
-                    price: Some(price.to_f64().ok_or(anyhow!(""))?),
-                    order_type: OrderType::Limit,
-                    side: OrderSide::Sell,
-                    time_in_force: Some(TimeInForce::FOK),
-                    ..OrderRequest::default()
-                })
-                .await?;
-            let last_id = sql::insert_arb_stable_coin_info(model::ArbStableCoinInfo {
-                id: 0,
-                stable_coin_id: stable.id,
-                user_id: stable.user_id,
-                platform: stable.platform,
-                coin: stable.coin,
-                market: stable.market,
-                symbol: stable.symbol,
-                option_type: "sell".to_string(),
-                price,
-                amount,
-                order_id: tran.order_id.to_string(),
-                is_ok: model::arb_stable_coin_info::IS_OK_COMPLETED,
-                created: Some(Local::now().timestamp()),
-                updated: None,
-                bak: None,
-            })
-            .await?;
+        Some(_) => StableSignal::Hold,
+    }
+}
 
+/// 策略，price <= option_open buy -> price >= option_close sell
+pub fn fixed_threshold_signal(
+    stable: &model::ArbStableCoin,
+    last_price: Decimal,
+    last_info: LastInfo,
+) -> StableSignal {
+    match last_info {
+        None | Some(("sell", _, _)) => {
+            if last_price.le(&stable.option_open) {
+                return buy_signal(stable, last_price);
+            }
+            StableSignal::Hold
+        }
+        Some(("buy", _, bought)) => {
+            if last_price.ge(&stable.option_close) {
+                return sell_signal(stable, last_price, bought);
+            }
+            StableSignal::Hold
+        }
+        Some(_) => StableSignal::Hold,
+    }
+}
+
+/// 策略，price <= reference * (1 + option_open) buy -> price >= 买入价 * (1 + option_close) sell
+///
+/// option_open / option_close 为比率, 如 -0.0050 表示较参考价下跌 0.5% 买入,
+/// 0.0050 表示较买入成交价上涨 0.5% 卖出; reference 为上次卖出后的最高价
+pub fn percentage_signal(
+    stable: &model::ArbStableCoin,
+    reference: Decimal,
+    last_price: Decimal,
+    last_info: LastInfo,
+) -> StableSignal {
+    match last_info {
+        None | Some(("sell", _, _)) => {
+            let open_price = reference.mul(Decimal::ONE.add(stable.option_open));
+            if last_price.le(&open_price) {
+                return buy_signal(stable, last_price);
+            }
+            StableSignal::Hold
+        }
+        Some(("buy", bought_price, bought)) => {
+            let close_price = bought_price.mul(Decimal::ONE.add(stable.option_close));
+            if last_price.ge(&close_price) {
+                return sell_signal(stable, last_price, bought);
+            }
+            StableSignal::Hold
+        }
+        Some(_) => StableSignal::Hold,
+    }
+}
+
+/// 订单的成交均价和成交数量, 未成交 (如 FOK 过期) 时为 None
+fn order_fill(
+    tran: &Transaction,
+    price_truncate: i8,
+) -> anyhow::Result<Option<(Decimal, Decimal)>> {
+    if tran.executed_qty <= 0.0 {
+        return Ok(None);
+    }
+    let amount = Decimal::from_f64(tran.executed_qty).ok_or(anyhow!("decimal from f64 qty"))?;
+    let quote = Decimal::from_f64(tran.cummulative_quote_qty)
+        .ok_or(anyhow!("decimal from f64 quote qty"))?;
+    let price = quote
+        .checked_div(amount)
+        .ok_or(anyhow!("average fill price"))?
+        .round_dp(price_truncate as u32);
+    Ok(Some((price, amount)))
+}
+
+/// FOK 下单, 有成交时按成交均价和成交数量写入 stable_coin_info, Hold 时不做任何操作
+async fn execute_signal(
+    api: &MyApi,
+    stable: model::ArbStableCoin,
+    signal: StableSignal,
+) -> anyhow::Result<()> {
+    let (order_side, option_type, price, amount) = match signal {
+        StableSignal::Buy { price, amount } => (OrderSide::Buy, "buy", price, amount),
+        StableSignal::Sell { price, amount } => (OrderSide::Sell, "sell", price, amount),
+        StableSignal::Hold => return Ok(()),
+    };
+    // 按交易所 tickSize/stepSize 取整, 低于最小下单量或名义价值时不下单
+    let filter =
+        exchange_info::symbol_filter(api, stable.market.as_str(), stable.symbol.as_str()).await?;
+    let rounding = match order_side {
+        OrderSide::Buy => RoundingStrategy::AwayFromZero,
+        OrderSide::Sell => RoundingStrategy::ToZero,
+    };
+    let price = filter.round_price(price, rounding);
+    let amount = filter.round_qty(amount);
+    filter.check_order(price, amount)?;
+
+    let tran = api
+        .place_order(OrderRequest {
+            symbol: stable.symbol.clone(),
+            quantity: Some(amount.to_f64().ok_or(anyhow!(""))?),
+            price: Some(price.to_f64().ok_or(anyhow!(""))?),
+            order_type: OrderType::Limit,
+            side: order_side,
+            time_in_force: Some(TimeInForce::FOK),
+            ..OrderRequest::default()
+        })
+        .await?;
+    let strategy = stable.strategy.clone();
+    // 未成交时不写入, 下一轮仍按上一条记录决定买卖
+    let (price, amount) = match order_fill(&tran, stable.price_truncate)? {
+        Some(fill) => fill,
+        None => {
             info!(
-                "--------------- boll, up: {:?}, dn: {:?}, current price: {:?}",
-                upp, low, price
-            );
-            info!(
-                "sell, amount: {:?}, orderId: {:?}, info table lastInsertId: {:?}",
-                info_list[0].amount, tran.order_id, last_id
+                "strategy: {}, {}, price: {:?}, amount: {:?}, orderId: {:?}, status: {:?}, not filled",
+                strategy, option_type, price, amount, tran.order_id, tran.status
             );
+            return Ok(());
         }
+    };
+    let last_id = sql::insert_arb_stable_coin_info(model::ArbStableCoinInfo {
+        id: 0,
+        stable_coin_id: stable.id,
+        user_id: stable.user_id,
+        platform: stable.platform,
+        coin: stable.coin,
+        market: stable.market,
+        symbol: stable.symbol,
+        option_type: option_type.to_string(),
+        price,
+        amount,
+        order_id: tran.order_id.to_string(),
+        is_ok: model::arb_stable_coin_info::IS_OK_COMPLETED,
+        created: Some(Local::now().timestamp()),
+        updated: None,
+        bak: None,
+    })
+    .await?;
+
+    info!(
+        "strategy: {}, {}, price: {:?}, amount: {:?}, orderId: {:?}, info table lastInsertId: {:?}",
+        strategy, option_type, price, amount, tran.order_id, last_id
+    );
+    Ok(())
+}
+
+/// 获取stable_coin_info 表最后1条数据
+async fn last_stable_coin_info(
+    stable_coin_id: i64,
+) -> anyhow::Result<Option<model::ArbStableCoinInfo>> {
+    let mut info_list =
+        sql::get_arb_stable_coin_info_list_by_stable_coin_id(stable_coin_id, 1).await?;
+    Ok(info_list.pop())
+}
+
+/// 最近 limit 根1m k线, 和 boll 一样持久化在 RocksDB 中
+async fn minute_klines(
+    api: &MyApi,
+    stable: &model::ArbStableCoin,
+    limit: usize,
+) -> anyhow::Result<Vec<KlineSummary>> {
+    kline::recent_klines(api, "spot", &stable.symbol, "1m", limit).await
+}
+
+/// 价格过期保护, 最后一根k线过期时不开仓也不平仓
+fn klines_fresh(
+    guard: &PriceGuardConfig,
+    stable: &model::ArbStableCoin,
+    klines: &[KlineSummary],
+) -> bool {
+    let result = match klines.last() {
+        Some(last) => {
+            price::check_kline_age(guard, &stable.market, last, Local::now().timestamp_millis())
+        }
+        None => Err(anyhow!("{} klines is empty", stable.symbol)),
+    };
+    if let Err(e) = result {
+        warn!("stable_coin_id: {}, {}", stable.id, e);
+        return false;
     }
+    true
+}
 
-    /*println!("{:?}", info_list);
-    println!(
-        "up: {:?} dn: {:?} count: {:?} price: {:?}",
-        upp,
-        low,
-        klines.len(),
-        last_price
-    );*/
+fn to_price(value: f64, price_truncate: i8) -> anyhow::Result<Decimal> {
+    let mut price = Decimal::from_f64(value).ok_or(anyhow!("decimal from f64 price"))?;
+    price.rescale(price_truncate as u32);
+    Ok(price)
+}
 
-    Ok(())
+async fn boll(
+    guard: &PriceGuardConfig,
+    api: MyApi,
+    stable: model::ArbStableCoin,
+) -> anyhow::Result<()> {
+    // 15m K线, 持久化在 RocksDB 中, 只补拉缺失和未收盘的部分
+    let klines = kline::recent_klines(&api, "spot", &stable.symbol, "15m", 1000).await?;
+
+    if !klines_fresh(guard, &stable, &klines) {
+        return Ok(());
+    }
+
+    // 计算Boll
+    let bands = boll_bands(&klines, 20, 2.0_f64, stable.price_truncate)?;
+
+    let info = last_stable_coin_info(stable.id).await?;
+    let last_info = info
+        .as_ref()
+        .map(|info| (info.option_type.as_str(), info.price, info.amount));
+    let signal = boll_signal(&stable, &bands, last_info);
+    if signal != StableSignal::Hold {
+        info!(
+            "--------------- boll, up: {:?}, dn: {:?}, current price: {:?}",
+            bands.upper, bands.lower, bands.last_price
+        );
+    }
+    execute_signal(&api, stable, signal).await
 }
 
-#[allow(dead_code, unused)]
-async fn percentage(api: MyApi, stable: model::ArbStableCoin) -> anyhow::Result<()> {
-    // println!("percentage: {:?}", stable);
-    Ok(())
+async fn percentage(
+    guard: &PriceGuardConfig,
+    api: MyApi,
+    stable: model::ArbStableCoin,
+) -> anyhow::Result<()> {
+    let info = last_stable_coin_info(stable.id).await?;
+    let last_info = info
+        .as_ref()
+        .map(|info| (info.option_type.as_str(), info.price, info.amount));
+
+    // 参考价为最近1000根1m k线中, 上次卖出之后的最高收盘价
+    let sold_at = match &info {
+        Some(info) if info.option_type == "sell" => info.created.unwrap_or_default() * 1000,
+        _ => 0,
+    };
+    let klines = minute_klines(&api, &stable, 1000).await?;
+    if !klines_fresh(guard, &stable, &klines) {
+        return Ok(());
+    }
+    let last = klines
+        .last()
+        .ok_or(anyhow!("{} 1m klines is empty", stable.symbol))?;
+    let high = klines
+        .iter()
+        .filter(|k| k.close_time >= sold_at)
+        .map(|k| k.close)
+        .fold(last.close, f64::max);
+    let reference = to_price(high, stable.price_truncate)?;
+    let last_price = to_price(last.close, stable.price_truncate)?;
+
+    let signal = percentage_signal(&stable, reference, last_price, last_info);
+    if signal != StableSignal::Hold {
+        info!(
+            "--------------- percentage, reference: {:?}, current price: {:?}",
+            reference, last_price
+        );
+    }
+    execute_signal(&api, stable, signal).await
 }
 
-#[allow(dead_code, unused)]
-async fn fixed_threshold(api: MyApi, stable: model::ArbStableCoin) -> anyhow::Result<()> {
-    // println!("fixed_threshold: {:?}", stable);
-    Ok(())
+async fn fixed_threshold(
+    guard: &PriceGuardConfig,
+    api: MyApi,
+    stable: model::ArbStableCoin,
+) -> anyhow::Result<()> {
+    let info = last_stable_coin_info(stable.id).await?;
+    let last_info = info
+        .as_ref()
+        .map(|info| (info.option_type.as_str(), info.price, info.amount));
+
+    let klines = minute_klines(&api, &stable, 1).await?;
+    if !klines_fresh(guard, &stable, &klines) {
+        return Ok(());
+    }
+    let last = klines
+        .last()
+        .ok_or(anyhow!("{} 1m klines is empty", stable.symbol))?;
+    let last_price = to_price(last.close, stable.price_truncate)?;
+
+    let signal = fixed_threshold_signal(&stable, last_price, last_info);
+    if signal != StableSignal::Hold {
+        info!(
+            "--------------- fixed threshold, open: {:?}, close: {:?}, current price: {:?}",
+            stable.option_open, stable.option_close, last_price
+        );
+    }
+    execute_signal(&api, stable, signal).await
 }
 
+/// 轮询运行中的稳定币策略分发给 workers, 停止交易时不分发, 退出时关闭通道
 pub async fn inspect_stable_coin(txs: HashMap<i64, UnboundedSender<model::ArbStableCoin>>) {
     loop {
         tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+        if halt::is_shutting_down() {
+            info!("stop dispatching stable coins");
+            break;
+        }
+        if halt::is_halted() {
+            continue;
+        }
 
         match sql::get_arb_stable_coin_list_by_doing_status(
             model::arb_stable_coin::DOING_STATUS_RUN,
@@ -292,3 +489,136 @@ pub async fn inspect_stable_coin(txs: HashMap<i64, UnboundedSender<model::ArbSta
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::binance::rest_model::OrderStatus;
+    use std::str::FromStr;
+
+    fn d(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    fn stable(strategy: &str, option_open: &str, option_close: &str) -> model::ArbStableCoin {
+        model::ArbStableCoin {
+            symbol: "FDUSDUSDT".to_string(),
+            price_truncate: 4,
+            amt_truncate: 0,
+            strategy: strategy.to_string(),
+            option_open: d(option_open),
+            option_close: d(option_close),
+            option_amt: d("100"),
+            fok_diff: d("0.0001"),
+            ..model::ArbStableCoin::default()
+        }
+    }
+
+    #[test]
+    fn fixed_threshold_buys_at_open_and_sells_at_close() {
+        let s = stable("31", "0.9980", "1.0010");
+
+        assert_eq!(
+            fixed_threshold_signal(&s, d("0.9981"), None),
+            StableSignal::Hold
+        );
+        assert_eq!(
+            fixed_threshold_signal(&s, d("0.9980"), None),
+            StableSignal::Buy {
+                price: d("0.9981"),
+                amount: d("100")
+            }
+        );
+        let bought = Some(("buy", d("0.9981"), d("100")));
+        assert_eq!(
+            fixed_threshold_signal(&s, d("0.9975"), bought),
+            StableSignal::Hold
+        );
+        assert_eq!(
+            fixed_threshold_signal(&s, d("1.0012"), bought),
+            StableSignal::Sell {
+                price: d("1.0011"),
+                amount: d("100")
+            }
+        );
+        let sold = Some(("sell", d("1.0011"), d("100")));
+        assert!(matches!(
+            fixed_threshold_signal(&s, d("0.9970"), sold),
+            StableSignal::Buy { .. }
+        ));
+    }
+
+    #[test]
+    fn percentage_buys_after_drop_and_sells_after_rise_from_fill() {
+        let s = stable("21", "-0.0050", "0.0050");
+
+        // 1.0000 下跌 0.5% 为 0.9950
+        assert_eq!(
+            percentage_signal(&s, d("1.0000"), d("0.9951"), None),
+            StableSignal::Hold
+        );
+        assert_eq!(
+            percentage_signal(&s, d("1.0000"), d("0.9950"), None),
+            StableSignal::Buy {
+                price: d("0.9951"),
+                amount: d("100")
+            }
+        );
+
+        // 以买入成交价 0.9951 为基准上涨 0.5% 约为 1.0001
+        let bought = Some(("buy", d("0.9951"), d("100")));
+        assert_eq!(
+            percentage_signal(&s, d("1.0000"), d("1.0000"), bought),
+            StableSignal::Hold
+        );
+        assert_eq!(
+            percentage_signal(&s, d("1.0000"), d("1.0002"), bought),
+            StableSignal::Sell {
+                price: d("1.0001"),
+                amount: d("100")
+            }
+        );
+    }
+
+    fn fok_buy(status: OrderStatus, executed_qty: f64, quote_qty: f64) -> Transaction {
+        Transaction {
+            symbol: "FDUSDUSDT".to_string(),
+            order_id: 1,
+            client_order_id: "fok".to_string(),
+            transact_time: 0,
+            price: 0.9981,
+            orig_qty: 100.0,
+            executed_qty,
+            cummulative_quote_qty: quote_qty,
+            status,
+            time_in_force: TimeInForce::FOK,
+            order_type: OrderType::Limit,
+            side: OrderSide::Buy,
+            fills: vec![],
+        }
+    }
+
+    #[test]
+    fn expired_fok_is_not_a_fill() {
+        let tran = fok_buy(OrderStatus::Expired, 0.0, 0.0);
+        assert_eq!(order_fill(&tran, 4).unwrap(), None);
+
+        // 未写入成交记录, 下一轮仍是买入信号而不是卖出未买到的币
+        let s = stable("31", "0.9980", "1.0010");
+        assert!(matches!(
+            fixed_threshold_signal(&s, d("0.9975"), None),
+            StableSignal::Buy { .. }
+        ));
+        assert_eq!(
+            fixed_threshold_signal(&s, d("1.0012"), None),
+            StableSignal::Hold
+        );
+    }
+
+    #[test]
+    fn fill_uses_executed_qty_and_average_price() {
+        // 限价 0.9981, 实际以 0.9979 成交
+        let tran = fok_buy(OrderStatus::Filled, 100.0, 99.79);
+        assert_eq!(order_fill(&tran, 4).unwrap(), Some((d("0.9979"), d("100"))));
+    }
+}
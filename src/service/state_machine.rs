@@ -0,0 +1,534 @@
+use crate::binance::rest_model::{OrderSide, UniversalTransferType};
+use crate::model;
+use crate::service::common::ExDesc;
+use anyhow::anyhow;
+use rust_decimal::Decimal;
+use std::ops::{Add, Sub};
+
+// 策略状态机定义
+// 每个套利形态由一组按顺序执行的腿(Leg)组成，状态 i 表示前 i 条腿已完成，
+// arb_strategy_ex 表按腿的顺序生成，执行器也按同一份定义推进状态。
+
+/// 腿所在的一侧，决定使用 from/to 的市场、交易对、价格及精度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LegSide {
+    From,
+    To,
+}
+
+/// 腿的执行方式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LegAction {
+    /// 现货下单
+    Spot(OrderSide),
+    /// U本位合约下单
+    Futures(OrderSide),
+    /// 币本位合约下单
+    Delivery(OrderSide),
+    /// 账户间划转
+    Transfer(UniversalTransferType),
+}
+
+/// 手续费类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fee {
+    Spot,
+    Futures,
+    Delivery,
+}
+
+/// 腿的下单数量来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LegAmount {
+    /// 当前腿预生成的 option_amount
+    Planned,
+    /// 当前腿 option_amount 按合约面值换算的张数
+    PlannedContracts,
+    /// 第 n 条腿的成交数量
+    Executed(usize),
+    /// 第 n 条腿的成交数量扣除手续费
+    ExecutedLessFee(usize, Fee),
+    /// 第 n 条腿的成交数量按合约面值换算的张数
+    ExecutedContracts(usize),
+    /// 第 n 条腿币本位订单成交的币数量(cum_base)扣除手续费
+    DeliveryCumBaseLessFee(usize, Fee),
+}
+
+/// 阀值
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Threshold {
+    OptionOpen,
+    OptionClose,
+}
+
+/// diff_rate 进入条件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Guard {
+    /// diff_rate >= 阀值
+    AtLeast(Threshold),
+    /// diff_rate <= 阀值
+    AtMost(Threshold),
+}
+
+impl Guard {
+    pub fn pass(&self, diff_rate: Decimal, strategy: &model::ArbStrategy) -> bool {
+        let threshold = |t: &Threshold| match t {
+            Threshold::OptionOpen => strategy.option_open,
+            Threshold::OptionClose => strategy.option_close,
+        };
+        match self {
+            Guard::AtLeast(t) => diff_rate >= threshold(t),
+            Guard::AtMost(t) => diff_rate <= threshold(t),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Leg {
+    pub option_type: &'static str,
+    pub side: LegSide,
+    pub action: LegAction,
+    pub amount: LegAmount,
+    pub guard: Guard,
+}
+
+impl Leg {
+    pub fn market(&self, strategy: &model::ArbStrategy) -> String {
+        match (&self.action, self.side) {
+            (LegAction::Transfer(_), _) => "transfer".to_string(),
+            (_, LegSide::From) => strategy.from_market.clone(),
+            (_, LegSide::To) => strategy.to_market.clone(),
+        }
+    }
+
+    pub fn symbol(&self, strategy: &model::ArbStrategy) -> String {
+        match (&self.action, self.side) {
+            (LegAction::Transfer(_), _) => strategy.coin.clone(),
+            (_, LegSide::From) => strategy.from_symbol.clone(),
+            (_, LegSide::To) => strategy.to_symbol.clone(),
+        }
+    }
+
+    pub fn order_side(&self) -> Option<OrderSide> {
+        match &self.action {
+            LegAction::Spot(side) | LegAction::Futures(side) | LegAction::Delivery(side) => {
+                Some(side.clone())
+            }
+            LegAction::Transfer(_) => None,
+        }
+    }
+
+    /// 最新价格 +- fok_diff，买入加、卖出减
+    pub fn price(
+        &self,
+        strategy: &model::ArbStrategy,
+        from_price: Decimal,
+        to_price: Decimal,
+    ) -> Decimal {
+        let (last_price, truncate) = match self.side {
+            LegSide::From => (from_price, strategy.from_price_truncate),
+            LegSide::To => (to_price, strategy.to_price_truncate),
+        };
+        let mut price = match self.order_side() {
+            Some(OrderSide::Buy) => last_price.add(strategy.fok_diff),
+            Some(OrderSide::Sell) => last_price.sub(strategy.fok_diff),
+            None => Decimal::ZERO,
+        };
+        price.rescale(truncate as u32);
+        price
+    }
+
+    pub fn amt_truncate(&self, strategy: &model::ArbStrategy) -> u32 {
+        match self.side {
+            LegSide::From => strategy.from_amt_truncate as u32,
+            LegSide::To => strategy.to_amt_truncate as u32,
+        }
+    }
+}
+
+/// 腿的进入条件比较的指标
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// arb_diff_rate_info 的差价比率
+    DiffRate,
+    /// 永续合约预测资金费率
+    FundingRate,
+}
+
+/// 当前状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StrategyState {
+    /// 等待执行第 n 条腿
+    Leg(usize),
+    /// 所有腿已完成
+    Done,
+}
+
+#[derive(Debug, Clone)]
+pub struct StrategyMachine {
+    pub signal: Signal,
+    pub legs: Vec<Leg>,
+}
+
+impl StrategyMachine {
+    /// 根据策略方向及市场选择状态机，不支持的组合返回 None
+    pub fn of(strategy: &model::ArbStrategy) -> Option<Self> {
+        match (
+            strategy.option_choose.as_str(),
+            strategy.from_market.as_str(),
+            strategy.to_market.as_str(),
+        ) {
+            ("positive", _, _) => Some(Self::positive()),
+            ("reverse", "futures", "futures") => Some(Self::reverse_usdm()),
+            ("reverse", "delivery", "delivery") => Some(Self::reverse_coinm()),
+            ("funding", "spot", "futures") => Some(Self::funding()),
+            _ => None,
+        }
+    }
+
+    /// 正向: 差价比率 >= option_open 现货spot买入 -> transfer到币本期货 -> delivery卖出，
+    /// 差价比率 <= option_close delivery买入 -> transfer到现货 -> 现货spot卖出
+    pub fn positive() -> Self {
+        let open = Guard::AtLeast(Threshold::OptionOpen);
+        let close = Guard::AtMost(Threshold::OptionClose);
+        StrategyMachine {
+            signal: Signal::DiffRate,
+            legs: vec![
+                Leg {
+                    option_type: "spot_buy",
+                    side: LegSide::From,
+                    action: LegAction::Spot(OrderSide::Buy),
+                    amount: LegAmount::Planned,
+                    guard: open,
+                },
+                Leg {
+                    option_type: "transfer_spot_to_delivery",
+                    side: LegSide::From,
+                    action: LegAction::Transfer(UniversalTransferType::MainCmfuture),
+                    amount: LegAmount::ExecutedLessFee(0, Fee::Spot),
+                    guard: open,
+                },
+                Leg {
+                    option_type: "delivery_sell",
+                    side: LegSide::To,
+                    action: LegAction::Delivery(OrderSide::Sell),
+                    amount: LegAmount::ExecutedContracts(1),
+                    guard: open,
+                },
+                Leg {
+                    option_type: "delivery_buy",
+                    side: LegSide::To,
+                    action: LegAction::Delivery(OrderSide::Buy),
+                    amount: LegAmount::Executed(2),
+                    guard: close,
+                },
+                Leg {
+                    option_type: "transfer_delivery_to_spot",
+                    side: LegSide::To,
+                    action: LegAction::Transfer(UniversalTransferType::CmfutureMain),
+                    amount: LegAmount::DeliveryCumBaseLessFee(3, Fee::Delivery),
+                    guard: close,
+                },
+                Leg {
+                    option_type: "spot_sell",
+                    side: LegSide::From,
+                    action: LegAction::Spot(OrderSide::Sell),
+                    amount: LegAmount::Executed(4),
+                    guard: close,
+                },
+            ],
+        }
+    }
+
+    /// 反向U本位: 差价比率 <= option_open 远期futures买入 -> futures永续卖出，
+    /// 差价比率 >= option_close futures永续买入 -> 远期futures卖出
+    pub fn reverse_usdm() -> Self {
+        let open = Guard::AtMost(Threshold::OptionOpen);
+        let close = Guard::AtLeast(Threshold::OptionClose);
+        StrategyMachine {
+            signal: Signal::DiffRate,
+            legs: vec![
+                Leg {
+                    option_type: "futures_buy",
+                    side: LegSide::From,
+                    action: LegAction::Futures(OrderSide::Buy),
+                    amount: LegAmount::Planned,
+                    guard: open,
+                },
+                Leg {
+                    option_type: "futures_sell",
+                    side: LegSide::To,
+                    action: LegAction::Futures(OrderSide::Sell),
+                    amount: LegAmount::Executed(0),
+                    guard: open,
+                },
+                Leg {
+                    option_type: "futures_buy",
+                    side: LegSide::To,
+                    action: LegAction::Futures(OrderSide::Buy),
+                    amount: LegAmount::Executed(1),
+                    guard: close,
+                },
+                Leg {
+                    option_type: "futures_sell",
+                    side: LegSide::From,
+                    action: LegAction::Futures(OrderSide::Sell),
+                    amount: LegAmount::Executed(0),
+                    guard: close,
+                },
+            ],
+        }
+    }
+
+    /// 反向币本位: 差价比率 <= option_open 远期delivery买入 -> delivery永续卖出，
+    /// 差价比率 >= option_close delivery永续买入 -> 远期delivery卖出
+    pub fn reverse_coinm() -> Self {
+        let open = Guard::AtMost(Threshold::OptionOpen);
+        let close = Guard::AtLeast(Threshold::OptionClose);
+        StrategyMachine {
+            signal: Signal::DiffRate,
+            legs: vec![
+                Leg {
+                    option_type: "delivery_buy",
+                    side: LegSide::From,
+                    action: LegAction::Delivery(OrderSide::Buy),
+                    amount: LegAmount::PlannedContracts,
+                    guard: open,
+                },
+                Leg {
+                    option_type: "delivery_sell",
+                    side: LegSide::To,
+                    action: LegAction::Delivery(OrderSide::Sell),
+                    amount: LegAmount::PlannedContracts,
+                    guard: open,
+                },
+                Leg {
+                    option_type: "delivery_buy",
+                    side: LegSide::To,
+                    action: LegAction::Delivery(OrderSide::Buy),
+                    amount: LegAmount::Executed(1),
+                    guard: close,
+                },
+                Leg {
+                    option_type: "delivery_sell",
+                    side: LegSide::From,
+                    action: LegAction::Delivery(OrderSide::Sell),
+                    amount: LegAmount::Executed(0),
+                    guard: close,
+                },
+            ],
+        }
+    }
+
+    /// 资金费率: 预测资金费率 >= option_open 现货spot买入 -> U本位永续futures卖出，
+    /// 预测资金费率 <= option_close futures永续买入 -> 现货spot卖出
+    pub fn funding() -> Self {
+        let open = Guard::AtLeast(Threshold::OptionOpen);
+        let close = Guard::AtMost(Threshold::OptionClose);
+        StrategyMachine {
+            signal: Signal::FundingRate,
+            legs: vec![
+                Leg {
+                    option_type: "spot_buy",
+                    side: LegSide::From,
+                    action: LegAction::Spot(OrderSide::Buy),
+                    amount: LegAmount::Planned,
+                    guard: open,
+                },
+                Leg {
+                    option_type: "futures_sell",
+                    side: LegSide::To,
+                    action: LegAction::Futures(OrderSide::Sell),
+                    amount: LegAmount::ExecutedLessFee(0, Fee::Spot),
+                    guard: open,
+                },
+                Leg {
+                    option_type: "futures_buy",
+                    side: LegSide::To,
+                    action: LegAction::Futures(OrderSide::Buy),
+                    amount: LegAmount::Executed(1),
+                    guard: close,
+                },
+                Leg {
+                    option_type: "spot_sell",
+                    side: LegSide::From,
+                    action: LegAction::Spot(OrderSide::Sell),
+                    amount: LegAmount::ExecutedLessFee(0, Fee::Spot),
+                    guard: close,
+                },
+            ],
+        }
+    }
+
+    /// 开仓腿已全部完成且平仓腿尚未开始
+    pub fn holding(&self, step: usize) -> bool {
+        let open = self.legs[0].guard;
+        step > 0
+            && step < self.legs.len()
+            && self.legs[step - 1].guard == open
+            && self.legs[step].guard != open
+    }
+
+    /// 生成 arb_strategy_ex 描述，顺序即执行顺序
+    pub fn ex_desc(&self, strategy: &model::ArbStrategy) -> Vec<ExDesc> {
+        self.legs
+            .iter()
+            .map(|leg| ExDesc {
+                market: leg.market(strategy),
+                symbol: leg.symbol(strategy),
+                option_type: leg.option_type.to_string(),
+            })
+            .collect()
+    }
+
+    /// 根据已完成的执行记录计算当前状态，ex_list 需按执行顺序排列
+    pub fn state(&self, ex_list: &[model::ArbStrategyEx]) -> anyhow::Result<StrategyState> {
+        if ex_list.len() != self.legs.len() {
+            return Err(anyhow!("arb count err: {:?}", ex_list.len()));
+        }
+
+        let mut current = StrategyState::Done;
+        for (i, (leg, ex)) in self.legs.iter().zip(ex_list).enumerate() {
+            if ex.option_type != leg.option_type {
+                return Err(anyhow!(
+                    "arb_strategy_ex {} option_type err, expect: {}, got: {}",
+                    ex.id,
+                    leg.option_type,
+                    ex.option_type
+                ));
+            }
+            let done = ex.option_status == model::arb_strategy_ex::OPTION_STATUS_DONE;
+            match current {
+                StrategyState::Done if !done => current = StrategyState::Leg(i),
+                // 判断执行顺序
+                StrategyState::Leg(n) if done => {
+                    return Err(anyhow!("done count err, leg {} done before leg {}", i, n));
+                }
+                _ => {}
+            }
+        }
+        Ok(current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strategy(option_choose: &str, from_market: &str, to_market: &str) -> model::ArbStrategy {
+        model::ArbStrategy {
+            option_choose: option_choose.to_string(),
+            coin: "BTC".to_string(),
+            from_market: from_market.to_string(),
+            from_symbol: "BTCUSD_240628".to_string(),
+            to_market: to_market.to_string(),
+            to_symbol: "BTCUSD_PERP".to_string(),
+            option_open: Decimal::new(5, 2),
+            option_close: Decimal::ZERO,
+            ..model::ArbStrategy::default()
+        }
+    }
+
+    fn ex_list(machine: &StrategyMachine, done: usize) -> Vec<model::ArbStrategyEx> {
+        machine
+            .legs
+            .iter()
+            .enumerate()
+            .map(|(i, leg)| model::ArbStrategyEx {
+                id: i as i64,
+                option_type: leg.option_type.to_string(),
+                option_status: if i < done {
+                    model::arb_strategy_ex::OPTION_STATUS_DONE
+                } else {
+                    model::arb_strategy_ex::OPTION_STATUS_UN_DONE
+                },
+                ..model::ArbStrategyEx::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_machine_of() {
+        assert_eq!(
+            StrategyMachine::of(&strategy("positive", "spot", "delivery"))
+                .unwrap()
+                .legs
+                .len(),
+            6
+        );
+        assert!(StrategyMachine::of(&strategy("reverse", "futures", "futures")).is_some());
+        assert!(StrategyMachine::of(&strategy("reverse", "delivery", "delivery")).is_some());
+        assert!(StrategyMachine::of(&strategy("reverse", "spot", "delivery")).is_none());
+        let funding = StrategyMachine::of(&strategy("funding", "spot", "futures")).unwrap();
+        assert_eq!(funding.signal, Signal::FundingRate);
+        assert_eq!(funding.legs[1].action, LegAction::Futures(OrderSide::Sell));
+        assert!(StrategyMachine::of(&strategy("funding", "spot", "delivery")).is_none());
+    }
+
+    #[test]
+    fn test_ex_desc() {
+        let s = strategy("reverse", "delivery", "delivery");
+        let desc = StrategyMachine::reverse_coinm().ex_desc(&s);
+        let keys: Vec<String> = desc
+            .iter()
+            .map(|d| format!("{}-{}", d.option_type, d.symbol))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                "delivery_buy-BTCUSD_240628",
+                "delivery_sell-BTCUSD_PERP",
+                "delivery_buy-BTCUSD_PERP",
+                "delivery_sell-BTCUSD_240628",
+            ]
+        );
+
+        let positive =
+            StrategyMachine::positive().ex_desc(&strategy("positive", "spot", "delivery"));
+        assert_eq!(positive[1].market, "transfer");
+        assert_eq!(positive[1].symbol, "BTC");
+    }
+
+    #[test]
+    fn test_state() {
+        let machine = StrategyMachine::positive();
+        assert_eq!(
+            machine.state(&ex_list(&machine, 0)).unwrap(),
+            StrategyState::Leg(0)
+        );
+        assert_eq!(
+            machine.state(&ex_list(&machine, 4)).unwrap(),
+            StrategyState::Leg(4)
+        );
+        assert_eq!(
+            machine.state(&ex_list(&machine, 6)).unwrap(),
+            StrategyState::Done
+        );
+
+        let mut out_of_order = ex_list(&machine, 1);
+        out_of_order[3].option_status = model::arb_strategy_ex::OPTION_STATUS_DONE;
+        assert!(machine.state(&out_of_order).is_err());
+        assert!(machine.state(&out_of_order[..5]).is_err());
+    }
+
+    #[test]
+    fn test_holding() {
+        let machine = StrategyMachine::positive();
+        assert!(!machine.holding(0));
+        assert!(!machine.holding(2));
+        assert!(machine.holding(3));
+        assert!(!machine.holding(4));
+        assert!(StrategyMachine::reverse_usdm().holding(2));
+    }
+
+    #[test]
+    fn test_guard() {
+        let s = strategy("positive", "spot", "delivery");
+        let open = Guard::AtLeast(Threshold::OptionOpen);
+        assert!(open.pass(Decimal::new(6, 2), &s));
+        assert!(!open.pass(Decimal::new(4, 2), &s));
+        let close = Guard::AtMost(Threshold::OptionClose);
+        assert!(close.pass(Decimal::new(-1, 2), &s));
+        assert!(!close.pass(Decimal::new(1, 2), &s));
+    }
+}
@@ -0,0 +1,480 @@
+use crate::binance::config::Config;
+use crate::binance::errors::Error;
+use crate::binance::websockets::WebSockets;
+use crate::binance::ws_model::WebsocketEvent;
+use crate::binance::{ApiPool, MyApi};
+use crate::conf::redis_key;
+use crate::service::binance_strategy::{order_closed, order_filled};
+use crate::{db, model, sql};
+use anyhow::anyhow;
+use log::{error, info, warn};
+use redis::AsyncCommands;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::time::Duration;
+
+// binance listenKey 60分钟过期, 建议30分钟续期一次
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(30 * 60);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// 账户数据流, market 为 spot、futures、delivery
+///
+/// 订单成交/过期事件到达时立即回写 arb_strategy_ex 和 arb_strategy_ex_info,
+/// 余额和持仓写入redis; 模拟盘订单不经过交易所, 不启动
+///
+/// 配置了 accounts 时每个账户一条数据流, 否则只订阅 binance_api_config 的账户
+pub async fn user_data_stream(pool: Arc<ApiPool>, market: &'static str) {
+    let config = pool.config();
+    if config.paper.enabled {
+        info!("paper trading, {} user data stream disabled", market);
+        return;
+    }
+
+    let accounts: Vec<(Option<i64>, MyApi)> = if config.accounts.is_empty() {
+        vec![(None, MyApi::from_config(config))]
+    } else {
+        let mut accounts = vec![];
+        for account in &config.accounts {
+            match pool.get(account.user_id) {
+                Ok(api) => accounts.push((Some(account.user_id), api)),
+                Err(e) => error!(
+                    "user_id {} {} user data stream: {}",
+                    account.user_id, market, e
+                ),
+            }
+        }
+        accounts
+    };
+    let streams: Vec<_> = accounts
+        .into_iter()
+        .map(|(user_id, api)| tokio::spawn(account_data_stream(market, user_id, api)))
+        .collect();
+    futures::future::join_all(streams).await;
+}
+
+async fn account_data_stream(market: &'static str, user_id: Option<i64>, api: MyApi) {
+    let (event_tx, event_rx) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(handle_user_events(market, user_id, event_rx));
+
+    loop {
+        if let Err(e) = run_user_data_stream(&api, market, Config::default(), &event_tx).await {
+            error!(
+                "user_id {:?} {} user data stream err: {:?}",
+                user_id, market, e
+            );
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// 创建 listenKey 并订阅, 直到连接断开或 listenKey 失效
+// WebSockets 回调的返回类型固定为 binance::errors::Result
+#[allow(clippy::result_large_err)]
+pub async fn run_user_data_stream(
+    api: &MyApi,
+    market: &'static str,
+    config: Config,
+    event_tx: &UnboundedSender<WebsocketEvent>,
+) -> anyhow::Result<()> {
+    let listen_key = start_listen_key(api, market).await?;
+    info!("{} user data stream started", market);
+
+    let keep_running = Arc::new(AtomicBool::new(true));
+    let keep_alive = {
+        let api = api.clone();
+        let listen_key = listen_key.clone();
+        let keep_running = keep_running.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(KEEP_ALIVE_INTERVAL).await;
+                if let Err(e) = keep_alive_listen_key(&api, market, &listen_key).await {
+                    error!("{} listen key keepalive err: {:?}", market, e);
+                    keep_running.store(false, Ordering::Relaxed);
+                    break;
+                }
+            }
+        })
+    };
+
+    let mut web_socket: WebSockets<'_, WebsocketEvent> = WebSockets::new_with_options(
+        |event: WebsocketEvent| {
+            if let WebsocketEvent::ListenKeyExpired(expired) = event {
+                return Err(Error::InvalidListenKey(
+                    expired.listen_key.unwrap_or_default(),
+                ));
+            }
+            event_tx.send(event).map_err(|e| Error::Msg(e.to_string()))
+        },
+        config,
+    );
+    let connected = match market {
+        "spot" => web_socket.connect(&listen_key).await,
+        "futures" => web_socket.connect_futures(&listen_key).await,
+        _ => web_socket.connect_delivery(&listen_key).await,
+    };
+    let result = match connected {
+        Ok(_) => web_socket.event_loop(&keep_running).await,
+        Err(e) => Err(e),
+    };
+    keep_alive.abort();
+
+    let _ = web_socket.disconnect().await;
+    if let Err(e) = close_listen_key(api, market, &listen_key).await {
+        warn!("{} close listen key err: {:?}", market, e);
+    }
+    result.map_err(|e| anyhow!("{} user data stream: {}", market, e))
+}
+
+async fn start_listen_key(api: &MyApi, market: &str) -> anyhow::Result<String> {
+    let stream = match market {
+        "spot" => api.start_user_data_stream().await?,
+        "futures" => api.futures_start_user_data_stream().await?,
+        "delivery" => api.delivery_start_user_data_stream().await?,
+        _ => return Err(anyhow!("unknown market: {}", market)),
+    };
+    Ok(stream.listen_key)
+}
+
+async fn keep_alive_listen_key(api: &MyApi, market: &str, listen_key: &str) -> anyhow::Result<()> {
+    match market {
+        "spot" => api.keep_alive_user_data_stream(listen_key).await?,
+        "futures" => api.futures_keep_alive_user_data_stream(listen_key).await?,
+        _ => api.delivery_keep_alive_user_data_stream(listen_key).await?,
+    };
+    Ok(())
+}
+
+async fn close_listen_key(api: &MyApi, market: &str, listen_key: &str) -> anyhow::Result<()> {
+    match market {
+        "spot" => api.close_user_data_stream(listen_key).await?,
+        "futures" => api.futures_close_user_data_stream(listen_key).await?,
+        _ => api.delivery_close_user_data_stream(listen_key).await?,
+    };
+    Ok(())
+}
+
+async fn handle_user_events(
+    market: &'static str,
+    user_id: Option<i64>,
+    mut event_rx: UnboundedReceiver<WebsocketEvent>,
+) {
+    while let Some(event) = event_rx.recv().await {
+        if let Err(e) = handle_user_event(market, user_id, event).await {
+            error!("{} user event err: {:?}", market, e);
+        }
+    }
+}
+
+/// 订单事件中的 (订单ID, 客户端订单ID, 订单状态, 累计成交数量)
+///
+/// 现货撤单事件的 c 是撤单请求的ID, 下单时的客户端订单ID在 C 中
+pub fn order_event(event: &WebsocketEvent) -> Option<(String, &str, &str, &str)> {
+    match event {
+        WebsocketEvent::OrderUpdate(o) => Some((
+            o.order_id.to_string(),
+            if o.orig_client_order_id.is_empty() {
+                o.client_order_id.as_str()
+            } else {
+                o.orig_client_order_id.as_str()
+            },
+            o.order_status.as_str(),
+            o.cumulative_filled_qty.as_str(),
+        )),
+        WebsocketEvent::OrderTradeUpdate(e) => Some((
+            e.order.order_id.to_string(),
+            e.order.client_order_id.as_str(),
+            e.order.order_status.as_str(),
+            e.order.order_filled_accumulated_qty.as_str(),
+        )),
+        _ => None,
+    }
+}
+
+/// 余额和持仓的 redis key, 多账户时按 user_id 区分
+pub fn account_key(user_id: Option<i64>, market: &str, key: &str) -> String {
+    match user_id {
+        Some(user_id) => format!("{}_{}{}", user_id, market, key),
+        None => format!("{}{}", market, key),
+    }
+}
+
+async fn handle_user_event(
+    market: &str,
+    user_id: Option<i64>,
+    event: WebsocketEvent,
+) -> anyhow::Result<()> {
+    if let Some((order_id, client_order_id, status, executed_qty)) = order_event(&event) {
+        return order_update(
+            market,
+            user_id,
+            order_id,
+            client_order_id,
+            status,
+            executed_qty,
+        )
+        .await;
+    }
+
+    let mut redis = db::get_db()?.redis().await?;
+    match event {
+        WebsocketEvent::AccountPositionUpdate(update) => {
+            let mut items = vec![];
+            for balance in update.balances {
+                items.push((balance.asset.clone(), serde_json::to_string(&balance)?));
+            }
+            if !items.is_empty() {
+                let key = account_key(user_id, market, redis_key::BALANCE_KEY);
+                let _: () = redis.hset_multiple(key, &items).await?;
+            }
+        }
+        WebsocketEvent::AccountUpdate(update) => {
+            let mut balances = vec![];
+            for balance in update.data.balances {
+                balances.push((balance.asset.clone(), serde_json::to_string(&balance)?));
+            }
+            if !balances.is_empty() {
+                let key = account_key(user_id, market, redis_key::BALANCE_KEY);
+                let _: () = redis.hset_multiple(key, &balances).await?;
+            }
+
+            let mut positions = vec![];
+            for position in update.data.positions {
+                let field = format!("{}_{}", position.symbol, position.position_side);
+                positions.push((field, serde_json::to_string(&position)?));
+            }
+            if !positions.is_empty() {
+                let key = account_key(user_id, market, redis_key::POSITION_KEY);
+                let _: () = redis.hset_multiple(key, &positions).await?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+async fn order_update(
+    market: &str,
+    user_id: Option<i64>,
+    order_id: String,
+    client_order_id: &str,
+    status: &str,
+    executed_qty: &str,
+) -> anyhow::Result<()> {
+    // 只处理终态, 部分成交等待后续事件
+    let filled = match status {
+        "FILLED" => true,
+        "EXPIRED" | "EXPIRED_IN_MATCH" | "CANCELED" | "REJECTED" => false,
+        _ => return Ok(()),
+    };
+    // 客户端订单ID下单前已落库, 下单响应还未写回订单ID时也能匹配; 没有时按订单ID匹配
+    let ex_info = match sql::find_arb_strategy_ex_info_by_client_order_id(market, client_order_id)
+        .await?
+    {
+        Some(ex_info) => ex_info,
+        None => match sql::find_arb_strategy_ex_info_by_order_id(market, order_id.clone()).await? {
+            Some(ex_info) => ex_info,
+            // 非套利策略的订单(如稳定币策略)不处理
+            None => return Ok(()),
+        },
+    };
+    // 其他账户的同号订单
+    if user_id.is_some_and(|user_id| user_id != ex_info.user_id) {
+        return Ok(());
+    }
+    // 轮询已经处理过
+    if ex_info.is_ok != model::arb_strategy_ex_info::IS_OK_UN_DONE {
+        return Ok(());
+    }
+
+    // 下单响应写回之前就收到了终态, 补写订单ID
+    if ex_info.order_id.is_empty() {
+        let mut ex_info_data = HashMap::new();
+        ex_info_data.insert("order_id".to_string(), order_id.clone());
+        let _ = sql::update_strategy_ex_info_by_id(ex_info.id, ex_info_data).await?;
+    }
+    if filled {
+        order_filled(
+            ex_info.arb_strategy_ex_id,
+            ex_info.id,
+            executed_qty.to_string(),
+        )
+        .await?;
+    } else {
+        order_closed(
+            ex_info.arb_strategy_ex_id,
+            ex_info.id,
+            executed_qty.to_string(),
+        )
+        .await?;
+    }
+    info!(
+        "strategy_id: {}, {} order {} {} by user data stream",
+        ex_info.arb_strategy_id, market, order_id, status
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::binance::api::{FuturesOrderRequest, OrderRequest};
+    use crate::binance::rest_model::{OrderSide, OrderType, TimeInForce};
+    use crate::binance::simulator::{Market, MockExchange, OrderScript};
+
+    #[test]
+    fn test_parse_user_events() {
+        let report = r#"{"e":"executionReport","E":1499405658658,"s":"ETHBTC","c":"mUvoqJxFIILMdfAW5iGSOW","S":"BUY","o":"LIMIT","f":"GTC","q":"1.00000000","p":"0.10264410","P":"0.00000000","F":"0.00000000","g":-1,"C":"","x":"TRADE","X":"FILLED","r":"NONE","i":4293153,"l":"1.00000000","z":"1.00000000","L":"0.10264410","n":"0.00010264","N":"BNB","T":1499405658657,"t":1234,"I":8641984,"w":false,"m":false,"M":false,"O":1499405658657,"Z":"0.10264410","Y":"0.10264410","Q":"0.00000000","W":1499405658657,"V":"NONE"}"#;
+        let event: WebsocketEvent = serde_json::from_str(report).unwrap();
+        assert_eq!(
+            order_event(&event),
+            Some((
+                "4293153".to_string(),
+                "mUvoqJxFIILMdfAW5iGSOW",
+                "FILLED",
+                "1.00000000"
+            ))
+        );
+
+        // 撤单事件按原始客户端订单ID匹配
+        let canceled = report
+            .replace(r#""c":"mUvoqJxFIILMdfAW5iGSOW""#, r#""c":"cancel_1""#)
+            .replace(r#""C":"""#, r#""C":"arb_7_2""#)
+            .replace(r#""X":"FILLED""#, r#""X":"CANCELED""#);
+        let event: WebsocketEvent = serde_json::from_str(&canceled).unwrap();
+        assert_eq!(
+            order_event(&event),
+            Some(("4293153".to_string(), "arb_7_2", "CANCELED", "1.00000000"))
+        );
+
+        let trade = r#"{"e":"ORDER_TRADE_UPDATE","E":1568879465651,"T":1568879465650,"i":"SfsR","o":{"s":"BTCUSD_200925","c":"TEST","S":"SELL","o":"LIMIT","f":"GTC","q":"2","p":"0","ap":"0","sp":"9103.1","x":"EXPIRED","X":"EXPIRED","i":8886774,"l":"0","z":"0","L":"0","ma":"BTC","N":"BTC","n":"0","T":1568879465651,"t":0,"rp":"0","b":"0","a":"9.91","m":false,"R":false,"wt":"CONTRACT_PRICE","ot":"TRAILING_STOP_MARKET","ps":"LONG","cp":false,"AP":"9476.8","cr":"5.0","pP":false}}"#;
+        let event: WebsocketEvent = serde_json::from_str(trade).unwrap();
+        assert_eq!(
+            order_event(&event),
+            Some(("8886774".to_string(), "TEST", "EXPIRED", "0"))
+        );
+
+        let account = r#"{"e":"ACCOUNT_UPDATE","E":1564745798939,"T":1564745798938,"a":{"m":"ORDER","B":[{"a":"USDT","wb":"122624.12345678","cw":"100.12345678","bc":"50.12345678"}],"P":[{"s":"BTCUSDT","pa":"0","ep":"0.00000","bep":"0","cr":"200","up":"0","mt":"isolated","iw":"0.00000000","ps":"BOTH"}]}}"#;
+        match serde_json::from_str(account).unwrap() {
+            WebsocketEvent::AccountUpdate(update) => {
+                assert_eq!(update.data.balances[0].wallet_balance, "122624.12345678");
+                assert_eq!(update.data.positions[0].symbol, "BTCUSDT");
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+
+        let position = r#"{"e":"outboundAccountPosition","E":1564034571105,"u":1564034571073,"B":[{"a":"ETH","f":"10000.000000","l":"0.000000"}]}"#;
+        assert!(matches!(
+            serde_json::from_str(position).unwrap(),
+            WebsocketEvent::AccountPositionUpdate(_)
+        ));
+
+        let expired = r#"{"e":"listenKeyExpired","E":"1699596037418","listenKey":"OfYGbUzi3PraNagEkdKuFwUHn48brFsItTdsuiIXrucEvD0rhRXZ7I6URWfE8YE8"}"#;
+        assert!(matches!(
+            serde_json::from_str(expired).unwrap(),
+            WebsocketEvent::ListenKeyExpired(_)
+        ));
+
+        let lite = r#"{"e":"TRADE_LITE","E":1721895408092,"T":1721895408214,"s":"BTCUSDT"}"#;
+        assert!(matches!(
+            serde_json::from_str(lite).unwrap(),
+            WebsocketEvent::Unknown
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_user_data_stream_order_events() {
+        let mock = MockExchange::start().await.unwrap();
+        let api = mock.api();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let (stream_api, config) = (api.clone(), mock.config());
+        let spot =
+            tokio::spawn(
+                async move { run_user_data_stream(&stream_api, "spot", config, &tx).await },
+            );
+        // wait for the subscription before trading
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        mock.set_price(Market::Spot, "BTCUSDT", 67000.0);
+        let filled = api
+            .place_order(OrderRequest {
+                symbol: "BTCUSDT".to_string(),
+                side: OrderSide::Buy,
+                order_type: OrderType::Limit,
+                quantity: Some(0.1),
+                price: Some(67010.0),
+                time_in_force: Some(TimeInForce::FOK),
+                new_client_order_id: Some("arb_1_1".to_string()),
+                ..OrderRequest::default()
+            })
+            .await
+            .unwrap();
+        let event = rx.recv().await.unwrap();
+        assert_eq!(
+            order_event(&event),
+            Some((filled.order_id.to_string(), "arb_1_1", "FILLED", "0.1"))
+        );
+
+        mock.script_order(Market::Spot, OrderScript::NoFill);
+        let expired = api
+            .place_order(OrderRequest {
+                symbol: "BTCUSDT".to_string(),
+                side: OrderSide::Sell,
+                order_type: OrderType::Limit,
+                quantity: Some(0.1),
+                price: Some(68000.0),
+                time_in_force: Some(TimeInForce::FOK),
+                new_client_order_id: Some("arb_2_1".to_string()),
+                ..OrderRequest::default()
+            })
+            .await
+            .unwrap();
+        let event = rx.recv().await.unwrap();
+        assert_eq!(
+            order_event(&event),
+            Some((expired.order_id.to_string(), "arb_2_1", "EXPIRED", "0"))
+        );
+
+        // listenKeyExpired 结束订阅
+        mock.expire_listen_keys();
+        let result = spot.await.unwrap();
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_futures_user_data_stream() {
+        let mock = MockExchange::start().await.unwrap();
+        let api = mock.api();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let (stream_api, config) = (api.clone(), mock.config());
+        let futures =
+            tokio::spawn(
+                async move { run_user_data_stream(&stream_api, "futures", config, &tx).await },
+            );
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        mock.set_price(Market::Futures, "BTCUSDT", 67000.0);
+        let order = api
+            .futures_place_order(FuturesOrderRequest {
+                symbol: "BTCUSDT".to_string(),
+                side: OrderSide::Sell,
+                order_type: OrderType::Limit,
+                quantity: Some(0.01),
+                price: Some(66990.0),
+                time_in_force: Some(TimeInForce::FOK),
+                new_client_order_id: Some("arb_3_1".to_string()),
+                recv_window: None,
+            })
+            .await
+            .unwrap();
+        let event = rx.recv().await.unwrap();
+        assert_eq!(
+            order_event(&event),
+            Some((order.order_id.to_string(), "arb_3_1", "FILLED", "0.01"))
+        );
+        futures.abort();
+    }
+}
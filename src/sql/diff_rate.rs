@@ -27,6 +27,22 @@ pub async fn insert_arb_diff_rate_his(his: model::ArbDiffRateHis) -> anyhow::Res
     Ok(last_insert_id)
 }
 
+pub async fn get_arb_diff_rate_his_list_by_diff_rate_id(
+    diff_rate_id: i64,
+    start: i64,
+    end: i64,
+) -> anyhow::Result<Vec<model::ArbDiffRateHis>> {
+    let his_list = sqlx::query_as::<_, model::ArbDiffRateHis>(
+        "select * from arb_diff_rate_his where diff_rate_id = ? and created >= ? and created <= ? order by created, id",
+    )
+    .bind(diff_rate_id)
+    .bind(start)
+    .bind(end)
+    .fetch_all(db::get_db()?.database())
+    .await?;
+    Ok(his_list)
+}
+
 pub async fn get_arb_diff_rate_info_by_diff_rate_id(
     diff_rate_id: i64,
 ) -> anyhow::Result<model::ArbDiffRateInfo> {
@@ -57,12 +73,16 @@ pub async fn update_arb_diff_rate_info_by_id(
     to_price: Decimal,
     diff_price: Decimal,
     diff_rate: Decimal,
+    open_diff_rate: Decimal,
+    close_diff_rate: Decimal,
 ) -> anyhow::Result<u64> {
-    let rows = sqlx::query("update arb_diff_rate_info set from_price = ?, to_price = ?, diff_price = ?, diff_rate = ?, updated = ? where id = ?")
+    let rows = sqlx::query("update arb_diff_rate_info set from_price = ?, to_price = ?, diff_price = ?, diff_rate = ?, open_diff_rate = ?, close_diff_rate = ?, updated = ? where id = ?")
         .bind(from_price)
         .bind(to_price)
         .bind(diff_price)
         .bind(diff_rate)
+        .bind(open_diff_rate)
+        .bind(close_diff_rate)
         .bind(Local::now().timestamp())
         .bind(id)
         .execute(db::get_db()?.database())
@@ -71,6 +91,27 @@ pub async fn update_arb_diff_rate_info_by_id(
     Ok(rows)
 }
 
+/// 差价记录换到新的 To 交易对, 如展期后的季度合约
+pub async fn update_arb_diff_rate_to_symbol(
+    diff_rate_id: i64,
+    to_symbol: &str,
+) -> anyhow::Result<u64> {
+    let rows = sqlx::query("update arb_diff_rate set to_symbol = ?, updated = ? where id = ?")
+        .bind(to_symbol)
+        .bind(Local::now().timestamp())
+        .bind(diff_rate_id)
+        .execute(db::get_db()?.database())
+        .await?
+        .rows_affected();
+    sqlx::query("update arb_diff_rate_info set to_symbol = ?, updated = ? where diff_rate_id = ?")
+        .bind(to_symbol)
+        .bind(Local::now().timestamp())
+        .bind(diff_rate_id)
+        .execute(db::get_db()?.database())
+        .await?;
+    Ok(rows)
+}
+
 pub async fn insert_arb_diff_rate_info(info: model::ArbDiffRateInfo) -> anyhow::Result<u64> {
     let last_insert_id = sqlx::query(
         "insert into arb_diff_rate_info (
@@ -86,9 +127,11 @@ pub async fn insert_arb_diff_rate_info(info: model::ArbDiffRateInfo) -> anyhow::
         to_price,
         diff_price,
         diff_rate,
+        open_diff_rate,
+        close_diff_rate,
         created,
         updated
-        ) values (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        ) values (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
     )
     .bind(info.diff_rate_id)
     .bind(info.platform)
@@ -102,6 +145,8 @@ pub async fn insert_arb_diff_rate_info(info: model::ArbDiffRateInfo) -> anyhow::
     .bind(info.to_price)
     .bind(info.diff_price)
     .bind(info.diff_rate)
+    .bind(info.open_diff_rate)
+    .bind(info.close_diff_rate)
     .bind(info.created)
     .bind(info.updated)
     .execute(db::get_db()?.database())
@@ -109,3 +154,87 @@ pub async fn insert_arb_diff_rate_info(info: model::ArbDiffRateInfo) -> anyhow::
     .last_insert_id();
     Ok(last_insert_id)
 }
+
+pub async fn get_arb_diff_rate_list() -> anyhow::Result<Vec<model::ArbDiffRate>> {
+    let diff_rate_list =
+        sqlx::query_as::<_, model::ArbDiffRate>("select * from arb_diff_rate order by id")
+            .fetch_all(db::get_db()?.database())
+            .await?;
+    Ok(diff_rate_list)
+}
+
+pub async fn get_arb_diff_rate_by_id(id: i64) -> anyhow::Result<model::ArbDiffRate> {
+    let diff_rate =
+        sqlx::query_as::<_, model::ArbDiffRate>("select * from arb_diff_rate where id = ?")
+            .bind(id)
+            .fetch_one(db::get_db()?.database())
+            .await?;
+    Ok(diff_rate)
+}
+
+pub async fn insert_arb_diff_rate(diff_rate: model::ArbDiffRate) -> anyhow::Result<u64> {
+    let last_insert_id = sqlx::query("insert into arb_diff_rate (platform, coin, option_choose, from_market, from_symbol, to_market, to_symbol, investment_currency, return_currency, diff_status, created, updated, bak) values (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+        .bind(diff_rate.platform)
+        .bind(diff_rate.coin)
+        .bind(diff_rate.option_choose)
+        .bind(diff_rate.from_market)
+        .bind(diff_rate.from_symbol)
+        .bind(diff_rate.to_market)
+        .bind(diff_rate.to_symbol)
+        .bind(diff_rate.investment_currency)
+        .bind(diff_rate.return_currency)
+        .bind(diff_rate.diff_status)
+        .bind(Local::now().timestamp())
+        .bind(Local::now().timestamp())
+        .bind(diff_rate.bak)
+        .execute(db::get_db()?.database())
+        .await?
+        .last_insert_id();
+    Ok(last_insert_id)
+}
+
+/// 更新配置字段, 状态通过 update_arb_diff_rate_status 修改
+pub async fn update_arb_diff_rate(diff_rate: model::ArbDiffRate) -> anyhow::Result<u64> {
+    let rows = sqlx::query("update arb_diff_rate set platform = ?, coin = ?, option_choose = ?, from_market = ?, from_symbol = ?, to_market = ?, to_symbol = ?, investment_currency = ?, return_currency = ?, bak = ?, updated = ? where id = ?")
+        .bind(diff_rate.platform)
+        .bind(diff_rate.coin)
+        .bind(diff_rate.option_choose)
+        .bind(diff_rate.from_market)
+        .bind(diff_rate.from_symbol)
+        .bind(diff_rate.to_market)
+        .bind(diff_rate.to_symbol)
+        .bind(diff_rate.investment_currency)
+        .bind(diff_rate.return_currency)
+        .bind(diff_rate.bak)
+        .bind(Local::now().timestamp())
+        .bind(diff_rate.id)
+        .execute(db::get_db()?.database())
+        .await?
+        .rows_affected();
+    Ok(rows)
+}
+
+pub async fn update_arb_diff_rate_status(id: i64, diff_status: i8) -> anyhow::Result<u64> {
+    let rows = sqlx::query("update arb_diff_rate set diff_status = ?, updated = ? where id = ?")
+        .bind(diff_status)
+        .bind(Local::now().timestamp())
+        .bind(id)
+        .execute(db::get_db()?.database())
+        .await?
+        .rows_affected();
+    Ok(rows)
+}
+
+/// 删除差价记录及其最新差价
+pub async fn delete_arb_diff_rate(id: i64) -> anyhow::Result<u64> {
+    let rows = sqlx::query("delete from arb_diff_rate where id = ?")
+        .bind(id)
+        .execute(db::get_db()?.database())
+        .await?
+        .rows_affected();
+    sqlx::query("delete from arb_diff_rate_info where diff_rate_id = ?")
+        .bind(id)
+        .execute(db::get_db()?.database())
+        .await?;
+    Ok(rows)
+}
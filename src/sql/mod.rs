@@ -2,19 +2,48 @@ pub mod diff_rate;
 mod stable_coin;
 pub mod strategy;
 
+pub use diff_rate::delete_arb_diff_rate;
+pub use diff_rate::get_arb_diff_rate_by_id;
+pub use diff_rate::get_arb_diff_rate_his_list_by_diff_rate_id;
 pub use diff_rate::get_arb_diff_rate_info_by_diff_rate_id;
+pub use diff_rate::get_arb_diff_rate_list;
 pub use diff_rate::get_arb_diff_rate_list_by_diff_status;
+pub use diff_rate::insert_arb_diff_rate;
 pub use diff_rate::insert_arb_diff_rate_his;
 pub use diff_rate::insert_arb_diff_rate_info;
+pub use diff_rate::update_arb_diff_rate;
 pub use diff_rate::update_arb_diff_rate_info_by_id;
+pub use diff_rate::update_arb_diff_rate_status;
+pub use diff_rate::update_arb_diff_rate_to_symbol;
+pub use stable_coin::delete_arb_stable_coin;
+pub use stable_coin::get_arb_stable_coin_by_id;
 pub use stable_coin::get_arb_stable_coin_info_list_by_stable_coin_id;
+pub use stable_coin::get_arb_stable_coin_list;
 pub use stable_coin::get_arb_stable_coin_list_by_doing_status;
+pub use stable_coin::insert_arb_stable_coin;
 pub use stable_coin::insert_arb_stable_coin_info;
+pub use stable_coin::update_arb_stable_coin;
+pub use stable_coin::update_arb_stable_coin_status;
+pub use strategy::add_strategy_funding_income;
+pub use strategy::count_arb_strategy_ex_info;
+pub use strategy::delete_arb_strategy;
+pub use strategy::find_arb_strategy_ex_info_by_client_order_id;
+pub use strategy::find_arb_strategy_ex_info_by_order_id;
+pub use strategy::find_un_done_arb_strategy_ex_info;
+pub use strategy::get_arb_strategy_by_id;
 pub use strategy::get_arb_strategy_ex_info_by_order_id;
+pub use strategy::get_arb_strategy_ex_info_list_by_ex_id;
 pub use strategy::get_arb_strategy_ex_list_by_strategy_id;
+pub use strategy::get_arb_strategy_list;
+pub use strategy::get_arb_strategy_list_by_diff_rate_id;
 pub use strategy::get_arb_strategy_list_by_doing_status;
+pub use strategy::insert_arb_strategy;
 pub use strategy::insert_arb_strategy_ex;
 pub use strategy::insert_arb_strategy_ex_info;
+pub use strategy::update_arb_strategy;
 pub use strategy::update_strategy_by_id;
 pub use strategy::update_strategy_ex_by_id;
 pub use strategy::update_strategy_ex_info_by_id;
+pub use strategy::update_strategy_ex_symbol;
+pub use strategy::update_strategy_manual;
+pub use strategy::update_strategy_to_symbol;
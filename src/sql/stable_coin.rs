@@ -1,4 +1,5 @@
 use crate::{db, model};
+use chrono::Local;
 
 pub async fn get_arb_stable_coin_list_by_doing_status(
     doing_status: i8,
@@ -12,6 +13,15 @@ pub async fn get_arb_stable_coin_list_by_doing_status(
     Ok(stable_coin_list)
 }
 
+pub async fn get_arb_stable_coin_by_id(id: i64) -> anyhow::Result<model::ArbStableCoin> {
+    let stable_coin =
+        sqlx::query_as::<_, model::ArbStableCoin>("select * from arb_stable_coin where id = ?")
+            .bind(id)
+            .fetch_one(db::get_db()?.database())
+            .await?;
+    Ok(stable_coin)
+}
+
 pub async fn get_arb_stable_coin_info_list_by_stable_coin_id(
     stable_coin_id: i64,
     limit: u32,
@@ -46,3 +56,81 @@ pub async fn insert_arb_stable_coin_info(info: model::ArbStableCoinInfo) -> anyh
         .last_insert_id();
     Ok(last_insert_id)
 }
+
+pub async fn get_arb_stable_coin_list() -> anyhow::Result<Vec<model::ArbStableCoin>> {
+    let stable_coin_list =
+        sqlx::query_as::<_, model::ArbStableCoin>("select * from arb_stable_coin order by id")
+            .fetch_all(db::get_db()?.database())
+            .await?;
+    Ok(stable_coin_list)
+}
+
+pub async fn insert_arb_stable_coin(stable_coin: model::ArbStableCoin) -> anyhow::Result<u64> {
+    let last_insert_id = sqlx::query(
+        "insert into arb_stable_coin (user_id, platform, coin, market, symbol, price_truncate, amt_truncate, strategy, option_open, option_close, option_amt, fok_diff, doing_status, created, updated, bak) values (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+        .bind(stable_coin.user_id)
+        .bind(stable_coin.platform)
+        .bind(stable_coin.coin)
+        .bind(stable_coin.market)
+        .bind(stable_coin.symbol)
+        .bind(stable_coin.price_truncate)
+        .bind(stable_coin.amt_truncate)
+        .bind(stable_coin.strategy)
+        .bind(stable_coin.option_open)
+        .bind(stable_coin.option_close)
+        .bind(stable_coin.option_amt)
+        .bind(stable_coin.fok_diff)
+        .bind(stable_coin.doing_status)
+        .bind(Local::now().timestamp())
+        .bind(Local::now().timestamp())
+        .bind(stable_coin.bak)
+        .execute(db::get_db()?.database())
+        .await?
+        .last_insert_id();
+    Ok(last_insert_id)
+}
+
+/// 更新配置字段, 状态通过 update_arb_stable_coin_status 修改
+pub async fn update_arb_stable_coin(stable_coin: model::ArbStableCoin) -> anyhow::Result<u64> {
+    let rows = sqlx::query(
+        "update arb_stable_coin set user_id = ?, platform = ?, coin = ?, market = ?, symbol = ?, price_truncate = ?, amt_truncate = ?, strategy = ?, option_open = ?, option_close = ?, option_amt = ?, fok_diff = ?, bak = ?, updated = ? where id = ?")
+        .bind(stable_coin.user_id)
+        .bind(stable_coin.platform)
+        .bind(stable_coin.coin)
+        .bind(stable_coin.market)
+        .bind(stable_coin.symbol)
+        .bind(stable_coin.price_truncate)
+        .bind(stable_coin.amt_truncate)
+        .bind(stable_coin.strategy)
+        .bind(stable_coin.option_open)
+        .bind(stable_coin.option_close)
+        .bind(stable_coin.option_amt)
+        .bind(stable_coin.fok_diff)
+        .bind(stable_coin.bak)
+        .bind(Local::now().timestamp())
+        .bind(stable_coin.id)
+        .execute(db::get_db()?.database())
+        .await?
+        .rows_affected();
+    Ok(rows)
+}
+
+pub async fn update_arb_stable_coin_status(id: i64, doing_status: i8) -> anyhow::Result<u64> {
+    let rows = sqlx::query("update arb_stable_coin set doing_status = ?, updated = ? where id = ?")
+        .bind(doing_status)
+        .bind(Local::now().timestamp())
+        .bind(id)
+        .execute(db::get_db()?.database())
+        .await?
+        .rows_affected();
+    Ok(rows)
+}
+
+pub async fn delete_arb_stable_coin(id: i64) -> anyhow::Result<u64> {
+    let rows = sqlx::query("delete from arb_stable_coin where id = ?")
+        .bind(id)
+        .execute(db::get_db()?.database())
+        .await?
+        .rows_affected();
+    Ok(rows)
+}
@@ -1,5 +1,6 @@
 use crate::{db, model};
 use chrono::Local;
+use rust_decimal::Decimal;
 use std::collections::HashMap;
 
 pub async fn update_strategy_by_id(id: i64, doing_status: i8) -> anyhow::Result<u64> {
@@ -13,20 +14,86 @@ pub async fn update_strategy_by_id(id: i64, doing_status: i8) -> anyhow::Result<
     Ok(rows)
 }
 
+/// 标记为需人工处理, 原因写入 bak
+pub async fn update_strategy_manual(id: i64, reason: &str) -> anyhow::Result<u64> {
+    let reason: String = reason.chars().take(255).collect();
+    let rows =
+        sqlx::query("update arb_strategy set doing_status = ?, bak = ?, updated = ? where id = ?")
+            .bind(model::arb_strategy::DOING_STATUS_MANUAL)
+            .bind(reason)
+            .bind(Local::now().timestamp())
+            .bind(id)
+            .execute(db::get_db()?.database())
+            .await?
+            .rows_affected();
+    Ok(rows)
+}
+
+pub async fn add_strategy_funding_income(
+    id: i64,
+    income: Decimal,
+    funding_time: i64,
+) -> anyhow::Result<u64> {
+    let rows = sqlx::query("update arb_strategy set funding_income = funding_income + ?, funding_time = ?, updated = ? where id = ?")
+        .bind(income)
+        .bind(funding_time)
+        .bind(Local::now().timestamp())
+        .bind(id)
+        .execute(db::get_db()?.database())
+        .await?
+        .rows_affected();
+    Ok(rows)
+}
+
+pub async fn update_strategy_to_symbol(id: i64, to_symbol: &str) -> anyhow::Result<u64> {
+    let rows = sqlx::query("update arb_strategy set to_symbol = ?, updated = ? where id = ?")
+        .bind(to_symbol)
+        .bind(Local::now().timestamp())
+        .bind(id)
+        .execute(db::get_db()?.database())
+        .await?
+        .rows_affected();
+    Ok(rows)
+}
+
+/// 未完成的执行记录从 symbol 换到 new_symbol
+pub async fn update_strategy_ex_symbol(
+    strategy_id: i64,
+    symbol: &str,
+    new_symbol: &str,
+) -> anyhow::Result<u64> {
+    let rows = sqlx::query("update arb_strategy_ex set symbol = ?, updated = ? where arb_strategy_id = ? and symbol = ? and option_status = ?")
+        .bind(new_symbol)
+        .bind(Local::now().timestamp())
+        .bind(strategy_id)
+        .bind(symbol)
+        .bind(model::arb_strategy_ex::OPTION_STATUS_UN_DONE)
+        .execute(db::get_db()?.database())
+        .await?
+        .rows_affected();
+    Ok(rows)
+}
+
 pub async fn update_strategy_ex_by_id(
     id: i64,
     map: HashMap<String, String>,
 ) -> anyhow::Result<u64> {
-    let sql_pre: String = map
+    // 值按参数绑定, 空字符串等非数字值不需要拼引号
+    let (keys, values): (Vec<&String>, Vec<&String>) = map.iter().unzip();
+    let sql_pre: String = keys
         .iter()
-        .map(|(key, value)| format!("{}={}", key, value))
+        .map(|key| format!("{}=?", key))
         .collect::<Vec<String>>()
         .join(", ");
     let sql = format!(
         "update arb_strategy_ex set {}, updated = ? where id = ?",
         sql_pre
     );
-    let rows = sqlx::query(&sql)
+    let mut query = sqlx::query(&sql);
+    for value in values {
+        query = query.bind(value);
+    }
+    let rows = query
         .bind(Local::now().timestamp())
         .bind(id)
         .execute(db::get_db()?.database())
@@ -39,16 +106,22 @@ pub async fn update_strategy_ex_info_by_id(
     id: i64,
     map: HashMap<String, String>,
 ) -> anyhow::Result<u64> {
-    let sql_pre: String = map
+    // 值按参数绑定, 空字符串等非数字值不需要拼引号
+    let (keys, values): (Vec<&String>, Vec<&String>) = map.iter().unzip();
+    let sql_pre: String = keys
         .iter()
-        .map(|(key, value)| format!("{}={}", key, value))
+        .map(|key| format!("{}=?", key))
         .collect::<Vec<String>>()
         .join(", ");
     let sql = format!(
         "update arb_strategy_ex_info set {}, updated = ? where id = ?",
         sql_pre
     );
-    let rows = sqlx::query(&sql)
+    let mut query = sqlx::query(&sql);
+    for value in values {
+        query = query.bind(value);
+    }
+    let rows = query
         .bind(Local::now().timestamp())
         .bind(id)
         .execute(db::get_db()?.database())
@@ -69,11 +142,151 @@ pub async fn get_arb_strategy_list_by_doing_status(
     Ok(strategy_list)
 }
 
+pub async fn get_arb_strategy_list() -> anyhow::Result<Vec<model::ArbStrategy>> {
+    let strategy_list = sqlx::query_as::<_, model::ArbStrategy>("select * from arb_strategy")
+        .fetch_all(db::get_db()?.database())
+        .await?;
+    Ok(strategy_list)
+}
+
+pub async fn get_arb_strategy_by_id(id: i64) -> anyhow::Result<model::ArbStrategy> {
+    let strategy =
+        sqlx::query_as::<_, model::ArbStrategy>("select * from arb_strategy where id = ?")
+            .bind(id)
+            .fetch_one(db::get_db()?.database())
+            .await?;
+    Ok(strategy)
+}
+
+pub async fn get_arb_strategy_list_by_diff_rate_id(
+    diff_rate_id: i64,
+) -> anyhow::Result<Vec<model::ArbStrategy>> {
+    let strategy_list = sqlx::query_as::<_, model::ArbStrategy>(
+        "select * from arb_strategy where diff_rate_id = ? order by id",
+    )
+    .bind(diff_rate_id)
+    .fetch_all(db::get_db()?.database())
+    .await?;
+    Ok(strategy_list)
+}
+
+pub async fn insert_arb_strategy(strategy: model::ArbStrategy) -> anyhow::Result<u64> {
+    let last_insert_id = sqlx::query(
+        "insert into arb_strategy (
+        diff_rate_id,
+        user_id,
+        platform,
+        option_choose,
+        coin,
+        from_market,
+        from_symbol,
+        from_price_truncate,
+        from_amt_truncate,
+        to_market,
+        to_symbol,
+        to_price_truncate,
+        to_amt_truncate,
+        from_to_desc,
+        to_from_desc,
+        option_open,
+        option_close,
+        option_amt,
+        contract_mul,
+        margin_mul,
+        fok_diff,
+        spot_fee,
+        futures_fee,
+        delivery_fee,
+        doing_status,
+        created,
+        updated,
+        bak
+        ) values (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(strategy.diff_rate_id)
+    .bind(strategy.user_id)
+    .bind(strategy.platform)
+    .bind(strategy.option_choose)
+    .bind(strategy.coin)
+    .bind(strategy.from_market)
+    .bind(strategy.from_symbol)
+    .bind(strategy.from_price_truncate)
+    .bind(strategy.from_amt_truncate)
+    .bind(strategy.to_market)
+    .bind(strategy.to_symbol)
+    .bind(strategy.to_price_truncate)
+    .bind(strategy.to_amt_truncate)
+    .bind(strategy.from_to_desc)
+    .bind(strategy.to_from_desc)
+    .bind(strategy.option_open)
+    .bind(strategy.option_close)
+    .bind(strategy.option_amt)
+    .bind(strategy.contract_mul)
+    .bind(strategy.margin_mul)
+    .bind(strategy.fok_diff)
+    .bind(strategy.spot_fee)
+    .bind(strategy.futures_fee)
+    .bind(strategy.delivery_fee)
+    .bind(strategy.doing_status)
+    .bind(Local::now().timestamp())
+    .bind(Local::now().timestamp())
+    .bind(strategy.bak)
+    .execute(db::get_db()?.database())
+    .await?
+    .last_insert_id();
+    Ok(last_insert_id)
+}
+
+/// 更新配置字段, 状态通过 update_strategy_by_id 修改
+pub async fn update_arb_strategy(strategy: model::ArbStrategy) -> anyhow::Result<u64> {
+    let rows = sqlx::query("update arb_strategy set diff_rate_id = ?, user_id = ?, platform = ?, option_choose = ?, coin = ?, from_market = ?, from_symbol = ?, from_price_truncate = ?, from_amt_truncate = ?, to_market = ?, to_symbol = ?, to_price_truncate = ?, to_amt_truncate = ?, from_to_desc = ?, to_from_desc = ?, option_open = ?, option_close = ?, option_amt = ?, contract_mul = ?, margin_mul = ?, fok_diff = ?, spot_fee = ?, futures_fee = ?, delivery_fee = ?, bak = ?, updated = ? where id = ?")
+        .bind(strategy.diff_rate_id)
+        .bind(strategy.user_id)
+        .bind(strategy.platform)
+        .bind(strategy.option_choose)
+        .bind(strategy.coin)
+        .bind(strategy.from_market)
+        .bind(strategy.from_symbol)
+        .bind(strategy.from_price_truncate)
+        .bind(strategy.from_amt_truncate)
+        .bind(strategy.to_market)
+        .bind(strategy.to_symbol)
+        .bind(strategy.to_price_truncate)
+        .bind(strategy.to_amt_truncate)
+        .bind(strategy.from_to_desc)
+        .bind(strategy.to_from_desc)
+        .bind(strategy.option_open)
+        .bind(strategy.option_close)
+        .bind(strategy.option_amt)
+        .bind(strategy.contract_mul)
+        .bind(strategy.margin_mul)
+        .bind(strategy.fok_diff)
+        .bind(strategy.spot_fee)
+        .bind(strategy.futures_fee)
+        .bind(strategy.delivery_fee)
+        .bind(strategy.bak)
+        .bind(Local::now().timestamp())
+        .bind(strategy.id)
+        .execute(db::get_db()?.database())
+        .await?
+        .rows_affected();
+    Ok(rows)
+}
+
+pub async fn delete_arb_strategy(id: i64) -> anyhow::Result<u64> {
+    let rows = sqlx::query("delete from arb_strategy where id = ?")
+        .bind(id)
+        .execute(db::get_db()?.database())
+        .await?
+        .rows_affected();
+    Ok(rows)
+}
+
 pub async fn get_arb_strategy_ex_list_by_strategy_id(
     strategy_id: i64,
 ) -> anyhow::Result<Vec<model::ArbStrategyEx>> {
     let strategy_ex_list = sqlx::query_as::<_, model::ArbStrategyEx>(
-        "select * from arb_strategy_ex where arb_strategy_id = ?",
+        "select * from arb_strategy_ex where arb_strategy_id = ? order by id",
     )
     .bind(strategy_id)
     .fetch_all(db::get_db()?.database())
@@ -93,6 +306,74 @@ pub async fn get_arb_strategy_ex_info_by_order_id(
     Ok(ex_info)
 }
 
+pub async fn get_arb_strategy_ex_info_list_by_ex_id(
+    ex_id: i64,
+) -> anyhow::Result<Vec<model::ArbStrategyExInfo>> {
+    let ex_info_list = sqlx::query_as::<_, model::ArbStrategyExInfo>(
+        "select * from arb_strategy_ex_info where arb_strategy_ex_id = ? order by id",
+    )
+    .bind(ex_id)
+    .fetch_all(db::get_db()?.database())
+    .await?;
+    Ok(ex_info_list)
+}
+
+/// 执行记录最近一条未完成的下单记录
+pub async fn find_un_done_arb_strategy_ex_info(
+    ex_id: i64,
+) -> anyhow::Result<Option<model::ArbStrategyExInfo>> {
+    let ex_info = sqlx::query_as::<_, model::ArbStrategyExInfo>(
+        "select * from arb_strategy_ex_info where arb_strategy_ex_id = ? and is_ok = ? order by id desc limit 1",
+    )
+    .bind(ex_id)
+    .bind(model::arb_strategy_ex_info::IS_OK_UN_DONE)
+    .fetch_optional(db::get_db()?.database())
+    .await?;
+    Ok(ex_info)
+}
+
+/// 执行记录已有的下单次数
+pub async fn count_arb_strategy_ex_info(ex_id: i64) -> anyhow::Result<i64> {
+    let count: i64 = sqlx::query_scalar(
+        "select count(*) from arb_strategy_ex_info where arb_strategy_ex_id = ?",
+    )
+    .bind(ex_id)
+    .fetch_one(db::get_db()?.database())
+    .await?;
+    Ok(count)
+}
+
+pub async fn find_arb_strategy_ex_info_by_order_id(
+    market: &str,
+    order_id: String,
+) -> anyhow::Result<Option<model::ArbStrategyExInfo>> {
+    let ex_info = sqlx::query_as::<_, model::ArbStrategyExInfo>(
+        "select * from arb_strategy_ex_info where market = ? and order_id = ?",
+    )
+    .bind(market)
+    .bind(order_id)
+    .fetch_optional(db::get_db()?.database())
+    .await?;
+    Ok(ex_info)
+}
+
+pub async fn find_arb_strategy_ex_info_by_client_order_id(
+    market: &str,
+    client_order_id: &str,
+) -> anyhow::Result<Option<model::ArbStrategyExInfo>> {
+    if client_order_id.is_empty() {
+        return Ok(None);
+    }
+    let ex_info = sqlx::query_as::<_, model::ArbStrategyExInfo>(
+        "select * from arb_strategy_ex_info where market = ? and client_order_id = ?",
+    )
+    .bind(market)
+    .bind(client_order_id)
+    .fetch_optional(db::get_db()?.database())
+    .await?;
+    Ok(ex_info)
+}
+
 pub async fn insert_arb_strategy_ex(ex: model::ArbStrategyEx) -> anyhow::Result<u64> {
     let last_insert_id = sqlx::query("insert into arb_strategy_ex (user_id, platform, option_choose, arb_strategy_id, coin, market, symbol, option_type, option_status, option_amount, option_executed_amt, current_order_id, created, updated) values (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
         .bind(ex.user_id)
@@ -117,7 +398,7 @@ pub async fn insert_arb_strategy_ex(ex: model::ArbStrategyEx) -> anyhow::Result<
 
 pub async fn insert_arb_strategy_ex_info(ex: model::ArbStrategyExInfo) -> anyhow::Result<u64> {
     let last_insert_id = sqlx::query(
-        "insert into arb_strategy_ex_info (user_id, platform, option_choose, arb_strategy_id, arb_strategy_ex_id, coin, market, symbol, option_type, price, amount, executed_amt, order_id, is_ok, created) values (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        "insert into arb_strategy_ex_info (user_id, platform, option_choose, arb_strategy_id, arb_strategy_ex_id, coin, market, symbol, option_type, price, amount, executed_amt, order_id, client_order_id, is_ok, created, simulated) values (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
     )
         .bind(ex.user_id)
         .bind(ex.platform)
@@ -132,8 +413,10 @@ pub async fn insert_arb_strategy_ex_info(ex: model::ArbStrategyExInfo) -> anyhow
         .bind(ex.amount)
         .bind(ex.executed_amt)
         .bind(ex.order_id)
+        .bind(ex.client_order_id)
         .bind(ex.is_ok)
         .bind(ex.created)
+        .bind(ex.simulated)
         .execute(db::get_db()?.database())
         .await?
         .last_insert_id();